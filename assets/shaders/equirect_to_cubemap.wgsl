@@ -0,0 +1,34 @@
+#include assets/shaders/lib/header.wgsl
+#include assets/shaders/lib/space.wgsl
+
+@group(0) @binding(0)
+var panorama_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var panorama_sampler: sampler;
+
+// the faces of the cubemap as layers, in the cubemap's face order
+@group(0) @binding(2)
+var cubemap_faces: texture_storage_2d_array<rgba32float, write>;
+
+// Where a normalized direction points to on the panorama, with its center towards -z and its top
+// towards +y. Must match `equirect_uv` in `sky.rs`.
+fn equirect_uv(dir: vec3<f32>) -> vec2<f32> {
+    return vec2(0.5 + atan2(dir.x, -dir.z) / TAU, acos(clamp(dir.y, -1.0, 1.0)) / PI);
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn compute(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(cubemap_faces);
+
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+
+    let coords = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size) * 2.0 - 1.0;
+    let dir = normalize(cube_face_direction(CubeFaceCoords(id.z, coords)));
+
+    let color = textureSampleLevel(panorama_texture, panorama_sampler, equirect_uv(dir), 0.0);
+
+    textureStore(cubemap_faces, id.xy, id.z, vec4(color.rgb, 1.0));
+}
@@ -0,0 +1,35 @@
+#include assets/shaders/lib/lbvh/build.wgsl
+
+// writes every node to the BVH buffer, in the same layout as the BVHs built on the cpu
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn compute(@builtin(global_invocation_id) id: vec3<u32>) {
+    let count = settings.triangle_count;
+
+    if id.x >= 2u * count - 1u {
+        return;
+    }
+
+    let slot = slots[id.x];
+
+    var node: BvhNode;
+    node.bounds = load_node_bounds(slot);
+
+    if id.x < count - 1u {
+        let range = ranges[id.x];
+
+        node.start_index = settings.triangle_offset + range.x;
+        node.len = range.y - range.x + 1u;
+        node.child_node = settings.node_offset + 2u * id.x + 1u;
+    } else {
+        let leaf = id.x - (count - 1u);
+
+        node.start_index = settings.triangle_offset + leaf;
+        node.len = 1u;
+        node.child_node = 0u;
+
+        // the leaves refer to the triangles in sorted order
+        sorted_triangles[leaf] = triangles.list[settings.triangle_offset + values[leaf]];
+    }
+
+    bvh.nodes[settings.node_offset + slot] = node;
+}
@@ -0,0 +1,26 @@
+#include assets/shaders/lib/lbvh/build.wgsl
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn compute(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= settings.triangle_count {
+        return;
+    }
+
+    var slot = slots[leaf_index(id.x)];
+    store_node_bounds(slot, triangle_bounds(values[id.x]));
+
+    // walk up to the root, leaving the bounds of every node to the last of its children to arrive
+    while slot != 0u {
+        let parent = (slot - 1u) / 2u;
+
+        if atomicAdd(&counters[parent], 1u) == 0u {
+            return;
+        }
+
+        let left = load_node_bounds(2u * parent + 1u);
+        let right = load_node_bounds(2u * parent + 2u);
+
+        slot = slots[parent];
+        store_node_bounds(slot, BoundingVolume(min(left.min, right.min), max(left.max, right.max)));
+    }
+}
@@ -0,0 +1,96 @@
+#include assets/shaders/lib/lbvh/build.wgsl
+
+// The length of the common prefix of two sorted keys, or -1 if `j` is out of range. Equal keys are
+// told apart by their indices.
+fn common_prefix(i: i32, j: i32) -> i32 {
+    if j < 0 || j >= i32(settings.triangle_count) {
+        return -1;
+    }
+
+    let a = keys[i];
+    let b = keys[j];
+
+    if a == b {
+        return 32 + i32(countLeadingZeros(u32(i) ^ u32(j)));
+    }
+
+    return i32(countLeadingZeros(a ^ b));
+}
+
+// Finds the leaves below every internal node and where its children split them, following Karras,
+// "Maximizing Parallelism in the Construction of BVHs, Octrees, and k-d Trees" (2012).
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn compute(@builtin(global_invocation_id) id: vec3<u32>) {
+    let count = settings.triangle_count;
+
+    // a single triangle is a single leaf at the root
+    if count == 1u {
+        if id.x == 0u {
+            slots[0] = 0u;
+        }
+
+        return;
+    }
+
+    if id.x >= count - 1u {
+        return;
+    }
+
+    let i = i32(id.x);
+
+    // the range of the node extends from i towards the neighbor sharing the longer prefix
+    let direction = select(-1, 1, common_prefix(i, i + 1) > common_prefix(i, i - 1));
+    let min_prefix = common_prefix(i, i - direction);
+
+    // find the other end of the range, with an exponential and then a binary search
+    var max_length = 2;
+
+    while common_prefix(i, i + max_length * direction) > min_prefix {
+        max_length *= 2;
+    }
+
+    var length = 0;
+
+    for (var step = max_length / 2; step > 0; step /= 2) {
+        if common_prefix(i, i + (length + step) * direction) > min_prefix {
+            length += step;
+        }
+    }
+
+    let j = i + length * direction;
+
+    // find the split, the last key sharing more than the node's prefix with i
+    let node_prefix = common_prefix(i, j);
+
+    var split = 0;
+    var step = length;
+
+    loop {
+        step = (step + 1) / 2;
+
+        if common_prefix(i, i + (split + step) * direction) > node_prefix {
+            split += step;
+        }
+
+        if step <= 1 {
+            break;
+        }
+    }
+
+    let gamma = u32(i + split * direction + min(direction, 0));
+    let first = u32(min(i, j));
+    let last = u32(max(i, j));
+
+    let left = select(gamma, leaf_index(gamma), first == gamma);
+    let right = select(gamma + 1u, leaf_index(gamma + 1u), last == gamma + 1u);
+
+    // the children of every internal node are stored next to each other, after the root
+    slots[left] = 2u * id.x + 1u;
+    slots[right] = 2u * id.x + 2u;
+    ranges[id.x] = vec2(first, last);
+
+    // the root is never a child
+    if id.x == 0u {
+        slots[0] = 0u;
+    }
+}
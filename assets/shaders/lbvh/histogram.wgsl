@@ -0,0 +1,26 @@
+#include assets/shaders/lib/lbvh/radix_sort.wgsl
+
+var<workgroup> counts: array<atomic<u32>, RADIX_SIZE>;
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn compute(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+    @builtin(workgroup_id) tile: vec3<u32>,
+) {
+    if local < RADIX_SIZE {
+        atomicStore(&counts[local], 0u);
+    }
+
+    workgroupBarrier();
+
+    if id.x < settings.triangle_count {
+        atomicAdd(&counts[digit(keys_in[id.x])], 1u);
+    }
+
+    workgroupBarrier();
+
+    if local < RADIX_SIZE {
+        histogram[local * tile_count() + tile.x] = atomicLoad(&counts[local]);
+    }
+}
@@ -0,0 +1,35 @@
+#include assets/shaders/lib/lbvh/build.wgsl
+
+// spreads the lowest 10 bits out to every third bit
+fn expand_bits(value: u32) -> u32 {
+    var x = value & 0x3ffu;
+
+    x = (x | (x << 16u)) & 0x030000ffu;
+    x = (x | (x << 8u)) & 0x0300f00fu;
+    x = (x | (x << 4u)) & 0x030c30c3u;
+    x = (x | (x << 2u)) & 0x09249249u;
+
+    return x;
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn compute(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= settings.triangle_count {
+        return;
+    }
+
+    let bounds = triangle_bounds(id.x);
+    let centroid = (bounds.min + bounds.max) * 0.5;
+
+    let extent = max(settings.bounds_max - settings.bounds_min, vec3(1e-20));
+    let position = clamp((centroid - settings.bounds_min) / extent, vec3(0.0), vec3(1.0));
+    let cell = vec3<u32>(position * 1023.0);
+
+    keys[id.x] = (expand_bits(cell.x) << 2u) | (expand_bits(cell.y) << 1u) | expand_bits(cell.z);
+    values[id.x] = id.x;
+
+    // reset the counters of the internal nodes for fitting their bounds
+    if id.x + 1u < settings.triangle_count {
+        atomicStore(&counters[id.x], 0u);
+    }
+}
@@ -0,0 +1,10 @@
+#include assets/shaders/lib/lbvh/build.wgsl
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn compute(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= settings.triangle_count {
+        return;
+    }
+
+    triangles.list[settings.triangle_offset + id.x] = sorted_triangles[id.x];
+}
@@ -0,0 +1,42 @@
+#include assets/shaders/lib/lbvh/radix_sort.wgsl
+
+var<workgroup> sums: array<u32, WORKGROUP_SIZE>;
+
+// an exclusive prefix sum over the whole histogram, run as a single workgroup that scans it in chunks
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn compute(@builtin(local_invocation_index) local: u32) {
+    let len = RADIX_SIZE * tile_count();
+    var carry = 0u;
+
+    for (var start = 0u; start < len; start += WORKGROUP_SIZE) {
+        let index = start + local;
+
+        var count = 0u;
+
+        if index < len {
+            count = histogram[index];
+        }
+
+        sums[local] = count;
+        workgroupBarrier();
+
+        for (var offset = 1u; offset < WORKGROUP_SIZE; offset <<= 1u) {
+            var sum = sums[local];
+
+            if local >= offset {
+                sum += sums[local - offset];
+            }
+
+            workgroupBarrier();
+            sums[local] = sum;
+            workgroupBarrier();
+        }
+
+        if index < len {
+            histogram[index] = carry + sums[local] - count;
+        }
+
+        carry += sums[WORKGROUP_SIZE - 1u];
+        workgroupBarrier();
+    }
+}
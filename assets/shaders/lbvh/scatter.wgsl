@@ -0,0 +1,56 @@
+#include assets/shaders/lib/lbvh/radix_sort.wgsl
+
+const MASK_WORDS: u32 = WORKGROUP_SIZE / 32u;
+const MASK_COUNT: u32 = RADIX_SIZE * MASK_WORDS;
+
+// a bit per thread of the tile for every digit, set if the thread's key has that digit
+var<workgroup> digit_masks: array<atomic<u32>, MASK_COUNT>;
+var<workgroup> digit_offsets: array<u32, RADIX_SIZE>;
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn compute(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+    @builtin(workgroup_id) tile: vec3<u32>,
+) {
+    if local < MASK_COUNT {
+        atomicStore(&digit_masks[local], 0u);
+    }
+
+    if local < RADIX_SIZE {
+        digit_offsets[local] = histogram[local * tile_count() + tile.x];
+    }
+
+    workgroupBarrier();
+
+    let valid = id.x < settings.triangle_count;
+    let word = local / 32u;
+    let bit = local % 32u;
+
+    var key = 0u;
+    var key_digit = 0u;
+
+    if valid {
+        key = keys_in[id.x];
+        key_digit = digit(key);
+
+        atomicOr(&digit_masks[key_digit * MASK_WORDS + word], 1u << bit);
+    }
+
+    workgroupBarrier();
+
+    if valid {
+        // count the keys before this one in the tile with the same digit, which keeps the sort stable
+        let masks = key_digit * MASK_WORDS;
+        var rank = countOneBits(atomicLoad(&digit_masks[masks + word]) & ((1u << bit) - 1u));
+
+        for (var i = 0u; i < word; i++) {
+            rank += countOneBits(atomicLoad(&digit_masks[masks + i]));
+        }
+
+        let destination = digit_offsets[key_digit] + rank;
+
+        keys_out[destination] = key;
+        values_out[destination] = values_in[id.x];
+    }
+}
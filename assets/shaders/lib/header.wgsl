@@ -38,6 +38,27 @@ struct View {
     height: u32,
 
     frame_count: u32,
+
+    // which layout BVHs are traversed in, see BVH_LAYOUT_*
+    bvh_layout: u32,
+
+    // nonzero if emitters are sampled directly
+    light_sampling: u32,
+
+    // rotation of the sky about the y axis, in radians
+    sky_rotation: f32,
+    sky_intensity: f32,
+
+    // nonzero if camera rays see the sky
+    sky_visible: u32,
+
+    // nonzero if the sky is computed by atmosphere_radiance instead of read from the cubemap, in
+    // which case the rest describes it
+    physical_sky: u32,
+    sun_direction: vec3<f32>,
+    sun_cone: f32, // 1 - cos of the sun's angular radius
+    turbidity: f32,
+    ground_albedo: f32,
 }
 
 struct ScreenUniform {
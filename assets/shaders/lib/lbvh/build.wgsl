@@ -0,0 +1,99 @@
+#include assets/shaders/lib/lbvh/common.wgsl
+#include assets/shaders/lib/raytrace/intersect.wgsl
+#include assets/shaders/lib/raytrace/bvh.wgsl
+
+struct TriangleListUniform {
+    num_triangles: u32,
+    list: array<Triangle>,
+}
+
+struct VertexListUniform {
+    num_vertices: u32,
+    list: array<Vertex>,
+}
+
+struct BvhUniform {
+    num_nodes: u32,
+    tlas_root: u32,
+    nodes: array<BvhNode>,
+}
+
+@group(0) @binding(0)
+var<storage, read_write> triangles: TriangleListUniform;
+
+@group(0) @binding(1)
+var<storage, read> vertices: VertexListUniform;
+
+@group(0) @binding(2)
+var<storage, read_write> bvh: BvhUniform;
+
+// morton codes, sorted by the radix sort passes
+@group(1) @binding(0)
+var<storage, read_write> keys: array<u32>;
+
+// the triangle of every morton code, relative to the mesh's first triangle
+@group(1) @binding(1)
+var<storage, read_write> values: array<u32>;
+
+// six floats per node slot, stored as bits so they can be shared between threads while fitting
+@group(1) @binding(2)
+var<storage, read_write> node_bounds: array<atomic<u32>>;
+
+// where every node ends up relative to the root, internal nodes first and then the leaves
+@group(1) @binding(3)
+var<storage, read_write> slots: array<u32>;
+
+// the first and last leaf below every internal node
+@group(1) @binding(4)
+var<storage, read_write> ranges: array<vec2<u32>>;
+
+// how many children of every internal node have been fitted
+@group(1) @binding(5)
+var<storage, read_write> counters: array<atomic<u32>>;
+
+@group(1) @binding(6)
+var<storage, read_write> sorted_triangles: array<Triangle>;
+
+// index of a leaf in the slots, after the internal nodes
+fn leaf_index(leaf: u32) -> u32 {
+    return settings.triangle_count - 1u + leaf;
+}
+
+fn triangle_bounds(index: u32) -> BoundingVolume {
+    let triangle = triangles.list[settings.triangle_offset + index];
+
+    let a = vertices.list[triangle.a].position;
+    let b = vertices.list[triangle.b].position;
+    let c = vertices.list[triangle.c].position;
+
+    return BoundingVolume(min(a, min(b, c)), max(a, max(b, c)));
+}
+
+fn store_node_bounds(slot: u32, bounds: BoundingVolume) {
+    let base = slot * 6u;
+
+    atomicStore(&node_bounds[base + 0u], bitcast<u32>(bounds.min.x));
+    atomicStore(&node_bounds[base + 1u], bitcast<u32>(bounds.min.y));
+    atomicStore(&node_bounds[base + 2u], bitcast<u32>(bounds.min.z));
+    atomicStore(&node_bounds[base + 3u], bitcast<u32>(bounds.max.x));
+    atomicStore(&node_bounds[base + 4u], bitcast<u32>(bounds.max.y));
+    atomicStore(&node_bounds[base + 5u], bitcast<u32>(bounds.max.z));
+}
+
+fn load_node_bounds(slot: u32) -> BoundingVolume {
+    let base = slot * 6u;
+
+    let min = vec3(
+        bitcast<f32>(atomicLoad(&node_bounds[base + 0u])),
+        bitcast<f32>(atomicLoad(&node_bounds[base + 1u])),
+        bitcast<f32>(atomicLoad(&node_bounds[base + 2u])),
+    );
+
+    let max = vec3(
+        bitcast<f32>(atomicLoad(&node_bounds[base + 3u])),
+        bitcast<f32>(atomicLoad(&node_bounds[base + 4u])),
+        bitcast<f32>(atomicLoad(&node_bounds[base + 5u])),
+    );
+
+    return BoundingVolume(min, max);
+}
@@ -0,0 +1,20 @@
+const WORKGROUP_SIZE: u32 = 256u;
+
+// must match LbvhSettings in lbvh.rs
+struct LbvhSettings {
+    triangle_offset: u32,
+    triangle_count: u32,
+    node_offset: u32,
+    // bit offset of the digit sorted by the current radix sort pass
+    shift: u32,
+    // bounds of the mesh, which morton codes are relative to
+    bounds_min: vec3<f32>,
+    bounds_max: vec3<f32>,
+}
+
+var<push_constant> settings: LbvhSettings;
+
+// the number of workgroups needed for one thread per triangle
+fn tile_count() -> u32 {
+    return (settings.triangle_count + WORKGROUP_SIZE - 1u) / WORKGROUP_SIZE;
+}
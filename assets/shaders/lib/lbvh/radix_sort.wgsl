@@ -0,0 +1,26 @@
+#include assets/shaders/lib/lbvh/common.wgsl
+
+// bits sorted per pass
+const RADIX_BITS: u32 = 4u;
+const RADIX_SIZE: u32 = 16u;
+
+@group(0) @binding(0)
+var<storage, read> keys_in: array<u32>;
+
+@group(0) @binding(1)
+var<storage, read> values_in: array<u32>;
+
+@group(0) @binding(2)
+var<storage, read_write> keys_out: array<u32>;
+
+@group(0) @binding(3)
+var<storage, read_write> values_out: array<u32>;
+
+// how many keys of every tile have every digit, digit-major. After the scan, where the first of them
+// goes in the output.
+@group(0) @binding(4)
+var<storage, read_write> histogram: array<u32>;
+
+fn digit(key: u32) -> u32 {
+    return (key >> settings.shift) & (RADIX_SIZE - 1u);
+}
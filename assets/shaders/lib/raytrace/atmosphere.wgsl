@@ -1,8 +1,201 @@
-// const ATMOSPHERE_RADIUS_MM = 6.460;
-// const GROUND_RADIUS_MM = 6.360;
+// Single scattering of sunlight in the atmosphere on a single wavelength, after Nishita et al.,
+// "Display of the Earth Taking into Account Atmospheric Scattering" (1993). Distances are in km. The
+// model and its constants must match PhysicalSky in atmosphere.rs.
 
-// fn get
+const GROUND_RADIUS_KM: f32 = 6360.0;
+const ATMOSPHERE_RADIUS_KM: f32 = 6460.0;
+const OBSERVER_ALTITUDE_KM: f32 = 0.01;
 
-// fn rsi_simple(ray: Ray, ) -> vec2<f32> {
+const RAYLEIGH_SCALE_HEIGHT_KM: f32 = 8.0;
+const MIE_SCALE_HEIGHT_KM: f32 = 1.2;
+const RAYLEIGH_SCATTERING_550: f32 = 13.558e-3; // per km
+const MIE_SINGLE_SCATTERING_ALBEDO: f32 = 0.9;
+const MIE_ANGSTROM_EXPONENT: f32 = 1.3;
+const MIE_ASYMMETRY: f32 = 0.76;
+const OZONE_ABSORPTION_PEAK: f32 = 2.44e-3; // per km, at 590 nm in the densest part of the layer
 
-// }
\ No newline at end of file
+const SUN_TEMPERATURE: f32 = 5778.0;
+
+const ATMOSPHERE_VIEW_SAMPLES: u32 = 24u;
+const ATMOSPHERE_SUN_SAMPLES: u32 = 8u;
+
+struct PhysicalSky {
+    sun_direction: vec3<f32>,
+    sun_cone: f32, // 1 - cos of the sun's angular radius
+    turbidity: f32,
+    ground_albedo: f32,
+}
+
+// Scattering and absorption coefficients on a wavelength, per km
+struct AtmosphereCoefficients {
+    rayleigh: f32,
+    mie_scattering: f32,
+    mie_extinction: f32,
+    ozone: f32,
+}
+
+fn atmosphere_coefficients(sky: PhysicalSky, wavelength: f32) -> AtmosphereCoefficients {
+    let rayleigh = RAYLEIGH_SCATTERING_550 * pow(550.0 / wavelength, 4.0);
+
+    // turbidity is the optical depth of the atmosphere relative to clean air
+    let mie_optical_depth = (sky.turbidity - 1.0) * RAYLEIGH_SCATTERING_550 * RAYLEIGH_SCALE_HEIGHT_KM;
+    let mie_extinction = mie_optical_depth / MIE_SCALE_HEIGHT_KM * pow(wavelength / 550.0, -MIE_ANGSTROM_EXPONENT);
+
+    let delta = wavelength - 590.0;
+
+    return AtmosphereCoefficients(
+        rayleigh,
+        mie_extinction * MIE_SINGLE_SCATTERING_ALBEDO,
+        mie_extinction,
+        OZONE_ABSORPTION_PEAK * exp(-delta * delta / 6120.0),
+    );
+}
+
+// Densities of air, aerosols and ozone at the altitude, relative to where they're the densest
+fn atmosphere_density(altitude: f32) -> vec3<f32> {
+    return vec3(
+        exp(-altitude / RAYLEIGH_SCALE_HEIGHT_KM),
+        exp(-altitude / MIE_SCALE_HEIGHT_KM),
+        max(0.0, 1.0 - abs(altitude - 25.0) / 15.0),
+    );
+}
+
+fn atmosphere_extinction(coefficients: AtmosphereCoefficients, density: vec3<f32>) -> f32 {
+    return dot(vec3(coefficients.rayleigh, coefficients.mie_extinction, coefficients.ozone), density);
+}
+
+fn rayleigh_phase(cos_theta: f32) -> f32 {
+    return 3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta);
+}
+
+// Cornette-Shanks phase function
+fn mie_phase(cos_theta: f32) -> f32 {
+    let g = MIE_ASYMMETRY;
+    let g2 = g * g;
+
+    return 3.0 / (8.0 * PI) * (1.0 - g2) * (1.0 + cos_theta * cos_theta) / ((2.0 + g2) * pow(1.0 + g2 - 2.0 * g * cos_theta, 1.5));
+}
+
+// The distance along the ray to the nearest point in front of it on a sphere around the planet's
+// center, or a negative distance if there's none
+fn sphere_distance(origin: vec3<f32>, dir: vec3<f32>, radius: f32) -> f32 {
+    let b = dot(origin, dir);
+    let distance = length(origin);
+
+    // factored, since both squares are huge compared to their difference
+    let c = (distance - radius) * (distance + radius);
+    let discriminant = b * b - c;
+
+    if discriminant < 0.0 {
+        return -1.0;
+    }
+
+    let root = sqrt(discriminant);
+
+    if -b - root > 0.0 {
+        return -b - root;
+    } else if -b + root > 0.0 {
+        return -b + root;
+    }
+
+    return -1.0;
+}
+
+// The spectral irradiance of the sun above the atmosphere, normalized so that a white surface facing
+// the sun there would be as bright as an emission of one at 560 nm
+fn sun_irradiance(wavelength: f32) -> f32 {
+    return PI * blackbody(wavelength, SUN_TEMPERATURE);
+}
+
+// How much sunlight reaches the point through the atmosphere, or zero if the planet is in the way
+fn sun_transmittance(sky: PhysicalSky, coefficients: AtmosphereCoefficients, point: vec3<f32>) -> f32 {
+    if sphere_distance(point, sky.sun_direction, GROUND_RADIUS_KM) > 0.0 {
+        return 0.0;
+    }
+
+    let step = max(0.0, sphere_distance(point, sky.sun_direction, ATMOSPHERE_RADIUS_KM)) / f32(ATMOSPHERE_SUN_SAMPLES);
+
+    var optical_depth = 0.0;
+
+    for (var i = 0u; i < ATMOSPHERE_SUN_SAMPLES; i++) {
+        let sample = point + sky.sun_direction * (f32(i) + 0.5) * step;
+        optical_depth += atmosphere_extinction(coefficients, atmosphere_density(length(sample) - GROUND_RADIUS_KM)) * step;
+    }
+
+    return exp(-optical_depth);
+}
+
+// The light arriving from the sky in the direction, not counting the sun's disc
+fn atmosphere_radiance(sky: PhysicalSky, dir: vec3<f32>, wavelength: f32) -> f32 {
+    let coefficients = atmosphere_coefficients(sky, wavelength);
+
+    let origin = vec3(0.0, GROUND_RADIUS_KM + OBSERVER_ALTITUDE_KM, 0.0);
+
+    let ground_distance = sphere_distance(origin, dir, GROUND_RADIUS_KM);
+    let hits_ground = ground_distance > 0.0;
+
+    var segment = max(0.0, sphere_distance(origin, dir, ATMOSPHERE_RADIUS_KM));
+
+    if hits_ground {
+        segment = ground_distance;
+    }
+
+    let cos_theta = dot(dir, sky.sun_direction);
+    let rayleigh = rayleigh_phase(cos_theta);
+    let mie = mie_phase(cos_theta);
+
+    var optical_depth = 0.0;
+    var radiance = 0.0;
+
+    let samples = f32(ATMOSPHERE_VIEW_SAMPLES);
+
+    for (var i = 0u; i < ATMOSPHERE_VIEW_SAMPLES; i++) {
+        // samples are denser close to the observer, where the air is the densest
+        let t = segment * pow((f32(i) + 0.5) / samples, 2.0);
+        let step = segment * (2.0 * f32(i) + 1.0) / (samples * samples);
+
+        let point = origin + dir * t;
+        let density = atmosphere_density(length(point) - GROUND_RADIUS_KM);
+
+        let extinction = atmosphere_extinction(coefficients, density) * step;
+        let transmittance = exp(-(optical_depth + 0.5 * extinction));
+        optical_depth += extinction;
+
+        let scattering = coefficients.rayleigh * density.x * rayleigh + coefficients.mie_scattering * density.y * mie;
+
+        radiance += transmittance * scattering * sun_transmittance(sky, coefficients, point) * step;
+    }
+
+    radiance *= sun_irradiance(wavelength);
+
+    if hits_ground {
+        // lift the point off the ground, so the ground doesn't shadow itself
+        let normal = normalize(origin + dir * segment);
+        let point = normal * (GROUND_RADIUS_KM + OBSERVER_ALTITUDE_KM);
+
+        radiance += exp(-optical_depth) * sky.ground_albedo / PI * sun_irradiance(wavelength)
+            * sun_transmittance(sky, coefficients, point) * max(0.0, dot(normal, sky.sun_direction));
+    }
+
+    return radiance;
+}
+
+// The radiance of the sun's disc in the direction, dimmed by the atmosphere in front of it, or zero
+// if the direction misses the disc
+fn sun_radiance(sky: PhysicalSky, dir: vec3<f32>, wavelength: f32) -> f32 {
+    if 1.0 - dot(dir, sky.sun_direction) > sky.sun_cone {
+        return 0.0;
+    }
+
+    let coefficients = atmosphere_coefficients(sky, wavelength);
+    let origin = vec3(0.0, GROUND_RADIUS_KM + OBSERVER_ALTITUDE_KM, 0.0);
+
+    if sphere_distance(origin, dir, GROUND_RADIUS_KM) > 0.0 {
+        return 0.0;
+    }
+
+    // a disc of uniform radiance casts an irradiance of pi * sin^2 times its radiance
+    let sin_squared = sky.sun_cone * (2.0 - sky.sun_cone);
+
+    return sun_irradiance(wavelength) / (PI * sin_squared) * sun_transmittance(sky, coefficients, origin);
+}
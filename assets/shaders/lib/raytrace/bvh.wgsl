@@ -10,6 +10,54 @@ struct BvhNode {
     child_node: u32,
 }
 
+// A mesh instance, placing the bottom-level BVH of a mesh in the scene
+struct BvhInstance {
+    object_to_world: mat4x4<f32>,
+    world_to_object: mat4x4<f32>,
+    blas_root: u32,
+    material: u32, // NO_MATERIAL keeps the materials of the mesh's triangles
+    wide_blas_root: u32, // NO_WIDE_ROOT if the mesh's BVH can only be traversed in the binary layout
+}
+
+const NO_MATERIAL: u32 = 0xffffffffu;
+
+// must match BvhLayout in wide_bvh.rs
+const BVH_LAYOUT_BINARY: u32 = 0u;
+const BVH_LAYOUT_WIDE: u32 = 1u;
+
+const NO_WIDE_ROOT: u32 = 0xffffffffu;
+const WIDE_BVH_WIDTH: u32 = 4u;
+
+// the object count of a child slot that holds an internal node
+const INTERNAL_CHILD: u32 = 0xffffu;
+
+// A node of a wide BVH, whose children's bounds are quantized to a byte per axis relative to the
+// node's lower corner. See WideBvhNode in wide_bvh.rs.
+struct WideBvhNode {
+    origin: vec3<f32>,
+    exponents: u32,
+    lower: vec3<u32>,
+    counts_low: u32,
+    upper: vec3<u32>,
+    counts_high: u32,
+    children: vec4<u32>,
+}
+
+const PRIMITIVE_MESH_INSTANCE: u32 = 0u;
+const PRIMITIVE_SPHERE: u32 = 1u;
+const PRIMITIVE_AABB: u32 = 2u;
+// planes aren't in the BVH, but hits on them are marked as planes too
+const PRIMITIVE_PLANE: u32 = 3u;
+
+// An object in the top-level BVH, indexing the list of its type
+struct BvhPrimitive {
+    ty: u32,
+    index: u32,
+}
+
+// the largest finite f32, farther than any hit
+const MAX_DISTANCE: f32 = 3.40282347e38;
+
 struct BvhHit {
     success: bool,
     distance: f32,
@@ -34,7 +82,25 @@ fn ray_bounding_volume_intersect(ray: Ray, bounding_volume: BoundingVolume) -> B
     }
 }
 
-const NODE_STACK_SIZE = 32u;
+// the number of objects in a leaf slot, INTERNAL_CHILD for internal nodes, or 0 for empty slots
+fn wide_child_count(node: WideBvhNode, slot: u32) -> u32 {
+    let counts = select(node.counts_high, node.counts_low, slot < 2u);
+    return (counts >> (16u * (slot % 2u))) & 0xffffu;
+}
+
+fn wide_child_bounds(node: WideBvhNode, slot: u32) -> BoundingVolume {
+    let exponents = (vec3(node.exponents) >> vec3(0u, 8u, 16u)) & vec3(0xffu);
+    let step = bitcast<vec3<f32>>(exponents << vec3(23u));
+
+    let shift = vec3(8u * slot);
+    let lower = vec3<f32>((node.lower >> shift) & vec3(0xffu));
+    let upper = vec3<f32>((node.upper >> shift) & vec3(0xffu));
+
+    return BoundingVolume(node.origin + lower * step, node.origin + upper * step);
+}
+
+// must match MAX_TRAVERSAL_DEPTH in bvh.rs
+const NODE_STACK_SIZE = 64u;
 
 struct NodeStack {
     len: u32,
@@ -69,6 +135,34 @@ fn pop_from_node_stack(node_stack: ptr<function, NodeStack>) {
 
 }
 
+// The internal children of a wide node that a ray hit, sorted from farthest to nearest
+struct WideChildren {
+    len: u32,
+    nodes: array<u32, WIDE_BVH_WIDTH>,
+    distances: array<f32, WIDE_BVH_WIDTH>,
+}
+
+fn insert_wide_child(children: ptr<function, WideChildren>, node: u32, distance: f32) {
+    var i = (*children).len;
+
+    while i > 0u && (*children).distances[i - 1u] < distance {
+        (*children).nodes[i] = (*children).nodes[i - 1u];
+        (*children).distances[i] = (*children).distances[i - 1u];
+        i--;
+    }
+
+    (*children).nodes[i] = node;
+    (*children).distances[i] = distance;
+    (*children).len += 1u;
+}
+
+// pushes the children so that the nearest is on top of the stack
+fn push_wide_children(node_stack: ptr<function, NodeStack>, children: ptr<function, WideChildren>) {
+    for (var i = 0u; i < (*children).len; i++) {
+        push_to_node_stack(node_stack, (*children).nodes[i]);
+    }
+}
+
 fn top_of_node_stack_or(node_stack: ptr<function, NodeStack>, or: u32) -> u32 {
     if node_stack_is_empty(node_stack) || ((*node_stack).len > NODE_STACK_SIZE) {
         return or;
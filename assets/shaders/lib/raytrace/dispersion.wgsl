@@ -0,0 +1,45 @@
+// Must match GpuDispersion in dispersion.rs
+const DISPERSION_NONE: u32 = 0u;
+const DISPERSION_ABBE: u32 = 1u;
+const DISPERSION_CAUCHY: u32 = 2u;
+const DISPERSION_SELLMEIER: u32 = 3u;
+
+// The Fraunhofer lines the Abbe number is defined with, in micrometers
+const FRAUNHOFER_D: f32 = 0.5876;
+const FRAUNHOFER_F: f32 = 0.4861;
+const FRAUNHOFER_C: f32 = 0.6563;
+
+struct Dispersion {
+    b: vec3<f32>, // the Abbe number, Cauchy's a and b, or Sellmeier's b
+    model: u32,
+    c: vec3<f32>, // Sellmeier's c
+}
+
+// The index of refraction on the wavelength in nm, where ior is the one on the d line
+fn dispersion_ior(dispersion: Dispersion, ior: f32, wavelength: f32) -> f32 {
+    let l = wavelength * 0.001;
+    let l2 = l * l;
+
+    switch dispersion.model {
+        case DISPERSION_ABBE: {
+            if dispersion.b.x <= 0.0 {
+                return ior;
+            }
+
+            // the Cauchy equation through the ior on the d line, which spreads the F and C lines apart
+            // by the Abbe number
+            let b = (ior - 1.0) / (dispersion.b.x * (1.0 / (FRAUNHOFER_F * FRAUNHOFER_F) - 1.0 / (FRAUNHOFER_C * FRAUNHOFER_C)));
+            return ior + b * (1.0 / l2 - 1.0 / (FRAUNHOFER_D * FRAUNHOFER_D));
+        }
+        case DISPERSION_CAUCHY: {
+            return dispersion.b.x + dispersion.b.y / l2;
+        }
+        case DISPERSION_SELLMEIER: {
+            let terms = dispersion.b * l2 / (l2 - dispersion.c);
+            return sqrt(max(1.0, 1.0 + terms.x + terms.y + terms.z));
+        }
+        default: {
+            return ior;
+        }
+    }
+}
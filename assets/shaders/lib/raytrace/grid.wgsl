@@ -0,0 +1,43 @@
+// Delta tracking gives up on rays that take more steps than this through the grid volumes
+const MAX_GRID_STEPS: u32 = 1024u;
+
+// A voxel grid placed in the scene, see GpuGridVolume in grid.rs
+struct GridVolume {
+    world_to_grid: mat4x4<f32>, // maps world space onto the grid, which spans from zero to one
+    resolution: vec3<u32>,
+    offset: u32, // the first layer of the grid in the atlas
+    max_density: f32,
+    material: u32, // absorption and scattering are per unit of density
+}
+
+// The part of the ray inside the grid, as distances along the ray starting no earlier than its
+// origin. The ray misses the grid if the start isn't less than the end.
+fn grid_volume_interval(ray: Ray, volume: GridVolume) -> vec2<f32> {
+    // the ray's direction isn't normalized in grid space, so distances stay the same
+    let origin = (volume.world_to_grid * vec4(ray.pos, 1.0)).xyz;
+    let dir = (volume.world_to_grid * vec4(ray.dir, 0.0)).xyz;
+
+    let t_min = -origin / dir;
+    let t_max = (1.0 - origin) / dir;
+
+    let t1 = min(t_min, t_max);
+    let t2 = max(t_min, t_max);
+
+    return vec2(max(max(max(t1.x, t1.y), t1.z), 0.0), min(min(t2.x, t2.y), t2.z));
+}
+
+// Where the point in world space is in the grid's part of the atlas, in texture coordinates, or a
+// negative value if it's outside the grid
+fn grid_atlas_coords(volume: GridVolume, point: vec3<f32>, atlas_size: vec3<f32>) -> vec3<f32> {
+    let uvw = (volume.world_to_grid * vec4(point, 1.0)).xyz;
+
+    if any(uvw < vec3(0.0)) || any(uvw > vec3(1.0)) {
+        return vec3(-1.0);
+    }
+
+    // stay half a voxel inside the grid, so that the grids next to it in the atlas don't bleed into it
+    let resolution = vec3<f32>(volume.resolution);
+    let voxel = clamp(uvw * resolution, vec3(0.5), resolution - 0.5);
+
+    return (voxel + vec3(0.0, 0.0, f32(volume.offset))) / atlas_size;
+}
@@ -1,3 +1,5 @@
+#include assets/shaders/lib/raytrace/dispersion.wgsl
+
 struct Ray {
     pos: vec3<f32>,
     dir: vec3<f32>,
@@ -8,52 +10,84 @@ struct Hit {
     position: vec3<f32>,
     normal: vec3<f32>,
     distance: f32,
-    uv: vec2<f32>, // if negative, there is no texture mapping
+    uv: vec2<f32>, // only triangles have texture coordinates
+    tangent: vec3<f32>, // zero if there are no texture coordinates
+    bitangent: vec3<f32>,
     far_distance: f32,
     front_face: bool,
-    material: Material,
+    outside_ior: f32, // of the medium outside of the hit object, filled in by the path tracer
+    material_id: u32,
+    material: Material, // looked up from the material id once the closest hit is known
+    primitive_ty: u32, // which object was hit, see PRIMITIVE_*
+    primitive_index: u32,
+    triangle: u32, // only for mesh instances
 }
 
 const MATERIAL_LAMBERTIAN: u32 = 0u;
 const MATERIAL_METAL: u32 = 1u;
 const MATERIAL_DIELECTRIC: u32 = 2u;
 const MATERIAL_VOLUME: u32 = 3u;
+const MATERIAL_PRINCIPLED: u32 = 4u;
+
+const NO_TEXTURE: u32 = 0xffffffffu;
 
 struct Material {
     albedo: vec3<f32>,
     ty: u32,
     emission: vec3<f32>,
     roughness: f32,
+    absorption: vec3<f32>, // per unit of distance, for volumes and the media inside dielectrics
     ior: f32,
+    scattering: vec3<f32>,
     g: f32,
+    dispersion: Dispersion,
+    metal_n: vec3<f32>, // complex index of refraction of metals, zero if it comes from the albedo
+    metal_k: vec3<f32>,
+    metallic: f32, // the rest are the layers of principled materials
+    specular_tint: vec3<f32>,
+    specular: f32,
+    sheen: vec3<f32>,
+    sheen_roughness: f32,
+    clearcoat: f32,
+    clearcoat_roughness: f32,
+    anisotropy: f32,
+    anisotropy_rotation: f32,
+    transmission: f32,
+    albedo_texture: u32,
+    roughness_metalness_texture: u32,
+    normal_texture: u32,
+    emission_texture: u32,
 }
 
 struct Sphere {
     center: vec3<f32>,
     radius: f32,
-    material: Material,
+    material: u32,
 }
 
 struct Plane {
     normal: vec3<f32>,
     point: vec3<f32>,
-    material: Material,
+    material: u32,
 }
 
 struct Aabb {
     min: vec3<f32>,
     max: vec3<f32>,
-    material: Material,
+    material: u32,
+}
+
+struct Vertex {
+    position: vec3<f32>,
+    uv: vec2<f32>,
 }
 
+// indices into the vertex and material lists
 struct Triangle {
-    a: vec3<f32>,
-    b: vec3<f32>,
-    c: vec3<f32>,
-    uv_a: vec2<f32>,
-    uv_b: vec2<f32>,
-    uv_c: vec2<f32>,
-    material: Material,
+    a: u32,
+    b: u32,
+    c: u32,
+    material: u32,
 }
 
 fn merge_hit(a: Hit, b: Hit) -> Hit {
@@ -80,7 +114,7 @@ fn merge_hit(a: Hit, b: Hit) -> Hit {
 fn ray_sphere_intersect(ray: Ray, sphere: Sphere) -> Hit {
     var hit: Hit;
     hit.success = false;
-    hit.material = sphere.material;
+    hit.material_id = sphere.material;
 
     let origin_to_center = ray.pos - sphere.center;
 
@@ -123,7 +157,7 @@ fn ray_sphere_intersect(ray: Ray, sphere: Sphere) -> Hit {
 fn ray_plane_intersect(ray: Ray, plane: Plane) -> Hit {
     var hit: Hit;
     hit.success = false;
-    hit.material = plane.material;
+    hit.material_id = plane.material;
 
     let denom = dot(plane.normal, ray.dir);
 
@@ -150,7 +184,7 @@ fn ray_plane_intersect(ray: Ray, plane: Plane) -> Hit {
 
 fn ray_aabb_intersect(ray: Ray, aabb: Aabb) -> Hit {
     var hit: Hit;
-    hit.material = aabb.material;
+    hit.material_id = aabb.material;
     hit.uv = vec2(-1.0);
     hit.front_face = !all(clamp(ray.pos, aabb.min, aabb.max) == ray.pos);
 
@@ -184,12 +218,12 @@ fn ray_aabb_intersect(ray: Ray, aabb: Aabb) -> Hit {
     return hit;
 }
 
-fn ray_triangle_intersect(ray: Ray, triangle: Triangle) -> Hit {
+// The hit's material id is left unset, the triangle holds it
+fn ray_triangle_intersect(ray: Ray, a: Vertex, b: Vertex, c: Vertex) -> Hit {
     var hit: Hit;
-    hit.material = triangle.material;
 
-    let edge1 = triangle.b - triangle.a;
-    let edge2 = triangle.c - triangle.a;
+    let edge1 = b.position - a.position;
+    let edge2 = c.position - a.position;
 
     let h = cross(ray.dir, edge2);
     let determinant = dot(h, edge1);
@@ -199,7 +233,7 @@ fn ray_triangle_intersect(ray: Ray, triangle: Triangle) -> Hit {
     }
 
     let f = 1.0 / determinant;
-    let s = ray.pos - triangle.a;
+    let s = ray.pos - a.position;
     let u = f * dot(s, h);
 
     if u < 0.0 || u > 1.0 {
@@ -232,7 +266,19 @@ fn ray_triangle_intersect(ray: Ray, triangle: Triangle) -> Hit {
     hit.front_face = dir_dot_normal < 0.0;
     hit.normal *= -sign(dir_dot_normal);
 
-    hit.uv = vec2(-1.0);
+    hit.uv = a.uv * (1.0 - u - v) + b.uv * u + c.uv * v;
+
+    // tangent space follows the direction of the texture coordinates across the triangle
+    let delta_uv1 = b.uv - a.uv;
+    let delta_uv2 = c.uv - a.uv;
+    let uv_determinant = delta_uv1.x * delta_uv2.y - delta_uv2.x * delta_uv1.y;
+
+    if abs(uv_determinant) > 1e-12 {
+        let r = 1.0 / uv_determinant;
+
+        hit.tangent = normalize((edge1 * delta_uv2.y - edge2 * delta_uv1.y) * r);
+        hit.bitangent = normalize((edge2 * delta_uv1.x - edge1 * delta_uv2.x) * r);
+    }
 
     return hit;
 }
\ No newline at end of file
@@ -0,0 +1,119 @@
+// An emissive sphere, aabb or mesh triangle that's sampled directly
+struct Emitter {
+    ty: u32, // see PRIMITIVE_*
+    index: u32, // of the sphere, aabb or mesh instance
+    triangle: u32, // only for mesh instances
+    cdf: f32, // the probability of sampling this emitter or any before it
+}
+
+// must match luminance() in light.rs
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// Weight of a sample taken with the first strategy, when the second could have taken it too
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let pdf_squared = pdf * pdf;
+    let other_pdf_squared = other_pdf * other_pdf;
+
+    if pdf_squared + other_pdf_squared == 0.0 {
+        return 0.0;
+    }
+
+    return pdf_squared / (pdf_squared + other_pdf_squared);
+}
+
+// A uniformly distributed direction within the cone around the axis. The cone's half angle is given
+// as one minus its cosine, which stays precise for narrow cones.
+fn sample_cone(axis: vec3<f32>, one_minus_cos_theta_max: f32) -> vec3<f32> {
+    let one_minus_cos_theta = next_f32() * one_minus_cos_theta_max;
+    let cos_theta = 1.0 - one_minus_cos_theta;
+    let sin_theta = sqrt(max(0.0, one_minus_cos_theta * (2.0 - one_minus_cos_theta)));
+    let phi = TAU * next_f32();
+
+    return tbn_matrix(axis) * vec3(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+}
+
+// A uniformly distributed point on the triangle
+fn sample_triangle(a: vec3<f32>, b: vec3<f32>, c: vec3<f32>) -> vec3<f32> {
+    let r = sqrt(next_f32());
+    let u = 1.0 - r;
+    let v = next_f32() * r;
+
+    return a * u + b * v + c * (1.0 - u - v);
+}
+
+const LIGHT_POINT: u32 = 0u;
+const LIGHT_SPOT: u32 = 1u;
+const LIGHT_DIRECTIONAL: u32 = 2u;
+const LIGHT_AREA: u32 = 3u;
+
+// A light that isn't an object, see GpuLight in light.rs
+struct Light {
+    position: vec3<f32>, // of point and spot lights, the corner of area lights
+    ty: u32, // see LIGHT_*
+    direction: vec3<f32>, // the direction spot and directional lights shine in, the normal of area lights
+    intensity: f32, // what the light's spectrum is scaled by
+    color: vec3<f32>,
+    temperature: f32, // in kelvin, or zero if the light has an rgb color
+    edge_u: vec3<f32>, // of area lights
+    cone_outer: f32, // cos of the outer angle of spot lights, 1 - cos of the angular radius of directional lights
+    edge_v: vec3<f32>,
+    cone_inner: f32, // cos of the inner angle of spot lights
+}
+
+// Planck's law, normalized to one at 560 nm. Written in terms of the ratio of the two exponentials, so
+// that neither overflows for low temperatures.
+fn blackbody(wavelength: f32, temperature: f32) -> f32 {
+    let c2 = 1.4387769e7; // the second radiation constant in nm K
+    let a = c2 / (560.0 * temperature);
+    let b = c2 / (wavelength * temperature);
+
+    return pow(560.0 / wavelength, 5.0) * exp(a - b) * (1.0 - exp(-a)) / (1.0 - exp(-b));
+}
+
+// How much of a spot light's intensity is emitted in the direction, going from one inside the inner
+// cone to zero outside the outer cone
+fn spot_falloff(light: Light, dir: vec3<f32>) -> f32 {
+    let cos_theta = dot(light.direction, dir);
+
+    if cos_theta >= light.cone_inner {
+        return 1.0;
+    }
+
+    if cos_theta <= light.cone_outer {
+        return 0.0;
+    }
+
+    return smoothstep(light.cone_outer, light.cone_inner, cos_theta);
+}
+
+// The distance along the ray to where it hits the front of an area light, or a negative distance if
+// it misses
+fn intersect_area_light(ray: Ray, light: Light) -> f32 {
+    let normal = cross(light.edge_u, light.edge_v);
+    let denom = dot(normal, ray.dir);
+
+    // area lights only emit on their front
+    if denom >= 0.0 {
+        return -1.0;
+    }
+
+    let distance = dot(normal, light.position - ray.pos) / denom;
+
+    if distance <= 0.0 {
+        return -1.0;
+    }
+
+    // the hit position in terms of the edges
+    let p = ray.pos + ray.dir * distance - light.position;
+    let normal_length_squared = dot(normal, normal);
+    let u = dot(cross(p, light.edge_v), normal) / normal_length_squared;
+    let v = dot(cross(light.edge_u, p), normal) / normal_length_squared;
+
+    if u < 0.0 || u > 1.0 || v < 0.0 || v > 1.0 {
+        return -1.0;
+    }
+
+    return distance;
+}
@@ -0,0 +1,141 @@
+// The GGX microfacet distribution with Smith masking-shadowing, after Heitz, "Understanding the
+// Masking-Shadowing Function in Microfacet-Based BRDFs" (2014). Directions are in the shading frame,
+// where the normal is +z, and point away from the surface. Alpha is the roughness along x and y,
+// which differ for anisotropic surfaces.
+
+// Surfaces smoother than this reflect and refract like perfect mirrors
+const GGX_MIN_ALPHA: f32 = 0.001;
+
+fn ggx_alpha(roughness: f32) -> vec2<f32> {
+    return vec2(roughness * roughness);
+}
+
+fn ggx_is_smooth(roughness: f32) -> bool {
+    return roughness * roughness < GGX_MIN_ALPHA;
+}
+
+// The density of microfacet normals
+fn ggx_d(m: vec3<f32>, alpha: vec2<f32>) -> f32 {
+    if m.z <= 0.0 {
+        return 0.0;
+    }
+
+    let stretched = m.xy / alpha;
+    let t = dot(stretched, stretched) + m.z * m.z;
+
+    return 1.0 / (PI * alpha.x * alpha.y * t * t);
+}
+
+fn ggx_lambda(v: vec3<f32>, alpha: vec2<f32>) -> f32 {
+    let stretched = v.xy * alpha;
+
+    return (sqrt(1.0 + dot(stretched, stretched) / (v.z * v.z)) - 1.0) * 0.5;
+}
+
+// The fraction of microfacets visible from the direction
+fn ggx_g1(v: vec3<f32>, alpha: vec2<f32>) -> f32 {
+    return 1.0 / (1.0 + ggx_lambda(v, alpha));
+}
+
+// The fraction of microfacets visible from both directions, which is higher than the product of
+// their g1, since microfacets that are high up tend to be visible from both
+fn ggx_g2(wo: vec3<f32>, wi: vec3<f32>, alpha: vec2<f32>) -> f32 {
+    return 1.0 / (1.0 + ggx_lambda(wo, alpha) + ggx_lambda(wi, alpha));
+}
+
+// A microfacet normal visible from the direction, distributed in proportion to how much of the
+// direction's view it covers, after Heitz, "Sampling the GGX Distribution of Visible Normals" (2018)
+fn sample_ggx_vndf(v: vec3<f32>, alpha: vec2<f32>) -> vec3<f32> {
+    // stretch the microfacets into a hemisphere
+    let vh = normalize(vec3(alpha * v.xy, v.z));
+
+    let length_squared = vh.x * vh.x + vh.y * vh.y;
+    var t1 = vec3(1.0, 0.0, 0.0);
+
+    if length_squared > 0.0 {
+        t1 = vec3(-vh.y, vh.x, 0.0) * inverseSqrt(length_squared);
+    }
+
+    let t2 = cross(vh, t1);
+
+    // a point on the disc the hemisphere projects to, squeezed into the part of it that's visible
+    let r = sqrt(next_f32());
+    let phi = TAU * next_f32();
+    let p1 = r * cos(phi);
+    let s = 0.5 * (1.0 + vh.z);
+    let p2 = (1.0 - s) * sqrt(max(0.0, 1.0 - p1 * p1)) + s * r * sin(phi);
+
+    let nh = p1 * t1 + p2 * t2 + sqrt(max(0.0, 1.0 - p1 * p1 - p2 * p2)) * vh;
+
+    // and unstretch the normal on the hemisphere back onto the microfacets
+    return normalize(vec3(alpha * nh.xy, max(1e-6, nh.z)));
+}
+
+// The probability density of sample_ggx_vndf picking the microfacet normal
+fn ggx_vndf_pdf(v: vec3<f32>, m: vec3<f32>, alpha: vec2<f32>) -> f32 {
+    return ggx_g1(v, alpha) * max(0.0, dot(v, m)) * ggx_d(m, alpha) / v.z;
+}
+
+// The distribution of fibers standing up from cloth, whose sheen is the light reflected off their
+// sides, after Estevez and Kulla, "Production Friendly Microfacet Sheen BRDF" (2017)
+fn charlie_d(m: vec3<f32>, alpha: f32) -> f32 {
+    let inverse_alpha = 1.0 / alpha;
+    let sin_theta_squared = max(0.0, 1.0 - m.z * m.z);
+
+    return (2.0 + inverse_alpha) * pow(sin_theta_squared, 0.5 * inverse_alpha) / TAU;
+}
+
+// The visibility term of sheen, which takes the place of the masking-shadowing function and the
+// denominator of the BRDF, after Neubelt and Pettineo, "Crafting a Next-Gen Material Pipeline for
+// The Order: 1886" (2013)
+fn neubelt_v(wo: vec3<f32>, wi: vec3<f32>) -> f32 {
+    return 1.0 / (4.0 * (wi.z + wo.z - wi.z * wo.z));
+}
+
+// The fraction of unpolarized light reflected by the boundary of a dielectric, where eta is the
+// index of refraction on the far side relative to the side the light arrives from
+fn fresnel_dielectric(cos_theta: f32, eta: f32) -> f32 {
+    let sin_theta_t_squared = (1.0 - cos_theta * cos_theta) / (eta * eta);
+
+    // total internal reflection
+    if sin_theta_t_squared >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_theta_t = sqrt(1.0 - sin_theta_t_squared);
+
+    let rs = (cos_theta - eta * cos_theta_t) / (cos_theta + eta * cos_theta_t);
+    let rp = (eta * cos_theta - cos_theta_t) / (eta * cos_theta + cos_theta_t);
+
+    return 0.5 * (rs * rs + rp * rp);
+}
+
+// The fraction of unpolarized light reflected by a conductor with the complex index of refraction
+// n + ik, relative to the medium around it
+fn fresnel_conductor(cos_theta: f32, n: f32, k: f32) -> f32 {
+    let cos_theta_squared = cos_theta * cos_theta;
+    let sin_theta_squared = 1.0 - cos_theta_squared;
+
+    let t0 = n * n - k * k - sin_theta_squared;
+    let a_squared_plus_b_squared = sqrt(t0 * t0 + 4.0 * n * n * k * k);
+    let a = sqrt(max(0.0, 0.5 * (a_squared_plus_b_squared + t0)));
+
+    let t1 = a_squared_plus_b_squared + cos_theta_squared;
+    let t2 = 2.0 * a * cos_theta;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos_theta_squared * a_squared_plus_b_squared + sin_theta_squared * sin_theta_squared;
+    let t4 = t2 * sin_theta_squared;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    return 0.5 * (rs + rp);
+}
+
+// fresnel_conductor for every color channel, with a complex index of refraction per channel
+fn fresnel_conductor_rgb(cos_theta: f32, n: vec3<f32>, k: vec3<f32>) -> vec3<f32> {
+    return vec3(
+        fresnel_conductor(cos_theta, n.r, k.r),
+        fresnel_conductor(cos_theta, n.g, k.g),
+        fresnel_conductor(cos_theta, n.b, k.b),
+    );
+}
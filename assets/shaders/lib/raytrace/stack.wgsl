@@ -1,12 +1,21 @@
 const STACK_SIZE = 12u;
 
+// What a ray is travelling through, on the path's wavelength
+struct Medium {
+    ior: f32, // dispersed, see dispersion_ior
+    absorption: f32, // per unit of distance
+    scattering: f32,
+    g: f32,
+}
+
+// The media the ray is inside of, the innermost one on top
 struct Stack {
     len: u32,
-    values: array<f32, STACK_SIZE>,
+    values: array<Medium, STACK_SIZE>,
 }
 
 fn new_stack() -> Stack {
-    return Stack(0, array<f32, STACK_SIZE>());
+    return Stack(0, array<Medium, STACK_SIZE>());
 }
 
 fn stack_is_empty(stack: ptr<function, Stack>) -> bool {
@@ -17,7 +26,7 @@ fn stack_is_full(stack: ptr<function, Stack>) -> bool {
     return (*stack).len >= STACK_SIZE;
 }
 
-fn push_to_stack(stack: ptr<function, Stack>, val: f32) {
+fn push_to_stack(stack: ptr<function, Stack>, val: Medium) {
     // only push if we still have capacity
     if !stack_is_full(stack) {
         (*stack).values[(*stack).len] = val;
@@ -33,10 +42,19 @@ fn pop_from_stack(stack: ptr<function, Stack>) {
 
 }
 
-fn top_of_stack_or(stack: ptr<function, Stack>, or: f32) -> f32 {
+fn top_of_stack_or(stack: ptr<function, Stack>, or: Medium) -> Medium {
     if stack_is_empty(stack) || ((*stack).len > STACK_SIZE) {
         return or;
     } else {
         return (*stack).values[(*stack).len - 1u];
     }
-}
\ No newline at end of file
+}
+
+// The medium that the top of the stack is inside of
+fn below_top_of_stack_or(stack: ptr<function, Stack>, or: Medium) -> Medium {
+    if (*stack).len < 2u || ((*stack).len > STACK_SIZE) {
+        return or;
+    } else {
+        return (*stack).values[(*stack).len - 2u];
+    }
+}
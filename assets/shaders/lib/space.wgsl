@@ -16,4 +16,58 @@ fn tbn_matrix(normal: vec3<f32>) -> mat3x3<f32> {
     let bitangent = cross(normal, tangent);
 
     return mat3x3(tangent, bitangent, normal);
-}
\ No newline at end of file
+}
+// Rotates about the y axis, turning +x towards -z for positive angles
+fn rotate_y(v: vec3<f32>, angle: f32) -> vec3<f32> {
+    let c = cos(angle);
+    let s = sin(angle);
+
+    return vec3(c * v.x + s * v.z, v.y, -s * v.x + c * v.z);
+}
+
+// A point on a face of a cubemap, with both coordinates from -1 to 1, starting at the top left corner
+// of the face as it's stored
+struct CubeFaceCoords {
+    face: u32, // in the order +x, -x, +y, -y, +z, -z
+    coords: vec2<f32>,
+}
+
+// The unnormalized direction a cubemap is sampled in to get the point on the face
+fn cube_face_direction(face_coords: CubeFaceCoords) -> vec3<f32> {
+    let s = face_coords.coords.x;
+    let t = face_coords.coords.y;
+
+    switch face_coords.face {
+        case 0u: { return vec3(1.0, -t, -s); }
+        case 1u: { return vec3(-1.0, -t, s); }
+        case 2u: { return vec3(s, 1.0, t); }
+        case 3u: { return vec3(s, -1.0, -t); }
+        case 4u: { return vec3(s, -t, 1.0); }
+        default: { return vec3(-s, -t, -1.0); }
+    }
+}
+
+// The point on a face of a cubemap that sampling it in the direction reads from
+fn cube_face_coords(dir: vec3<f32>) -> CubeFaceCoords {
+    let a = abs(dir);
+
+    if a.x >= a.y && a.x >= a.z {
+        if dir.x > 0.0 {
+            return CubeFaceCoords(0u, vec2(-dir.z, -dir.y) / a.x);
+        } else {
+            return CubeFaceCoords(1u, vec2(dir.z, -dir.y) / a.x);
+        }
+    } else if a.y >= a.z {
+        if dir.y > 0.0 {
+            return CubeFaceCoords(2u, vec2(dir.x, dir.z) / a.y);
+        } else {
+            return CubeFaceCoords(3u, vec2(dir.x, -dir.z) / a.y);
+        }
+    } else {
+        if dir.z > 0.0 {
+            return CubeFaceCoords(4u, vec2(dir.x, -dir.y) / a.z);
+        } else {
+            return CubeFaceCoords(5u, vec2(-dir.x, -dir.y) / a.z);
+        }
+    }
+}
@@ -5,6 +5,10 @@
 #include assets/shaders/lib/raytrace/intersect.wgsl
 #include assets/shaders/lib/raytrace/spectrum.wgsl
 #include assets/shaders/lib/raytrace/bvh.wgsl
+#include assets/shaders/lib/raytrace/light.wgsl
+#include assets/shaders/lib/raytrace/atmosphere.wgsl
+#include assets/shaders/lib/raytrace/grid.wgsl
+#include assets/shaders/lib/raytrace/microfacet.wgsl
 
 const IOR_AIR: f32 = 1.000293;
 
@@ -28,11 +32,59 @@ struct TriangleListUniform {
     list: array<Triangle>,
 }
 
+struct VertexListUniform {
+    num_vertices: u32,
+    list: array<Vertex>,
+}
+
+struct MaterialListUniform {
+    num_materials: u32,
+    list: array<Material>,
+}
+
 struct BvhUniform {
     num_nodes: u32,
+    tlas_root: u32,
     nodes: array<BvhNode>,
 }
 
+struct WideBvhUniform {
+    num_nodes: u32,
+    tlas_root: u32, // NO_WIDE_ROOT if the top-level BVH can only be traversed in the binary layout
+    nodes: array<WideBvhNode>,
+}
+
+struct InstanceListUniform {
+    num_instances: u32,
+    list: array<BvhInstance>,
+}
+
+struct PrimitiveListUniform {
+    num_primitives: u32,
+    list: array<BvhPrimitive>,
+}
+
+struct EmitterListUniform {
+    num_emitters: u32,
+    total_power: f32,
+    list: array<Emitter>,
+}
+
+struct LightListUniform {
+    num_lights: u32,
+    list: array<Light>,
+}
+
+struct GridVolumeListUniform {
+    num_grid_volumes: u32,
+    list: array<GridVolume>,
+}
+
+struct SkyDistributionUniform {
+    size: u32, // texels per side of every face
+    cdf: array<f32>, // the marginal cdf over the rows of every face, then the conditional cdf of every row
+}
+
 @group(0) @binding(0)
 var<storage> screen: ScreenUniform;
 
@@ -49,8 +101,45 @@ var<storage> aabbs: AabbListUniform;
 var<storage> triangles: TriangleListUniform;
 
 @group(1) @binding(4)
+var<storage> vertices: VertexListUniform;
+
+@group(1) @binding(5)
+var<storage> materials: MaterialListUniform;
+
+@group(1) @binding(6)
 var<storage> bvh: BvhUniform;
 
+@group(1) @binding(7)
+var<storage> instances: InstanceListUniform;
+
+@group(1) @binding(8)
+var<storage> primitives: PrimitiveListUniform;
+
+@group(1) @binding(9)
+var material_textures: texture_2d_array<f32>;
+
+@group(1) @binding(10)
+var material_texture_sampler: sampler;
+
+@group(1) @binding(11)
+var<storage> wide_bvh: WideBvhUniform;
+
+@group(1) @binding(12)
+var<storage> emitters: EmitterListUniform;
+
+@group(1) @binding(13)
+var<storage> lights: LightListUniform;
+
+@group(1) @binding(14)
+var<storage> grid_volumes: GridVolumeListUniform;
+
+// density in the red channel and emission in the green channel
+@group(1) @binding(15)
+var grid_atlas: texture_3d<f32>;
+
+@group(1) @binding(16)
+var grid_atlas_sampler: sampler;
+
 @group(2) @binding(0)
 var wavelength_to_xyz_lut: texture_storage_1d<rgba32float, read>;
 
@@ -63,64 +152,150 @@ var sky_cubemap_texture: texture_cube<f32>;
 @group(2) @binding(3)
 var sky_cubemap_sampler: sampler;
 
+@group(2) @binding(4)
+var<storage> sky_distribution: SkyDistributionUniform;
+
 @group(3) @binding(0)
 var color_texture: texture_storage_2d<rgba32float, write>;
 
 @group(3) @binding(1)
 var color_texture_copy: texture_storage_2d<rgba32float, read>;
 
-fn albedo(hit: Hit) -> vec3<f32> {
-    //if any(hit.uv < vec2(0.0)) {
-        return hit.material.albedo;
-    //} else {
-        //return vec3(clamp(hit.uv, vec2(0.0), vec2(1.0)), 0.01);
-    //}
+fn sample_material_texture(index: u32, uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(material_textures, material_texture_sampler, uv, index, 0.0);
 }
 
-fn sky(ray: Ray) -> vec3<f32> {
-    var color = textureSampleLevel(sky_cubemap_texture, sky_cubemap_sampler, ray.dir, 0.0).rgb;
-    color = pow(color, vec3(2.2));
-    let color_length = length(color);
+// Multiplies the material's properties by its textures at the hit's texture coordinates
+fn apply_material_textures(hit: Hit) -> Hit {
+    var textured_hit = hit;
+    let material = hit.material;
+
+    if material.albedo_texture != NO_TEXTURE {
+        let albedo = sample_material_texture(material.albedo_texture, hit.uv).rgb;
+        textured_hit.material.albedo *= pow(albedo, vec3(2.2));
+    }
+
+    if material.roughness_metalness_texture != NO_TEXTURE {
+        let roughness_metalness = sample_material_texture(material.roughness_metalness_texture, hit.uv);
+        textured_hit.material.roughness *= roughness_metalness.g;
+
+        // principled materials blend metal in, the others are either fully metallic or not at all,
+        // so partially metallic texels are chosen to be metallic with a probability of their metalness
+        if material.ty == MATERIAL_PRINCIPLED {
+            textured_hit.material.metallic *= roughness_metalness.b;
+        } else if material.ty == MATERIAL_METAL && next_f32() > roughness_metalness.b {
+            textured_hit.material.ty = MATERIAL_LAMBERTIAN;
+        }
+    }
+
+    if material.emission_texture != NO_TEXTURE {
+        let emission = sample_material_texture(material.emission_texture, hit.uv).rgb;
+        textured_hit.material.emission *= pow(emission, vec3(2.2));
+    }
+
+    if material.normal_texture != NO_TEXTURE {
+        let tangent_normal = sample_material_texture(material.normal_texture, hit.uv).xyz * 2.0 - 1.0;
 
-    color = normalize(color) * min(1000.0, color_length);
+        textured_hit.normal = normalize(tangent_frame(hit) * tangent_normal);
+    }
 
-    return color;
+    return textured_hit;
 }
 
-fn raytrace(ray: Ray) -> Hit {
-    var closest_hit: Hit;
+// The frame of the hit's texture coordinates around its normal, or any frame if it has none
+fn tangent_frame(hit: Hit) -> mat3x3<f32> {
+    if all(hit.tangent == vec3(0.0)) {
+        return tbn_matrix(hit.normal);
+    }
 
-    for (var i = 0u; i < spheres.num_spheres; i++) {
-        let sphere = spheres.list[i];
+    // make the tangent frame orthogonal to the normal, which may have been flipped to face the ray
+    let tangent = normalize(hit.tangent - hit.normal * dot(hit.normal, hit.tangent));
+    let bitangent = cross(hit.normal, tangent) * sign(dot(cross(hit.normal, tangent), hit.bitangent));
 
-        let hit = ray_sphere_intersect(ray, sphere);
-        closest_hit = merge_hit(closest_hit, hit);
+    return mat3x3(tangent, bitangent, hit.normal);
+}
+
+fn physical_sky() -> PhysicalSky {
+    return PhysicalSky(screen.view.sun_direction, screen.view.sun_cone, screen.view.turbidity, screen.view.ground_albedo);
+}
+
+// The sky is stored unrotated, so directions are turned into its space first
+fn sky_space_direction(dir: vec3<f32>) -> vec3<f32> {
+    return rotate_y(dir, -screen.view.sky_rotation);
+}
+
+// The light arriving from the sky on the wavelength. The sky is importance sampled in proportion to
+// this, see SkyDistribution in sky.rs.
+fn sky_radiance(ray: Ray, wavelength: f32) -> f32 {
+    let dir = sky_space_direction(ray.dir);
+
+    var radiance: f32;
+
+    if screen.view.physical_sky != 0u {
+        let sky = physical_sky();
+        radiance = atmosphere_radiance(sky, dir, wavelength) + sun_radiance(sky, dir, wavelength);
+    } else {
+        let color = textureSampleLevel(sky_cubemap_texture, sky_cubemap_sampler, dir, 0.0).rgb;
+        radiance = rgb_to_spectral_intensity(rgb_to_spectral_intensity_lut, color, wavelength);
     }
 
-    for (var i = 0u; i < planes.num_planes; i++) {
-        let plane = planes.list[i];
+    return radiance * screen.view.sky_intensity;
+}
 
-        let hit = ray_plane_intersect(ray, plane);
-        closest_hit = merge_hit(closest_hit, hit);
+// The sky in rgb, for the debug render. Physical skies are only evaluated on a wavelength per channel.
+fn sky(ray: Ray) -> vec3<f32> {
+    if screen.view.physical_sky != 0u {
+        return vec3(sky_radiance(ray, 610.0), sky_radiance(ray, 550.0), sky_radiance(ray, 465.0));
     }
 
-    for (var i = 0u; i < aabbs.num_aabbs; i++) {
-        let aabb = aabbs.list[i];
+    let color = textureSampleLevel(sky_cubemap_texture, sky_cubemap_sampler, sky_space_direction(ray.dir), 0.0).rgb;
+
+    return color * screen.view.sky_intensity;
+}
+
+// Planes are unbounded, so they can't be part of the BVH
+fn raytrace_planes(ray: Ray) -> Hit {
+    var closest_hit: Hit;
+
+    for (var i = 0u; i < planes.num_planes; i++) {
+        let plane = planes.list[i];
+
+        var hit = ray_plane_intersect(ray, plane);
+        hit.primitive_ty = PRIMITIVE_PLANE;
+        hit.primitive_index = i;
 
-        let hit = ray_aabb_intersect(ray, aabb);
         closest_hit = merge_hit(closest_hit, hit);
     }
 
     return closest_hit;
 }
 
-fn raytrace_bvh(ray: Ray) -> Hit {
+fn raytrace_triangle(ray: Ray, index: u32) -> Hit {
+    let triangle = triangles.list[index];
+
+    var hit = ray_triangle_intersect(
+        ray,
+        vertices.list[triangle.a],
+        vertices.list[triangle.b],
+        vertices.list[triangle.c]
+    );
+
+    hit.material_id = triangle.material;
+    hit.triangle = index;
+
+    return hit;
+}
+
+// Traverses the bottom-level BVH of a mesh, with the ray in the mesh's space. Only hits closer than
+// max_distance are returned.
+fn raytrace_blas(ray: Ray, root: u32, max_distance: f32) -> Hit {
     var node_stack = new_node_stack();
-    let default_node = 0u;
+    let default_node = root;
 
     var closest_hit: Hit;
+    var closest_distance = max_distance;
 
-    push_to_node_stack(&node_stack, 0u);
+    push_to_node_stack(&node_stack, root);
 
     while !node_stack_is_empty(&node_stack) {
         let node_index = top_of_node_stack_or(&node_stack, default_node);
@@ -128,14 +303,6 @@ fn raytrace_bvh(ray: Ray) -> Hit {
 
         pop_from_node_stack(&node_stack);
 
-        let node_hit = ray_bounding_volume_intersect(ray, node.bounds);
-
-        // // Skip to the next node if we don't intersect the current node's bounding box
-        // // or if we've already found a hit closer than the bounding box
-        // if !node_hit.success || (closest_hit.success && closest_hit.distance < node_hit.distance) {
-        //     continue;
-        // }
-
         if node.child_node != 0 {
             // node has children, push them to the stack so we can test them next
             let bounds_a = bvh.nodes[node.child_node].bounds;
@@ -145,10 +312,10 @@ fn raytrace_bvh(ray: Ray) -> Hit {
             let child_b_hit = ray_bounding_volume_intersect(ray, bounds_b);
 
             // Push the first child if we're hitting its bounding volume, and if we haven't already found a closer triangle hit
-            var push_child_a = child_a_hit.success && !(closest_hit.success && closest_hit.distance < child_a_hit.distance);
+            var push_child_a = child_a_hit.success && child_a_hit.distance <= closest_distance;
 
             // Push the second child if we're hitting its bounding volume, and if we haven't already found a closer triangle hit
-            var push_child_b = child_b_hit.success && !(closest_hit.success && closest_hit.distance < child_b_hit.distance);
+            var push_child_b = child_b_hit.success && child_b_hit.distance <= closest_distance;
 
             if push_child_a && push_child_b {
                 if child_a_hit.distance < child_b_hit.distance {
@@ -166,10 +333,12 @@ fn raytrace_bvh(ray: Ray) -> Hit {
         } else {
             // node has no children, trace objects directly
             for (var i = node.start_index; i < node.start_index + node.len; i++) {
-                let triangle = triangles.list[i];
+                let hit = raytrace_triangle(ray, i);
 
-                let hit = ray_triangle_intersect(ray, triangle);
-                closest_hit = merge_hit(closest_hit, hit);
+                if hit.success && hit.distance < closest_distance {
+                    closest_hit = hit;
+                    closest_distance = hit.distance;
+                }
             }
         }
     }
@@ -177,161 +346,1753 @@ fn raytrace_bvh(ray: Ray) -> Hit {
     return closest_hit;
 }
 
-fn raytrace_all(ray: Ray) -> Hit {
-    var hit: Hit;
-    hit = merge_hit(hit, raytrace(ray));
-    hit = merge_hit(hit, raytrace_bvh(ray));
-    return hit;
-}
+// Traverses the wide bottom-level BVH of a mesh, like raytrace_blas. Leaf children are traced as soon
+// as their bounds are hit, and the internal children that were hit are visited nearest first.
+fn raytrace_blas_wide(ray: Ray, root: u32, max_distance: f32) -> Hit {
+    var node_stack = new_node_stack();
+
+    var closest_hit: Hit;
+    var closest_distance = max_distance;
+
+    push_to_node_stack(&node_stack, root);
+
+    while !node_stack_is_empty(&node_stack) {
+        let node = wide_bvh.nodes[top_of_node_stack_or(&node_stack, root)];
+
+        pop_from_node_stack(&node_stack);
+
+        var children: WideChildren;
+
+        for (var slot = 0u; slot < WIDE_BVH_WIDTH; slot++) {
+            let count = wide_child_count(node, slot);
+
+            if count == 0u {
+                continue;
+            }
+
+            let child_hit = ray_bounding_volume_intersect(ray, wide_child_bounds(node, slot));
+
+            if !child_hit.success || child_hit.distance > closest_distance {
+                continue;
+            }
+
+            let child = node.children[slot];
+
+            if count == INTERNAL_CHILD {
+                insert_wide_child(&children, child, child_hit.distance);
+                continue;
+            }
+
+            for (var i = child; i < child + count; i++) {
+                let hit = raytrace_triangle(ray, i);
+
+                if hit.success && hit.distance < closest_distance {
+                    closest_hit = hit;
+                    closest_distance = hit.distance;
+                }
+            }
+        }
 
-// Schlick approximation for reflectance
-fn reflectance(cos_theta: f32, ior: f32) -> f32 {
-    var r0 = (1.0 - ior) / (1.0 + ior);
-    r0 *= r0;
+        push_wide_children(&node_stack, &children);
+    }
 
-    return r0 + (1.0 - r0) * pow(1.0 - cos_theta, 5.0);
+    return closest_hit;
 }
 
-fn ggx_normal(normal: vec3<f32>, roughness: f32) -> vec3<f32> {
-    let r1 = next_f32();
-    let r2 = next_f32();
+// Traces the ray through the mesh instance's bottom-level BVH, and moves the hit back into world space
+fn raytrace_instance(ray: Ray, instance: BvhInstance, max_distance: f32) -> Hit {
+    // the direction isn't normalized, so that distances along the ray stay the same in both spaces
+    let object_ray = Ray(
+        (instance.world_to_object * vec4(ray.pos, 1.0)).xyz,
+        (instance.world_to_object * vec4(ray.dir, 0.0)).xyz,
+    );
 
-    let theta = acos(sqrt(r1));
-    let phi = 2.0 * PI * r2;
+    var hit: Hit;
 
-    let x = sin(theta) * cos(phi);
-    let y = sin(theta) * sin(phi);
-    let z = cos(theta);
+    if screen.view.bvh_layout == BVH_LAYOUT_WIDE && instance.wide_blas_root != NO_WIDE_ROOT {
+        hit = raytrace_blas_wide(object_ray, instance.wide_blas_root, max_distance);
+    } else {
+        hit = raytrace_blas(object_ray, instance.blas_root, max_distance);
+    }
+
+    if !hit.success {
+        return hit;
+    }
+
+    // instances are only rotated and uniformly scaled, so normals can be transformed like directions
+    hit.position = ray.pos + ray.dir * hit.distance;
+    hit.normal = normalize((instance.object_to_world * vec4(hit.normal, 0.0)).xyz);
 
-    let rough_normal = vec3(x, y, z);
+    if any(hit.tangent != vec3(0.0)) {
+        hit.tangent = normalize((instance.object_to_world * vec4(hit.tangent, 0.0)).xyz);
+        hit.bitangent = normalize((instance.object_to_world * vec4(hit.bitangent, 0.0)).xyz);
+    }
+
+    if instance.material != NO_MATERIAL {
+        hit.material_id = instance.material;
+    }
 
-    return mix(normal, normalize(tbn_matrix(normal) * rough_normal), roughness);
+    return hit;
 }
 
-struct MaterialHitResult {
-    brdf: f32,
-    next_ray: Ray,
+fn raytrace_primitive(ray: Ray, primitive: BvhPrimitive, max_distance: f32) -> Hit {
+    var hit: Hit;
+
+    switch primitive.ty {
+        case PRIMITIVE_SPHERE: {
+            hit = ray_sphere_intersect(ray, spheres.list[primitive.index]);
+        }
+        case PRIMITIVE_AABB: {
+            hit = ray_aabb_intersect(ray, aabbs.list[primitive.index]);
+        }
+        default: {
+            hit = raytrace_instance(ray, instances.list[primitive.index], max_distance);
+        }
+    }
+
+    hit.primitive_ty = primitive.ty;
+    hit.primitive_index = primitive.index;
+
+    return hit;
 }
 
-fn material_hit_result(hit: Hit, ray: Ray, stack: ptr<function, Stack>, wavelength: f32) -> MaterialHitResult {
-    let albedo = rgb_to_spectral_intensity(rgb_to_spectral_intensity_lut, albedo(hit), wavelength);
-    
-    if hit.material.ty == MATERIAL_LAMBERTIAN {
-        let brdf = albedo / PI;
-        let next_ray = Ray(hit.position + hit.normal * 0.0001, ggx_normal(hit.normal, 1.0));
+// Traverses the top-level BVH over the mesh instances, spheres and aabbs
+fn raytrace_bvh(ray: Ray) -> Hit {
+    var closest_hit: Hit;
 
-        return MaterialHitResult(brdf, next_ray);
-    } else if hit.material.ty == MATERIAL_METAL {
-        let rough_normal = ggx_normal(hit.normal, hit.material.roughness);
+    if primitives.num_primitives == 0u {
+        return closest_hit;
+    }
 
-        let brdf = albedo;
-        
-        let reflect_dir = reflect(ray.dir, rough_normal);
-        let next_ray = Ray(
-            hit.position + hit.normal * 0.0001, 
-            reflect_dir
-        );
+    var node_stack = new_node_stack();
+    let default_node = bvh.tlas_root;
 
-        return MaterialHitResult(brdf, next_ray);
-    } else if hit.material.ty == MATERIAL_DIELECTRIC {
-        let rough_normal = ggx_normal(hit.normal, hit.material.roughness);
+    var closest_distance = MAX_DISTANCE;
 
-        let cos_theta = dot(-ray.dir, rough_normal);
-        let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    push_to_node_stack(&node_stack, bvh.tlas_root);
 
-        let previous_ior = top_of_stack_or(stack, IOR_AIR);
-        let current_ior = hit.material.ior;
+    while !node_stack_is_empty(&node_stack) {
+        let node_index = top_of_node_stack_or(&node_stack, default_node);
+        let node = bvh.nodes[node_index];
 
-        var ior: f32;
+        pop_from_node_stack(&node_stack);
 
-        if hit.front_face {
-            ior = previous_ior / current_ior;
+        if node.child_node != 0 {
+            let bounds_a = bvh.nodes[node.child_node].bounds;
+            let bounds_b = bvh.nodes[node.child_node + 1].bounds;
+
+            let child_a_hit = ray_bounding_volume_intersect(ray, bounds_a);
+            let child_b_hit = ray_bounding_volume_intersect(ray, bounds_b);
+
+            var push_child_a = child_a_hit.success && child_a_hit.distance <= closest_distance;
+            var push_child_b = child_b_hit.success && child_b_hit.distance <= closest_distance;
+
+            if push_child_a && push_child_b {
+                if child_a_hit.distance < child_b_hit.distance {
+                    push_to_node_stack(&node_stack, node.child_node + 1);
+                    push_to_node_stack(&node_stack, node.child_node);
+                } else {
+                    push_to_node_stack(&node_stack, node.child_node);
+                    push_to_node_stack(&node_stack, node.child_node + 1);
+                }
+            } else if push_child_a {
+                push_to_node_stack(&node_stack, node.child_node);
+            } else if push_child_b {
+                push_to_node_stack(&node_stack, node.child_node + 1);
+            }
         } else {
-            ior = current_ior / previous_ior;
+            for (var i = node.start_index; i < node.start_index + node.len; i++) {
+                let hit = raytrace_primitive(ray, primitives.list[i], closest_distance);
+
+                if hit.success && hit.distance < closest_distance {
+                    closest_hit = hit;
+                    closest_distance = hit.distance;
+                }
+            }
         }
+    }
 
-        ior = ior - ((wavelength - 550.0) * 0.0001);
+    return closest_hit;
+}
 
-        let cannot_refract = ior * sin_theta > 1.0;
+// Traverses the wide top-level BVH, like raytrace_bvh
+fn raytrace_bvh_wide(ray: Ray) -> Hit {
+    var closest_hit: Hit;
 
-        var brdf = 0.0;
-        var pos = hit.position;
-        var dir = vec3(0.0);
+    if primitives.num_primitives == 0u {
+        return closest_hit;
+    }
 
-        if cannot_refract || reflectance(cos_theta, ior) > next_f32() {
-            brdf = 1.0;
-            
-            dir = reflect(ray.dir, rough_normal);
-            pos += hit.normal * 0.0001;
-        } else {
-            if hit.front_face {
-                push_to_stack(stack, current_ior);
-            } else {
-                pop_from_stack(stack);
+    var node_stack = new_node_stack();
+
+    var closest_distance = MAX_DISTANCE;
+
+    push_to_node_stack(&node_stack, wide_bvh.tlas_root);
+
+    while !node_stack_is_empty(&node_stack) {
+        let node = wide_bvh.nodes[top_of_node_stack_or(&node_stack, wide_bvh.tlas_root)];
+
+        pop_from_node_stack(&node_stack);
+
+        var children: WideChildren;
+
+        for (var slot = 0u; slot < WIDE_BVH_WIDTH; slot++) {
+            let count = wide_child_count(node, slot);
+
+            if count == 0u {
+                continue;
+            }
+
+            let child_hit = ray_bounding_volume_intersect(ray, wide_child_bounds(node, slot));
+
+            if !child_hit.success || child_hit.distance > closest_distance {
+                continue;
             }
 
-            brdf = albedo;
+            let child = node.children[slot];
 
-            dir = refract(ray.dir, rough_normal, ior);
-            pos -= hit.normal * 0.0001;
+            if count == INTERNAL_CHILD {
+                insert_wide_child(&children, child, child_hit.distance);
+                continue;
+            }
+
+            for (var i = child; i < child + count; i++) {
+                let hit = raytrace_primitive(ray, primitives.list[i], closest_distance);
+
+                if hit.success && hit.distance < closest_distance {
+                    closest_hit = hit;
+                    closest_distance = hit.distance;
+                }
+            }
         }
 
-        return MaterialHitResult(brdf, Ray(pos, dir));
-    // } else if hit.material.ty == MATERIAL_VOLUME {
-    //     var distance_through_volume = 0.0;
+        push_wide_children(&node_stack, &children);
+    }
 
-    //     if hit.front_face {
-    //         distance_through_volume = hit.far_distance - hit.distance;
-    //     } else {
-    //         distance_through_volume = hit.distance;
-    //     }
+    return closest_hit;
+}
 
-    //     let density = 0.05;
-    //     let scatter_distance = -log(next_f32()) / density;
-    //     let scatter = scatter_distance < distance_through_volume;
+fn raytrace_all(ray: Ray) -> Hit {
+    var hit: Hit;
+    hit = merge_hit(hit, raytrace_planes(ray));
 
-    //     if scatter {
-    //         let brdf = albedo;
-    //         let ray_dir = mix(generate_unit_vector(), ray.dir, 0.0);
-    //         let scattered_ray = Ray(hit.position + ray_dir * 0.0001, ray_dir);
-    //         return MaterialHitResult(albedo, scattered_ray);
-    //     } else {
-    //         let transmittance = exp(-density * distance_through_volume);
-    //         let brdf = 1.0 * transmittance;
-            
-    //         let transmitted_ray = Ray(hit.position + ray.dir * 0.0001, ray.dir);
-    //         return MaterialHitResult(brdf, transmitted_ray);
-    //     }
+    if screen.view.bvh_layout == BVH_LAYOUT_WIDE && wide_bvh.tlas_root != NO_WIDE_ROOT {
+        hit = merge_hit(hit, raytrace_bvh_wide(ray));
     } else {
-        return MaterialHitResult(0.0, Ray(vec3(0.0), vec3(0.0)));
+        hit = merge_hit(hit, raytrace_bvh(ray));
+    }
+
+    // only the closest hit's material is needed
+    if hit.success {
+        hit.material = materials.list[hit.material_id];
     }
+
+    return hit;
 }
 
-fn pathtrace(ray: Ray, wavelength: f32) -> vec3<f32> {
-    var incoming_normal = vec3(10.0);
-    var ior_stack = new_stack();
+// The vertex positions of a mesh instance's triangle in world space
+fn instance_triangle(instance: BvhInstance, index: u32) -> array<vec3<f32>, 3> {
+    let triangle = triangles.list[index];
 
-    var throughput = 1.0;
-    var radiance = 0.0;
+    return array(
+        (instance.object_to_world * vec4(vertices.list[triangle.a].position, 1.0)).xyz,
+        (instance.object_to_world * vec4(vertices.list[triangle.b].position, 1.0)).xyz,
+        (instance.object_to_world * vec4(vertices.list[triangle.c].position, 1.0)).xyz,
+    );
+}
 
-    var current_ray = ray;
+// The surface area of an emitter, which its power is computed from the same way as in light.rs
+fn emitter_area(ty: u32, index: u32, triangle: u32) -> f32 {
+    switch ty {
+        case PRIMITIVE_SPHERE: {
+            let radius = spheres.list[index].radius;
+            return 4.0 * PI * radius * radius;
+        }
+        case PRIMITIVE_AABB: {
+            let size = aabbs.list[index].max - aabbs.list[index].min;
+            return 2.0 * (size.x * size.y + size.y * size.z + size.z * size.x);
+        }
+        case PRIMITIVE_MESH_INSTANCE: {
+            let positions = instance_triangle(instances.list[index], triangle);
+            return 0.5 * length(cross(positions[1] - positions[0], positions[2] - positions[0]));
+        }
+        default: {
+            return 0.0;
+        }
+    }
+}
 
-    let max_bounces = 100;
+// One minus the cosine of the half angle of the cone the sphere fills as seen from the point, or zero
+// if the point is inside the sphere
+fn sphere_cone(sphere: Sphere, point: vec3<f32>) -> f32 {
+    let to_center = sphere.center - point;
+    let sin_theta_max_squared = sphere.radius * sphere.radius / dot(to_center, to_center);
 
-    for (var i = 0; i < max_bounces; i++) {
-        let hit = raytrace_all(current_ray);
+    if sin_theta_max_squared >= 1.0 {
+        return 0.0;
+    }
+
+    // computed from the sine, which stays precise for small and distant spheres
+    return sin_theta_max_squared / (1.0 + sqrt(1.0 - sin_theta_max_squared));
+}
+
+// Faces are numbered by axis, with the face on the lower side first. From inside, every face can be seen.
+fn aabb_face_visible(aabb: Aabb, point: vec3<f32>, face: u32) -> bool {
+    let axis = face / 2u;
+    let inside = all(point >= aabb.min) && all(point <= aabb.max);
+
+    if face % 2u == 0u {
+        return inside || point[axis] < aabb.min[axis];
+    } else {
+        return inside || point[axis] > aabb.max[axis];
+    }
+}
+
+fn aabb_face_area(aabb: Aabb, face: u32) -> f32 {
+    let size = aabb.max - aabb.min;
+    let face_areas = vec3(size.y * size.z, size.z * size.x, size.x * size.y);
+
+    return face_areas[face / 2u];
+}
 
-        if !hit.success {
-            // hit sky
-            radiance += throughput * rgb_to_spectral_intensity(rgb_to_spectral_intensity_lut, sky(current_ray), wavelength);
+fn aabb_visible_area(aabb: Aabb, point: vec3<f32>) -> f32 {
+    var area = 0.0;
+
+    for (var face = 0u; face < 6u; face++) {
+        if aabb_face_visible(aabb, point, face) {
+            area += aabb_face_area(aabb, face);
+        }
+    }
+
+    return area;
+}
+
+// A uniformly distributed point on the faces of the aabb that can be seen from the point, so that
+// every direction towards the aabb has a single point it could have been sampled from
+fn sample_aabb(aabb: Aabb, point: vec3<f32>) -> vec3<f32> {
+    var remaining_area = next_f32() * aabb_visible_area(aabb, point);
+    var face = 0u;
+
+    for (var i = 0u; i < 6u; i++) {
+        if !aabb_face_visible(aabb, point, i) {
+            continue;
+        }
+
+        face = i;
+        remaining_area -= aabb_face_area(aabb, i);
+
+        if remaining_area <= 0.0 {
             break;
         }
+    }
 
-        incoming_normal = hit.normal;
-        radiance += throughput * rgb_to_spectral_intensity(rgb_to_spectral_intensity_lut, hit.material.emission, wavelength);
+    var sample = mix(aabb.min, aabb.max, vec3(next_f32(), next_f32(), next_f32()));
 
-        let material_hit_result = material_hit_result(hit, current_ray, &ior_stack, wavelength);
-        throughput *= material_hit_result.brdf;
+    let axis = face / 2u;
+    sample[axis] = select(aabb.min[axis], aabb.max[axis], face % 2u == 1u);
+
+    return sample;
+}
+
+// Picks an emitter in proportion to its power
+fn sample_emitter() -> Emitter {
+    let u = next_f32();
+
+    var low = 0u;
+    var high = emitters.num_emitters - 1u;
+
+    while low < high {
+        let middle = (low + high) / 2u;
+
+        if emitters.list[middle].cdf < u {
+            low = middle + 1u;
+        } else {
+            high = middle;
+        }
+    }
+
+    return emitters.list[low];
+}
+
+// A direction from the point towards the emitter, or a zero vector if it can't be seen from the point
+fn sample_emitter_direction(emitter: Emitter, point: vec3<f32>) -> vec3<f32> {
+    switch emitter.ty {
+        case PRIMITIVE_SPHERE: {
+            let sphere = spheres.list[emitter.index];
+            let cone = sphere_cone(sphere, point);
+
+            if cone <= 0.0 {
+                return vec3(0.0);
+            }
+
+            return sample_cone(normalize(sphere.center - point), cone);
+        }
+        case PRIMITIVE_AABB: {
+            let aabb = aabbs.list[emitter.index];
+
+            if aabb_visible_area(aabb, point) <= 0.0 {
+                return vec3(0.0);
+            }
+
+            return normalize(sample_aabb(aabb, point) - point);
+        }
+        default: {
+            let positions = instance_triangle(instances.list[emitter.index], emitter.triangle);
+            return normalize(sample_triangle(positions[0], positions[1], positions[2]) - point);
+        }
+    }
+}
+
+// The probability density over solid angle of sample_emitter and sample_emitter_direction picking the
+// direction from the point towards the hit. Zero if the hit object isn't an emitter. The hit's material
+// and normal must not have been changed by textures.
+fn emitter_pdf(point: vec3<f32>, hit: Hit) -> f32 {
+    if emitters.num_emitters == 0u || hit.primitive_ty == PRIMITIVE_PLANE {
+        return 0.0;
+    }
+
+    let area = emitter_area(hit.primitive_ty, hit.primitive_index, hit.triangle);
+    let power = luminance(hit.material.emission) * area;
+
+    if power <= 0.0 {
+        return 0.0;
+    }
+
+    let selection_pdf = power / emitters.total_power;
+
+    let to_hit = hit.position - point;
+    let distance_squared = dot(to_hit, to_hit);
+    let cos_theta = abs(dot(hit.normal, to_hit)) * inverseSqrt(distance_squared);
+
+    switch hit.primitive_ty {
+        case PRIMITIVE_SPHERE: {
+            let cone = sphere_cone(spheres.list[hit.primitive_index], point);
+
+            if cone <= 0.0 {
+                return 0.0;
+            }
+
+            return selection_pdf / (TAU * cone);
+        }
+        case PRIMITIVE_AABB: {
+            let visible_area = aabb_visible_area(aabbs.list[hit.primitive_index], point);
+
+            if visible_area <= 0.0 || cos_theta <= 0.0 {
+                return 0.0;
+            }
+
+            return selection_pdf * distance_squared / (cos_theta * visible_area);
+        }
+        default: {
+            if cos_theta <= 0.0 {
+                return 0.0;
+            }
+
+            return selection_pdf * distance_squared / (cos_theta * area);
+        }
+    }
+}
+
+fn hits_emitter(hit: Hit, emitter: Emitter) -> bool {
+    return hit.success
+        && hit.primitive_ty == emitter.ty
+        && hit.primitive_index == emitter.index
+        && (emitter.ty != PRIMITIVE_MESH_INSTANCE || hit.triangle == emitter.triangle);
+}
+
+// The light's spectrum at the wavelength, scaled by its intensity
+fn light_radiance(light: Light, wavelength: f32) -> f32 {
+    var spectrum: f32;
+
+    if light.temperature > 0.0 {
+        spectrum = blackbody(wavelength, light.temperature);
+    } else {
+        spectrum = rgb_to_spectral_intensity(rgb_to_spectral_intensity_lut, light.color, wavelength);
+    }
+
+    return light.intensity * spectrum;
+}
+
+struct AreaLightHit {
+    success: bool,
+    index: u32,
+    distance: f32,
+}
+
+// Area lights aren't objects, so like planes every ray is tested against all of them
+fn raytrace_area_lights(ray: Ray, max_distance: f32) -> AreaLightHit {
+    var hit = AreaLightHit(false, 0u, max_distance);
+
+    for (var i = 0u; i < lights.num_lights; i++) {
+        let light = lights.list[i];
+
+        if light.ty != LIGHT_AREA {
+            continue;
+        }
+
+        let distance = intersect_area_light(ray, light);
+
+        if distance > 0.0 && distance < hit.distance {
+            hit = AreaLightHit(true, i, distance);
+        }
+    }
+
+    return hit;
+}
+
+// The probability density over solid angle of sample_lights picking the direction from the point
+// towards a point on the light at the distance. Zero for lights that only shine from a single point or
+// direction, which can't be hit by chance.
+fn light_pdf(light: Light, point: vec3<f32>, dir: vec3<f32>, distance: f32) -> f32 {
+    let selection_pdf = 1.0 / f32(lights.num_lights);
+
+    switch light.ty {
+        case LIGHT_DIRECTIONAL: {
+            if light.cone_outer <= 0.0 {
+                return 0.0;
+            }
+
+            return selection_pdf / (TAU * light.cone_outer);
+        }
+        case LIGHT_AREA: {
+            let area = length(cross(light.edge_u, light.edge_v));
+            let cos_theta = -dot(light.direction, dir);
+
+            if cos_theta <= 0.0 {
+                return 0.0;
+            }
+
+            return selection_pdf * distance * distance / (cos_theta * area);
+        }
+        default: {
+            return 0.0;
+        }
+    }
+}
+
+// Light from the directional lights whose disc the ray points into, weighted against the material
+// sampling the same direction if bsdf_pdf isn't zero
+fn directional_lights(ray: Ray, bsdf_pdf: f32, wavelength: f32) -> f32 {
+    var radiance = 0.0;
+
+    for (var i = 0u; i < lights.num_lights; i++) {
+        let light = lights.list[i];
+
+        if light.ty != LIGHT_DIRECTIONAL || light.cone_outer <= 0.0 {
+            continue;
+        }
+
+        if 1.0 - dot(-light.direction, ray.dir) > light.cone_outer {
+            continue;
+        }
+
+        var weight = 1.0;
+
+        if bsdf_pdf > 0.0 {
+            weight = power_heuristic(bsdf_pdf, light_pdf(light, ray.pos, ray.dir, 0.0));
+        }
+
+        radiance += weight * light_radiance(light, wavelength);
+    }
+
+    return radiance;
+}
+
+struct LightSample {
+    dir: vec3<f32>,
+    distance: f32, // to the sampled point on the light
+    radiance: f32, // arriving at the point, or the irradiance for lights without a pdf
+    pdf: f32, // see light_pdf
+}
+
+fn sample_light(light: Light, point: vec3<f32>, wavelength: f32) -> LightSample {
+    var sample = LightSample(vec3(0.0), MAX_DISTANCE, 0.0, 0.0);
+
+    switch light.ty {
+        case LIGHT_POINT, LIGHT_SPOT: {
+            let to_light = light.position - point;
+            let distance_squared = dot(to_light, to_light);
+
+            sample.distance = sqrt(distance_squared);
+            sample.dir = to_light / sample.distance;
+            sample.radiance = light_radiance(light, wavelength) / distance_squared;
+
+            if light.ty == LIGHT_SPOT {
+                sample.radiance *= spot_falloff(light, -sample.dir);
+            }
+        }
+        case LIGHT_DIRECTIONAL: {
+            sample.dir = -light.direction;
+
+            if light.cone_outer > 0.0 {
+                sample.dir = sample_cone(-light.direction, light.cone_outer);
+                sample.pdf = light_pdf(light, point, sample.dir, sample.distance);
+            }
+
+            sample.radiance = light_radiance(light, wavelength);
+        }
+        default: {
+            let position = light.position + light.edge_u * next_f32() + light.edge_v * next_f32();
+            let to_light = position - point;
+
+            sample.distance = length(to_light);
+            sample.dir = to_light / sample.distance;
+            sample.pdf = light_pdf(light, point, sample.dir, sample.distance);
+
+            // points behind the light have no pdf, and receive no light
+            if sample.pdf > 0.0 {
+                sample.radiance = light_radiance(light, wavelength);
+            }
+        }
+    }
+
+    return sample;
+}
+
+// Shadow rays give up on light that would have to cross more volume boundaries than this
+const MAX_VOLUME_CROSSINGS: u32 = 8u;
+
+struct VolumeTrace {
+    hit: Hit, // with its distance from the ray's origin
+    transmittance: f32,
+}
+
+// The closest surface along the ray that isn't the boundary of a volume, and how much of the light
+// travelling back along the ray from it, or from the max distance if it's further away, makes it
+// through the media and grid volumes on the way. The ray starts inside the media on the stack.
+fn trace_through_volumes(ray: Ray, max_distance: f32, media: Stack, wavelength: f32) -> VolumeTrace {
+    var stack = media;
+    var current_ray = ray;
+    var travelled = 0.0;
+    var transmittance = 1.0;
+
+    for (var i = 0u; i < MAX_VOLUME_CROSSINGS; i++) {
+        var hit = raytrace_all(current_ray);
+        let medium = top_of_stack_or(&stack, air_medium());
+
+        var distance = max_distance - travelled;
+
+        if hit.success {
+            distance = min(distance, hit.distance);
+        }
+
+        if medium.absorption + medium.scattering > 0.0 {
+            transmittance *= exp(-(medium.absorption + medium.scattering) * distance);
+        }
+
+        transmittance *= grid_transmittance(current_ray, distance, wavelength);
+
+        if !hit.success || travelled + hit.distance >= max_distance || hit.material.ty != MATERIAL_VOLUME {
+            hit.distance += travelled;
+            return VolumeTrace(hit, transmittance);
+        }
+
+        if hit.front_face {
+            push_to_stack(&stack, volume_medium(hit.material, medium, wavelength));
+        } else {
+            pop_from_stack(&stack);
+        }
+
+        travelled += hit.distance;
+        current_ray = Ray(hit.position + current_ray.dir * 0.0001, current_ray.dir);
+    }
+
+    var none: Hit;
+    return VolumeTrace(none, 0.0);
+}
+
+// How much of the light from a light at the distance in the direction arrives at the origin. Nothing
+// does if anything other than volumes is in the way.
+fn light_transmittance(origin: vec3<f32>, dir: vec3<f32>, distance: f32, media: Stack, wavelength: f32) -> f32 {
+    let ray = Ray(origin, dir);
+    let max_distance = distance * 0.9999;
+
+    let trace = trace_through_volumes(ray, max_distance, media, wavelength);
+
+    if trace.hit.success && trace.hit.distance < max_distance {
+        return 0.0;
+    }
+
+    if raytrace_area_lights(ray, max_distance).success {
+        return 0.0;
+    }
+
+    return trace.transmittance;
+}
+
+// Light arriving at the hit directly from a uniformly picked light. Lights that can be hit by chance
+// are weighted against the material sampling the same direction with multiple importance sampling.
+fn sample_lights(hit: Hit, ray: Ray, media: Stack, wavelength: f32) -> f32 {
+    if lights.num_lights == 0u {
+        return 0.0;
+    }
+
+    let origin = hit.position + hit.normal * 0.0001;
+
+    let index = min(u32(next_f32() * f32(lights.num_lights)), lights.num_lights - 1u);
+    let sample = sample_light(lights.list[index], origin, wavelength);
+
+    if sample.radiance <= 0.0 {
+        return 0.0;
+    }
+
+    let bsdf = material_bsdf(hit, ray, sample.dir, wavelength);
+
+    if bsdf <= 0.0 {
+        return 0.0;
+    }
+
+    let transmittance = light_transmittance(origin, sample.dir, sample.distance, media, wavelength);
+
+    if transmittance <= 0.0 {
+        return 0.0;
+    }
+
+    // lights that shine from a single point or direction are only picked by chance
+    if sample.pdf <= 0.0 {
+        return bsdf * transmittance * sample.radiance * f32(lights.num_lights);
+    }
+
+    return bsdf * transmittance * sample.radiance * power_heuristic(sample.pdf, material_pdf(hit, ray, sample.dir, wavelength)) / sample.pdf;
+}
+
+// The entry of the part of the sky's cdfs from start to start + len that u falls into, relative to start
+fn search_sky_cdf(start: u32, len: u32, u: f32) -> u32 {
+    var low = 0u;
+    var high = len - 1u;
+
+    while low < high {
+        let middle = (low + high) / 2u;
+
+        if sky_distribution.cdf[start + middle] < u {
+            low = middle + 1u;
+        } else {
+            high = middle;
+        }
+    }
+
+    return low;
+}
+
+// The probability of search_sky_cdf picking the entry
+fn sky_cdf_probability(start: u32, index: u32) -> f32 {
+    var previous = 0.0;
+
+    if index > 0u {
+        previous = sky_distribution.cdf[start + index - 1u];
+    }
+
+    return sky_distribution.cdf[start + index] - previous;
+}
+
+// The probability of sample_sky_direction picking a direction towards the sun's disc of a physical
+// sky, which is much too small for the sky's distribution to find
+fn sun_sampling_probability() -> f32 {
+    if screen.view.physical_sky != 0u && screen.view.sun_direction.y > 0.0 {
+        return 0.5;
+    }
+
+    return 0.0;
+}
+
+// A direction towards the sky, picked in proportion to the light arriving from it. Picks a row of a
+// cubemap face, then a texel within the row, and then a uniformly distributed point on the texel.
+// The sun of a physical sky is picked on its own, uniformly over its disc.
+fn sample_sky_direction() -> vec3<f32> {
+    if next_f32() < sun_sampling_probability() {
+        return rotate_y(sample_cone(screen.view.sun_direction, screen.view.sun_cone), screen.view.sky_rotation);
+    }
+
+    let size = sky_distribution.size;
+    let rows = 6u * size;
+
+    let row = search_sky_cdf(0u, rows, next_f32());
+    let column = search_sky_cdf(rows + row * size, size, next_f32());
+
+    let texel = vec2(f32(column), f32(row % size)) + vec2(next_f32(), next_f32());
+    let dir = normalize(cube_face_direction(CubeFaceCoords(row / size, texel / f32(size) * 2.0 - 1.0)));
+
+    return rotate_y(dir, screen.view.sky_rotation);
+}
+
+// The probability density over solid angle of sample_sky_direction picking the direction
+fn sky_pdf(dir: vec3<f32>) -> f32 {
+    let sky_dir = sky_space_direction(dir);
+    let sun_probability = sun_sampling_probability();
+
+    var pdf = (1.0 - sun_probability) * sky_distribution_pdf(sky_dir);
+
+    if sun_probability > 0.0 && 1.0 - dot(sky_dir, screen.view.sun_direction) <= screen.view.sun_cone {
+        pdf += sun_probability / (TAU * screen.view.sun_cone);
+    }
+
+    return pdf;
+}
+
+// The probability density over solid angle of the sky's distribution picking the direction in the
+// sky's space
+fn sky_distribution_pdf(dir: vec3<f32>) -> f32 {
+    let size = sky_distribution.size;
+
+    if size == 0u {
+        return 0.0;
+    }
+
+    let face_coords = cube_face_coords(dir);
+    let texel = min(vec2<u32>((face_coords.coords * 0.5 + 0.5) * f32(size)), vec2(size - 1u));
+    let row = face_coords.face * size + texel.y;
+
+    let probability = sky_cdf_probability(0u, row) * sky_cdf_probability(6u * size + row * size, texel.x);
+
+    // points are picked uniformly on the texel, and faces are at a distance of one from the center of
+    // the cube, so the solid angle of a small area shrinks with the cube of its distance
+    let texel_area = 4.0 / f32(size * size);
+    let distance_squared = 1.0 + dot(face_coords.coords, face_coords.coords);
+
+    return probability / texel_area * distance_squared * sqrt(distance_squared);
+}
+
+// Light arriving at the hit directly from the sky, weighted against the material sampling the same
+// direction with multiple importance sampling
+fn sample_sky(hit: Hit, ray: Ray, media: Stack, wavelength: f32) -> f32 {
+    if sky_distribution.size == 0u {
+        return 0.0;
+    }
+
+    let origin = hit.position + hit.normal * 0.0001;
+    let dir = sample_sky_direction();
+
+    let bsdf = material_bsdf(hit, ray, dir, wavelength);
+
+    if bsdf <= 0.0 {
+        return 0.0;
+    }
+
+    let transmittance = light_transmittance(origin, dir, MAX_DISTANCE, media, wavelength);
+
+    if transmittance <= 0.0 {
+        return 0.0;
+    }
+
+    let pdf = sky_pdf(dir);
+
+    if pdf <= 0.0 {
+        return 0.0;
+    }
+
+    let radiance = sky_radiance(Ray(origin, dir), wavelength);
+
+    return bsdf * transmittance * radiance * power_heuristic(pdf, material_pdf(hit, ray, dir, wavelength)) / pdf;
+}
+
+fn air_medium() -> Medium {
+    return Medium(IOR_AIR, 0.0, 0.0, 0.0);
+}
+
+fn material_ior(material: Material, wavelength: f32) -> f32 {
+    return dispersion_ior(material.dispersion, material.ior, wavelength);
+}
+
+// The medium inside the material on the wavelength
+fn material_medium(material: Material, wavelength: f32) -> Medium {
+    return Medium(
+        material_ior(material, wavelength),
+        max(0.0, rgb_to_spectral_intensity(rgb_to_spectral_intensity_lut, material.absorption, wavelength)),
+        max(0.0, rgb_to_spectral_intensity(rgb_to_spectral_intensity_lut, material.scattering, wavelength)),
+        // the phase function becomes a spike at either end
+        clamp(material.g, -0.99, 0.99),
+    );
+}
+
+// The medium inside a volume, which has the same index of refraction as the medium around it, so
+// its boundary doesn't bend light
+fn volume_medium(material: Material, outside: Medium, wavelength: f32) -> Medium {
+    var medium = material_medium(material, wavelength);
+    medium.ior = outside.ior;
+
+    return medium;
+}
+
+// The point the ray is scattered at inside a medium, at the distance along the ray. Its material only
+// describes the medium's phase function, and it has no normal to offset rays from it with.
+fn medium_scatter_hit(ray: Ray, distance: f32, g: f32) -> Hit {
+    var hit: Hit;
+
+    hit.success = true;
+    hit.position = ray.pos + ray.dir * distance;
+    hit.distance = distance;
+    hit.material.ty = MATERIAL_VOLUME;
+    hit.material.g = g;
+
+    return hit;
+}
+
+// Density and emission of the grid volume at the point, which are zero outside of it
+fn sample_grid(volume: GridVolume, point: vec3<f32>) -> vec2<f32> {
+    let coords = grid_atlas_coords(volume, point, vec3<f32>(textureDimensions(grid_atlas)));
+
+    if coords.x < 0.0 {
+        return vec2(0.0);
+    }
+
+    return textureSampleLevel(grid_atlas, grid_atlas_sampler, coords, 0.0).xy;
+}
+
+fn grid_volume_emission(volume: GridVolume, wavelength: f32) -> f32 {
+    return max(0.0, rgb_to_spectral_intensity(rgb_to_spectral_intensity_lut, materials.list[volume.material].emission, wavelength));
+}
+
+// The part of a ray the grid volumes it passes through cover, and the sum of their majorants
+struct GridSegment {
+    start: f32,
+    end: f32,
+    majorant: f32, // no point on the segment is denser than this
+    emissive: bool,
+}
+
+fn grid_segment(ray: Ray, max_distance: f32, wavelength: f32) -> GridSegment {
+    var segment = GridSegment(max_distance, 0.0, 0.0, false);
+
+    for (var i = 0u; i < grid_volumes.num_grid_volumes; i++) {
+        let volume = grid_volumes.list[i];
+        let interval = grid_volume_interval(ray, volume);
+        let end = min(interval.y, max_distance);
+
+        if interval.x >= end {
+            continue;
+        }
+
+        let medium = material_medium(materials.list[volume.material], wavelength);
+
+        segment.start = min(segment.start, interval.x);
+        segment.end = max(segment.end, end);
+        segment.majorant += volume.max_density * (medium.absorption + medium.scattering);
+        segment.emissive = segment.emissive || grid_volume_emission(volume, wavelength) > 0.0;
+    }
+
+    return segment;
+}
+
+struct GridCollision {
+    distance: f32, // MAX_DISTANCE if the ray makes it through every grid volume
+    emission: f32, // emitted by the grid volumes towards the ray's origin, up to the collision
+    scattering_albedo: f32, // the fraction of the light scattered at the collision, the rest is absorbed
+    g: f32,
+}
+
+// Where the ray first collides with the medium of a grid volume before the max distance, with delta
+// tracking. Tentative collisions are picked with the combined majorant of the grid volumes along the
+// ray, and are real in proportion to the density at them, otherwise the ray carries on. The emission
+// at every tentative collision is divided by the majorant, which adds up to the light emitted along
+// the ray, dimmed by the media in front of it.
+fn track_grid_volumes(ray: Ray, max_distance: f32, wavelength: f32) -> GridCollision {
+    var collision = GridCollision(MAX_DISTANCE, 0.0, 0.0, 0.0);
+
+    if grid_volumes.num_grid_volumes == 0u {
+        return collision;
+    }
+
+    let segment = grid_segment(ray, max_distance, wavelength);
+    var majorant = segment.majorant;
+
+    // emission is only gathered at tentative collisions, so glowing grid volumes take a few steps even
+    // if they're hardly dense at all, which only adds collisions that aren't real
+    if segment.emissive {
+        majorant = max(majorant, 16.0 / (segment.end - segment.start));
+    }
+
+    if majorant <= 0.0 {
+        return collision;
+    }
+
+    var t = segment.start;
+
+    for (var step = 0u; step < MAX_GRID_STEPS; step++) {
+        t -= log(1.0 - next_f32()) / majorant;
+
+        if t >= segment.end {
+            break;
+        }
+
+        let point = ray.pos + ray.dir * t;
+        var extinction = 0.0;
+
+        for (var i = 0u; i < grid_volumes.num_grid_volumes; i++) {
+            let volume = grid_volumes.list[i];
+            let grid = sample_grid(volume, point);
+
+            if all(grid == vec2(0.0)) {
+                continue;
+            }
+
+            let medium = material_medium(materials.list[volume.material], wavelength);
+            let volume_extinction = grid.x * (medium.absorption + medium.scattering);
+
+            collision.emission += grid.y * grid_volume_emission(volume, wavelength) / majorant;
+            extinction += volume_extinction;
+
+            // should the collision be real, it's in each grid volume in proportion to its extinction
+            if volume_extinction > 0.0 && next_f32() * extinction < volume_extinction {
+                collision.scattering_albedo = medium.scattering / (medium.absorption + medium.scattering);
+                collision.g = medium.g;
+            }
+        }
+
+        if next_f32() * majorant < extinction {
+            collision.distance = t;
+            break;
+        }
+    }
+
+    return collision;
+}
+
+// How much light makes it through the grid volumes along the ray up to the max distance, with ratio
+// tracking, which dims it at every tentative collision by how likely the collision is to be real
+fn grid_transmittance(ray: Ray, max_distance: f32, wavelength: f32) -> f32 {
+    if grid_volumes.num_grid_volumes == 0u {
+        return 1.0;
+    }
+
+    let segment = grid_segment(ray, max_distance, wavelength);
+
+    if segment.majorant <= 0.0 {
+        return 1.0;
+    }
+
+    var transmittance = 1.0;
+    var t = segment.start;
+
+    for (var step = 0u; step < MAX_GRID_STEPS; step++) {
+        t -= log(1.0 - next_f32()) / segment.majorant;
+
+        if t >= segment.end || transmittance <= 0.0 {
+            break;
+        }
+
+        let point = ray.pos + ray.dir * t;
+        var extinction = 0.0;
+
+        for (var i = 0u; i < grid_volumes.num_grid_volumes; i++) {
+            let volume = grid_volumes.list[i];
+            let medium = material_medium(materials.list[volume.material], wavelength);
+
+            extinction += sample_grid(volume, point).x * (medium.absorption + medium.scattering);
+        }
+
+        transmittance *= 1.0 - extinction / segment.majorant;
+    }
+
+    return max(0.0, transmittance);
+}
+
+// The Henyey-Greenstein phase function, for light scattered by the angle from its direction
+fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+
+    return (1.0 - g * g) / (4.0 * PI * denominator * sqrt(denominator));
+}
+
+// A direction light travelling in the direction is scattered towards, picked in proportion to the
+// Henyey-Greenstein phase function
+fn sample_henyey_greenstein(dir: vec3<f32>, g: f32) -> vec3<f32> {
+    let u = next_f32();
+
+    var cos_theta = 1.0 - 2.0 * u;
+
+    if abs(g) > 1e-3 {
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+        cos_theta = clamp((1.0 + g * g - s * s) / (2.0 * g), -1.0, 1.0);
+    }
+
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let phi = TAU * next_f32();
+
+    return tbn_matrix(dir) * vec3(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+}
+
+// A direction around the normal, distributed in proportion to the cosine of its angle with it
+fn cosine_direction(normal: vec3<f32>) -> vec3<f32> {
+    let r1 = next_f32();
+    let r2 = next_f32();
+
+    let theta = acos(sqrt(r1));
+    let phi = 2.0 * PI * r2;
+
+    let x = sin(theta) * cos(phi);
+    let y = sin(theta) * sin(phi);
+    let z = cos(theta);
+
+    return normalize(tbn_matrix(normal) * vec3(x, y, z));
+}
+
+// Whether rays can pass through the surface into the medium inside the material
+fn material_is_transmissive(material: Material) -> bool {
+    return material.ty == MATERIAL_DIELECTRIC || (material.ty == MATERIAL_PRINCIPLED && material.transmission > 0.0);
+}
+
+// The index of refraction outside of the hit object, which for transmissive materials the ray is
+// leaving is the medium below theirs on the stack
+fn hit_outside_ior(hit: Hit, stack: ptr<function, Stack>) -> f32 {
+    if material_is_transmissive(hit.material) && !hit.front_face {
+        return below_top_of_stack_or(stack, air_medium()).ior;
+    }
+
+    return top_of_stack_or(stack, air_medium()).ior;
+}
+
+// The index of refraction the ray is heading into relative to the one it's coming from
+fn dielectric_eta(hit: Hit, wavelength: f32) -> f32 {
+    let ior = material_ior(hit.material, wavelength);
+
+    if hit.front_face {
+        return ior / hit.outside_ior;
+    }
+
+    return hit.outside_ior / ior;
+}
+
+// The fraction of the light a metal reflects off a microfacet
+fn metal_fresnel(hit: Hit, cos_theta: f32, wavelength: f32) -> f32 {
+    let material = hit.material;
+
+    if all(material.metal_n == vec3(0.0)) && all(material.metal_k == vec3(0.0)) {
+        let albedo = rgb_to_spectral_intensity(rgb_to_spectral_intensity_lut, material.albedo, wavelength);
+
+        // with n = 1, the reflectance head on is k^2 / (4 + k^2), which is solved for the albedo
+        let reflectance = clamp(albedo, 0.0, 0.999);
+        return fresnel_conductor(cos_theta, 1.0, 2.0 * sqrt(reflectance / (1.0 - reflectance)));
+    }
+
+    // n and k aren't reflectances and can be well above 1, which the spectrum lut can't uplift. The
+    // reflectance is computed per color channel instead, and uplifted once it's between 0 and 1.
+    let n = max(vec3(0.001), material.metal_n) / hit.outside_ior;
+    let k = max(vec3(0.0), material.metal_k) / hit.outside_ior;
+    let reflectance = material.albedo * fresnel_conductor_rgb(cos_theta, n, k);
+
+    return rgb_to_spectral_intensity(rgb_to_spectral_intensity_lut, clamp(reflectance, vec3(0.0), vec3(1.0)), wavelength);
+}
+
+// A principled material's layers on the path's wavelength
+struct PrincipledLayers {
+    albedo: f32,
+    sheen: f32,
+    specular_tint: f32,
+    eta: f32, // of the dielectric base, see dielectric_eta
+    alpha: vec2<f32>, // of the base, stretched along the anisotropy
+    clearcoat_alpha: vec2<f32>,
+    sheen_alpha: f32,
+}
+
+fn principled_layers(hit: Hit, wavelength: f32) -> PrincipledLayers {
+    let material = hit.material;
+
+    // microfacets are never perfectly smooth, so that every layer can be evaluated in any direction
+    let alpha = max(material.roughness * material.roughness, GGX_MIN_ALPHA);
+    let anisotropy = clamp(material.anisotropy, 0.0, 1.0);
+
+    return PrincipledLayers(
+        rgb_to_spectral_intensity(rgb_to_spectral_intensity_lut, material.albedo, wavelength),
+        clamp(rgb_to_spectral_intensity(rgb_to_spectral_intensity_lut, material.sheen, wavelength), 0.0, 1.0),
+        max(0.0, rgb_to_spectral_intensity(rgb_to_spectral_intensity_lut, material.specular_tint, wavelength)),
+        dielectric_eta(hit, wavelength),
+        vec2(mix(alpha, 1.0, anisotropy * anisotropy), alpha),
+        vec2(max(material.clearcoat_roughness * material.clearcoat_roughness, GGX_MIN_ALPHA)),
+        max(material.sheen_roughness * material.sheen_roughness, GGX_MIN_ALPHA),
+    );
+}
+
+// The shading frame of principled materials, whose x axis points along the anisotropy
+fn principled_frame(hit: Hit) -> mat3x3<f32> {
+    let frame = tangent_frame(hit);
+
+    let c = cos(hit.material.anisotropy_rotation);
+    let s = sin(hit.material.anisotropy_rotation);
+
+    return mat3x3(frame[0] * c + frame[1] * s, frame[1] * c - frame[0] * s, frame[2]);
+}
+
+// The fraction of the light the clearcoat reflects, which doesn't reach the layers below it. The
+// clearcoat and the sheen are on the outside of the surface, so rays leaving the material don't see
+// them.
+fn clearcoat_fresnel(hit: Hit, cos_theta: f32) -> f32 {
+    if !hit.front_face {
+        return 0.0;
+    }
+
+    return hit.material.clearcoat * fresnel_dielectric(cos_theta, 1.5);
+}
+
+// The fraction of the light the dielectric base reflects specularly
+fn principled_specular_fresnel(hit: Hit, layers: PrincipledLayers, cos_theta: f32) -> f32 {
+    return hit.material.specular * min(1.0, layers.specular_tint * fresnel_dielectric(cos_theta, layers.eta));
+}
+
+// The probabilities of sampling a direction with each of the lobes of a principled material, roughly
+// in proportion to how much light they reflect or transmit. Lobes below the clearcoat are dimmed by
+// how much light it reflects when seen from the direction, and the same for the specular reflection
+// of the dielectric base over the diffuse and transmissive parts of it.
+struct PrincipledLobes {
+    clearcoat: f32,
+    sheen: f32,
+    metal: f32,
+    specular: f32,
+    diffuse: f32,
+    transmission: f32,
+}
+
+fn principled_lobes(hit: Hit, layers: PrincipledLayers, wo: vec3<f32>) -> PrincipledLobes {
+    let material = hit.material;
+
+    let clearcoat = clearcoat_fresnel(hit, wo.z);
+    let base = 1.0 - clearcoat;
+    let dielectric = base * (1.0 - material.metallic);
+    let specular = principled_specular_fresnel(hit, layers, wo.z);
+
+    var sheen = 0.0;
+
+    if hit.front_face {
+        sheen = base * layers.sheen;
+    }
+
+    let lobes = PrincipledLobes(
+        clearcoat,
+        sheen,
+        base * material.metallic,
+        dielectric * specular,
+        dielectric * (1.0 - specular) * (1.0 - material.transmission) * layers.albedo,
+        dielectric * (1.0 - specular) * material.transmission,
+    );
+
+    let sum = lobes.clearcoat + lobes.sheen + lobes.metal + lobes.specular + lobes.diffuse + lobes.transmission;
+
+    if sum <= 0.0 {
+        return PrincipledLobes(0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+    }
+
+    return PrincipledLobes(
+        lobes.clearcoat / sum,
+        lobes.sheen / sum,
+        lobes.metal / sum,
+        lobes.specular / sum,
+        lobes.diffuse / sum,
+        lobes.transmission / sum,
+    );
+}
+
+// The BSDF of a principled material times the cosine term, for light arriving from the direction
+// and leaving towards wo, in the material's shading frame
+fn principled_bsdf(hit: Hit, layers: PrincipledLayers, wo: vec3<f32>, wi: vec3<f32>, wavelength: f32) -> f32 {
+    let material = hit.material;
+    let clearcoat = clearcoat_fresnel(hit, wo.z);
+
+    if wi.z < 0.0 {
+        // refracted by the microfacets of the dielectric base, after Walter et al., "Microfacet
+        // Models for Refraction through Rough Surfaces" (2007)
+        var m = normalize(wo + wi * layers.eta);
+
+        if m.z < 0.0 {
+            m = -m;
+        }
+
+        let cos_o = dot(wo, m);
+        let cos_i = dot(wi, m);
+
+        if cos_o <= 0.0 || cos_i >= 0.0 {
+            return 0.0;
+        }
+
+        let denominator = cos_o + layers.eta * cos_i;
+        let btdf = layers.albedo * (1.0 - fresnel_dielectric(cos_o, layers.eta))
+            * ggx_d(m, layers.alpha) * ggx_g2(wo, wi, layers.alpha)
+            * cos_o * -cos_i * layers.eta * layers.eta / (wo.z * denominator * denominator);
+
+        return (1.0 - clearcoat) * (1.0 - material.metallic) * material.transmission * btdf;
+    }
+
+    let m = normalize(wo + wi);
+
+    // the cosine term cancels out the one in the denominator of the microfacet BRDFs
+    let microfacets = ggx_d(m, layers.alpha) * ggx_g2(wo, wi, layers.alpha) / (4.0 * wo.z);
+
+    let metal = material.metallic * metal_fresnel(hit, dot(wo, m), wavelength) * microfacets;
+    let specular = (1.0 - material.metallic) * principled_specular_fresnel(hit, layers, dot(wo, m)) * microfacets;
+    let diffuse = (1.0 - material.metallic) * (1.0 - material.transmission)
+        * (1.0 - principled_specular_fresnel(hit, layers, wo.z)) * layers.albedo / PI * wi.z;
+
+    var coat = 0.0;
+    var sheen = 0.0;
+
+    if hit.front_face {
+        coat = material.clearcoat * fresnel_dielectric(dot(wo, m), 1.5)
+            * ggx_d(m, layers.clearcoat_alpha) * ggx_g2(wo, wi, layers.clearcoat_alpha) / (4.0 * wo.z);
+        sheen = layers.sheen * charlie_d(m, layers.sheen_alpha) * neubelt_v(wo, wi) * wi.z;
+    }
+
+    return coat + (1.0 - clearcoat) * (sheen + metal + specular + diffuse);
+}
+
+// The probability density of sampling the direction with any of the principled material's lobes
+fn principled_pdf(layers: PrincipledLayers, lobes: PrincipledLobes, wo: vec3<f32>, wi: vec3<f32>) -> f32 {
+    if wi.z < 0.0 {
+        var m = normalize(wo + wi * layers.eta);
+
+        if m.z < 0.0 {
+            m = -m;
+        }
+
+        let cos_o = dot(wo, m);
+        let cos_i = dot(wi, m);
+
+        if cos_o <= 0.0 || cos_i >= 0.0 {
+            return 0.0;
+        }
+
+        // the jacobian of refracting the direction through the microfacet normal
+        let denominator = cos_o + layers.eta * cos_i;
+
+        return lobes.transmission * ggx_vndf_pdf(wo, m, layers.alpha) * -cos_i * layers.eta * layers.eta / (denominator * denominator);
+    }
+
+    let m = normalize(wo + wi);
+    let reflection = 1.0 / (4.0 * dot(wo, m));
+
+    return lobes.clearcoat * ggx_vndf_pdf(wo, m, layers.clearcoat_alpha) * reflection
+        + (lobes.sheen + lobes.diffuse) * wi.z / PI
+        + (lobes.metal + lobes.specular) * ggx_vndf_pdf(wo, m, layers.alpha) * reflection;
+}
+
+struct MaterialHitResult {
+    brdf: f32,
+    next_ray: Ray,
+}
+
+fn absorbed_hit_result(ray: Ray) -> MaterialHitResult {
+    return MaterialHitResult(0.0, ray);
+}
+
+fn material_hit_result(hit: Hit, ray: Ray, stack: ptr<function, Stack>, wavelength: f32) -> MaterialHitResult {
+    let albedo = rgb_to_spectral_intensity(rgb_to_spectral_intensity_lut, hit.material.albedo, wavelength);
+    
+    if hit.material.ty == MATERIAL_LAMBERTIAN {
+        // the cosine weighted direction cancels out the cosine term and the BRDF's 1 / pi
+        let brdf = albedo;
+        let next_ray = Ray(hit.position + hit.normal * 0.0001, cosine_direction(hit.normal));
+
+        return MaterialHitResult(brdf, next_ray);
+    } else if hit.material.ty == MATERIAL_METAL || hit.material.ty == MATERIAL_DIELECTRIC {
+        let tbn = tbn_matrix(hit.normal);
+        let wo = -ray.dir * tbn;
+
+        // normal maps can turn the surface away from the ray
+        if wo.z <= 0.0 {
+            return absorbed_hit_result(ray);
+        }
+
+        let alpha = ggx_alpha(hit.material.roughness);
+        let is_smooth = ggx_is_smooth(hit.material.roughness);
+
+        // the visible microfacet normals are sampled, so only the masking of the light on its way out
+        // remains of the microfacet BSDF divided by its pdf
+        var m = vec3(0.0, 0.0, 1.0);
+
+        if !is_smooth {
+            m = sample_ggx_vndf(wo, alpha);
+        }
+
+        var brdf = 1.0;
+        var pos = hit.position;
+        var wi: vec3<f32>;
+
+        if hit.material.ty == MATERIAL_METAL {
+            brdf = metal_fresnel(hit, dot(wo, m), wavelength);
+
+            wi = reflect(-wo, m);
+            pos += hit.normal * 0.0001;
+
+            if wi.z <= 0.0 {
+                return absorbed_hit_result(ray);
+            }
+        } else {
+            let eta = dielectric_eta(hit, wavelength);
+
+            // the ray is reflected or refracted with the probability of each, which cancels out the
+            // fresnel term
+            if fresnel_dielectric(dot(wo, m), eta) > next_f32() {
+                wi = reflect(-wo, m);
+                pos += hit.normal * 0.0001;
+
+                if wi.z <= 0.0 {
+                    return absorbed_hit_result(ray);
+                }
+            } else {
+                wi = refract(-wo, m, 1.0 / eta);
+                pos -= hit.normal * 0.0001;
+
+                if wi.z >= 0.0 {
+                    return absorbed_hit_result(ray);
+                }
+
+                if hit.front_face {
+                    push_to_stack(stack, material_medium(hit.material, wavelength));
+                } else {
+                    pop_from_stack(stack);
+                }
+
+                brdf = albedo;
+            }
+        }
+
+        if !is_smooth {
+            brdf *= ggx_g2(wo, wi, alpha) / ggx_g1(wo, alpha);
+        }
+
+        return MaterialHitResult(brdf, Ray(pos, tbn * wi));
+    } else if hit.material.ty == MATERIAL_PRINCIPLED {
+        let frame = principled_frame(hit);
+        let wo = -ray.dir * frame;
+
+        if wo.z <= 0.0 {
+            return absorbed_hit_result(ray);
+        }
+
+        let layers = principled_layers(hit, wavelength);
+        let lobes = principled_lobes(hit, layers, wo);
+
+        // a single lobe samples the direction, which is weighted by the pdf of every lobe sampling it
+        let u = next_f32();
+        var wi: vec3<f32>;
+
+        if u < lobes.clearcoat {
+            wi = reflect(-wo, sample_ggx_vndf(wo, layers.clearcoat_alpha));
+        } else if u < lobes.clearcoat + lobes.sheen + lobes.diffuse {
+            wi = cosine_direction(hit.normal) * frame;
+        } else if u < 1.0 - lobes.transmission {
+            wi = reflect(-wo, sample_ggx_vndf(wo, layers.alpha));
+        } else {
+            // zero if the light is reflected back in, which the specular lobe accounts for
+            wi = refract(-wo, sample_ggx_vndf(wo, layers.alpha), 1.0 / layers.eta);
+        }
+
+        let pdf = principled_pdf(layers, lobes, wo, wi);
+
+        if wi.z == 0.0 || pdf <= 0.0 {
+            return absorbed_hit_result(ray);
+        }
+
+        let brdf = principled_bsdf(hit, layers, wo, wi, wavelength) / pdf;
+        var pos = hit.position + hit.normal * 0.0001;
+
+        if wi.z < 0.0 {
+            pos = hit.position - hit.normal * 0.0001;
+
+            if hit.front_face {
+                push_to_stack(stack, material_medium(hit.material, wavelength));
+            } else {
+                pop_from_stack(stack);
+            }
+        }
+
+        return MaterialHitResult(brdf, Ray(pos, frame * wi));
+    } else if hit.material.ty == MATERIAL_VOLUME {
+        // scattered inside a medium, see medium_scatter_hit. The phase function is sampled exactly,
+        // and the light the medium absorbs was accounted for when the ray was scattered.
+        let dir = sample_henyey_greenstein(ray.dir, hit.material.g);
+
+        return MaterialHitResult(1.0, Ray(hit.position, dir));
+    } else {
+        return MaterialHitResult(0.0, Ray(vec3(0.0), vec3(0.0)));
+    }
+}
+
+// Whether the material's BSDF can be evaluated in any direction, which sampling emitters directly needs.
+// The others only pick up emission by hitting emitters. Volumes only get here when scattering inside
+// them, where their phase function is the BSDF. Smooth metals and dielectrics only reflect and refract
+// in a single direction, which emitters are never sampled in.
+fn material_samples_lights(material: Material) -> bool {
+    if material.ty == MATERIAL_METAL || material.ty == MATERIAL_DIELECTRIC {
+        return !ggx_is_smooth(material.roughness);
+    }
+
+    return material.ty == MATERIAL_LAMBERTIAN || material.ty == MATERIAL_VOLUME || material.ty == MATERIAL_PRINCIPLED;
+}
+
+// The BSDF times the cosine term, for light arriving from the direction and leaving back along the ray.
+// Only the light dielectrics reflect is sampled directly, the light they let through isn't.
+fn material_bsdf(hit: Hit, ray: Ray, dir: vec3<f32>, wavelength: f32) -> f32 {
+    if hit.material.ty == MATERIAL_VOLUME {
+        return henyey_greenstein(dot(ray.dir, dir), hit.material.g);
+    }
+
+    if hit.material.ty == MATERIAL_PRINCIPLED {
+        let frame = principled_frame(hit);
+        let wo = -ray.dir * frame;
+        let wi = dir * frame;
+
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+
+        return principled_bsdf(hit, principled_layers(hit, wavelength), wo, wi, wavelength);
+    }
+
+    if hit.material.ty == MATERIAL_LAMBERTIAN {
+        let albedo = rgb_to_spectral_intensity(rgb_to_spectral_intensity_lut, hit.material.albedo, wavelength);
+
+        return albedo / PI * max(0.0, dot(hit.normal, dir));
+    }
+
+    let tbn = tbn_matrix(hit.normal);
+    let wo = -ray.dir * tbn;
+    let wi = dir * tbn;
+
+    if wo.z <= 0.0 || wi.z <= 0.0 {
+        return 0.0;
+    }
+
+    let alpha = ggx_alpha(hit.material.roughness);
+    let m = normalize(wo + wi);
+
+    // the cosine term cancels out the one in the denominator of the microfacet BRDF
+    let microfacets = ggx_d(m, alpha) * ggx_g2(wo, wi, alpha) / (4.0 * wo.z);
+
+    if hit.material.ty == MATERIAL_METAL {
+        return metal_fresnel(hit, dot(wo, m), wavelength) * microfacets;
+    }
+
+    return fresnel_dielectric(dot(wo, m), dielectric_eta(hit, wavelength)) * microfacets;
+}
+
+// The probability density over solid angle of material_hit_result sampling the direction
+fn material_pdf(hit: Hit, ray: Ray, dir: vec3<f32>, wavelength: f32) -> f32 {
+    if hit.material.ty == MATERIAL_VOLUME {
+        return henyey_greenstein(dot(ray.dir, dir), hit.material.g);
+    }
+
+    if hit.material.ty == MATERIAL_PRINCIPLED {
+        let frame = principled_frame(hit);
+        let wo = -ray.dir * frame;
+        let wi = dir * frame;
+
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+
+        let layers = principled_layers(hit, wavelength);
+
+        return principled_pdf(layers, principled_lobes(hit, layers, wo), wo, wi);
+    }
+
+    if hit.material.ty == MATERIAL_LAMBERTIAN {
+        return max(0.0, dot(hit.normal, dir)) / PI;
+    }
+
+    let tbn = tbn_matrix(hit.normal);
+    let wo = -ray.dir * tbn;
+    let wi = dir * tbn;
+
+    // light passing through dielectrics isn't sampled directly, so those directions aren't weighted
+    if wo.z <= 0.0 || wi.z <= 0.0 {
+        return 0.0;
+    }
+
+    let alpha = ggx_alpha(hit.material.roughness);
+    let m = normalize(wo + wi);
+
+    // the jacobian of reflecting the direction off the microfacet normal
+    let pdf = ggx_vndf_pdf(wo, m, alpha) / (4.0 * dot(wo, m));
+
+    if hit.material.ty == MATERIAL_DIELECTRIC {
+        return fresnel_dielectric(dot(wo, m), dielectric_eta(hit, wavelength)) * pdf;
+    }
+
+    return pdf;
+}
+
+// Light arriving at the hit directly from a randomly picked emitter, weighted against the material
+// sampling the same direction with multiple importance sampling
+fn sample_emitters(hit: Hit, ray: Ray, media: Stack, wavelength: f32) -> f32 {
+    if emitters.num_emitters == 0u {
+        return 0.0;
+    }
+
+    let origin = hit.position + hit.normal * 0.0001;
+
+    let emitter = sample_emitter();
+    let dir = sample_emitter_direction(emitter, origin);
+
+    // also rejects emitters that couldn't be sampled
+    let bsdf = material_bsdf(hit, ray, dir, wavelength);
+
+    if bsdf <= 0.0 {
+        return 0.0;
+    }
+
+    // the emitter is occluded if something other than a volume is hit first
+    let trace = trace_through_volumes(Ray(origin, dir), MAX_DISTANCE, media, wavelength);
+    let emitter_hit = trace.hit;
+
+    if !hits_emitter(emitter_hit, emitter) {
+        return 0.0;
+    }
+
+    if raytrace_area_lights(Ray(origin, dir), emitter_hit.distance).success {
+        return 0.0;
+    }
+
+    let pdf = emitter_pdf(origin, emitter_hit);
+
+    if pdf <= 0.0 {
+        return 0.0;
+    }
+
+    let emission = rgb_to_spectral_intensity(
+        rgb_to_spectral_intensity_lut,
+        apply_material_textures(emitter_hit).material.emission,
+        wavelength
+    );
+
+    return bsdf * trace.transmittance * emission * power_heuristic(pdf, material_pdf(hit, ray, dir, wavelength)) / pdf;
+}
+
+fn pathtrace(ray: Ray, wavelength: f32) -> vec3<f32> {
+    var incoming_normal = vec3(10.0);
+    var media = new_stack();
+
+    var throughput = 1.0;
+    var radiance = 0.0;
+
+    var current_ray = ray;
+
+    // where the ray was last scattered from, which stays the same while it passes through the
+    // boundaries of volumes
+    var vertex = ray.pos;
+
+    // the probability density of the material sampling the current ray, or zero if lights weren't
+    // sampled at the last bounce, in which case hitting them isn't weighted
+    var bsdf_pdf = 0.0;
+
+    // emissive objects and the sky are only sampled directly with light sampling turned on, lights
+    // always are, since most of them can't be hit by chance
+    let emitter_sampling = screen.view.light_sampling != 0u;
+
+    let max_bounces = 100;
+
+    for (var i = 0; i < max_bounces; i++) {
+        let untextured_hit = raytrace_all(current_ray);
+
+        var max_distance = MAX_DISTANCE;
+
+        if untextured_hit.success {
+            max_distance = untextured_hit.distance;
+        }
+
+        let light_hit = raytrace_area_lights(current_ray, max_distance);
+
+        // the ray may be scattered or absorbed by the medium it's in before reaching anything,
+        // which happens more often the denser the medium is
+        let medium = top_of_stack_or(&media, air_medium());
+        let extinction = medium.absorption + medium.scattering;
+
+        var scatter_distance = MAX_DISTANCE;
+
+        if extinction > 0.0 {
+            scatter_distance = -log(1.0 - next_f32()) / extinction;
+        }
+
+        // grid volumes overlap the medium, so they're only tracked until the ray collides with it
+        let grid_collision = track_grid_volumes(current_ray, min(scatter_distance, light_hit.distance), wavelength);
+        radiance += throughput * grid_collision.emission;
+
+        var hit: Hit;
+
+        if grid_collision.distance < MAX_DISTANCE {
+            throughput *= grid_collision.scattering_albedo;
+
+            hit = medium_scatter_hit(current_ray, grid_collision.distance, grid_collision.g);
+        } else if scatter_distance < light_hit.distance {
+            // the rest is absorbed
+            throughput *= medium.scattering / extinction;
+
+            hit = medium_scatter_hit(current_ray, scatter_distance, medium.g);
+        } else {
+            if light_hit.success {
+                let light = lights.list[light_hit.index];
+
+                var light_weight = 1.0;
+
+                if bsdf_pdf > 0.0 {
+                    let light_distance = light_hit.distance + distance(vertex, current_ray.pos);
+                    light_weight = power_heuristic(bsdf_pdf, light_pdf(light, vertex, current_ray.dir, light_distance));
+                }
+
+                // area lights don't reflect any light
+                radiance += throughput * light_weight * light_radiance(light, wavelength);
+                break;
+            }
+
+            if !untextured_hit.success {
+                // hit sky, which camera rays only see if it's visible
+                if i == 0 && screen.view.sky_visible == 0u {
+                    break;
+                }
+
+                var sky_weight = 1.0;
+
+                if bsdf_pdf > 0.0 && emitter_sampling {
+                    sky_weight = power_heuristic(bsdf_pdf, sky_pdf(current_ray.dir));
+                }
+
+                radiance += throughput * sky_weight * sky_radiance(current_ray, wavelength);
+                radiance += throughput * directional_lights(current_ray, bsdf_pdf, wavelength);
+                break;
+            }
+
+            if untextured_hit.material.ty == MATERIAL_VOLUME {
+                // the boundary of a volume doesn't reflect or refract, the ray only enters or leaves
+                // its medium
+                if untextured_hit.front_face {
+                    push_to_stack(&media, volume_medium(untextured_hit.material, medium, wavelength));
+                } else {
+                    pop_from_stack(&media);
+                }
+
+                current_ray = Ray(untextured_hit.position + current_ray.dir * 0.0001, current_ray.dir);
+                continue;
+            }
+
+            hit = apply_material_textures(untextured_hit);
+            hit.outside_ior = hit_outside_ior(hit, &media);
+
+            var emission_weight = 1.0;
+
+            if bsdf_pdf > 0.0 && emitter_sampling {
+                emission_weight = power_heuristic(bsdf_pdf, emitter_pdf(vertex, untextured_hit));
+            }
+
+            incoming_normal = hit.normal;
+            radiance += throughput * emission_weight * rgb_to_spectral_intensity(rgb_to_spectral_intensity_lut, hit.material.emission, wavelength);
+        }
+
+        let samples_lights = material_samples_lights(hit.material);
+
+        if samples_lights {
+            if emitter_sampling {
+                radiance += throughput * sample_emitters(hit, current_ray, media, wavelength);
+                radiance += throughput * sample_sky(hit, current_ray, media, wavelength);
+            }
+
+            radiance += throughput * sample_lights(hit, current_ray, media, wavelength);
+        }
+
+        let material_hit_result = material_hit_result(hit, current_ray, &media, wavelength);
+        throughput *= material_hit_result.brdf;
+
+        bsdf_pdf = 0.0;
+
+        if samples_lights {
+            bsdf_pdf = material_pdf(hit, current_ray, material_hit_result.next_ray.dir, wavelength);
+        }
 
         // Russian roulette path termination
         let probability = clamp(throughput, 0.0, 1.0);
@@ -343,6 +2104,7 @@ fn pathtrace(ray: Ray, wavelength: f32) -> vec3<f32> {
         throughput *= 1.0 / probability;
 
         current_ray = material_hit_result.next_ray;
+        vertex = current_ray.pos;
     }
 
     return xyz_to_rgb(wavelength_to_xyz(wavelength_to_xyz_lut, wavelength)) * radiance;
@@ -0,0 +1,180 @@
+use std::{error::Error, path::PathBuf, str::FromStr};
+
+use clap::{Parser, Subcommand};
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    engine::{
+        self,
+        engine_state::EngineState,
+        headless::{self, OfflineRenderSettings},
+    },
+    renderer::lbvh,
+    state::{
+        bvh::{AccelerationStructure, BvhBuildSettings},
+        object::{ObjectList, Transform},
+        scene::Scene,
+    },
+};
+
+#[derive(Parser)]
+#[command(name = "goldenrod", about = "A spectral path tracer")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Open a window and view a scene interactively
+    View {
+        /// Scene description file or .glb/.gltf file; a random scene is generated if omitted
+        scene: Option<PathBuf>,
+    },
+    /// Render a scene offline and write the result to an image file
+    Render {
+        /// Scene description file or .glb/.gltf file
+        scene: PathBuf,
+        /// Samples per pixel, overriding the scene's render settings
+        #[arg(long)]
+        spp: Option<u32>,
+        /// Output resolution as WIDTHxHEIGHT, overriding the scene's render settings
+        #[arg(long)]
+        size: Option<Size>,
+        /// Output image; the format is chosen from the extension (png, exr or pfm)
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Use a software adapter if no hardware adapter is available
+        #[arg(long)]
+        allow_fallback_adapter: bool,
+    },
+    /// Load a scene and write it back out as a scene description file, e.g. to convert a .glb
+    Convert {
+        /// Scene description file or .glb/.gltf file
+        scene: PathBuf,
+        /// Output scene description file
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Build a bounding volume hierarchy for a mesh and print statistics about it
+    BvhStats {
+        /// A .glb file, or a directory containing a .gltf file
+        mesh: PathBuf,
+        /// Number of bins per axis when searching for splits, at least 2
+        #[arg(long, value_parser = parse_bin_count)]
+        bins: Option<usize>,
+        /// Nodes with more triangles are always split
+        #[arg(long)]
+        max_leaf_size: Option<u32>,
+        /// Cost of traversing a node, relative to the intersection cost
+        #[arg(long)]
+        traversal_cost: Option<f32>,
+        /// Cost of intersecting a triangle, relative to the traversal cost
+        #[arg(long)]
+        intersection_cost: Option<f32>,
+        /// Build a linear BVH on the gpu instead, and check it on the cpu
+        #[arg(long)]
+        gpu: bool,
+        /// Use a software adapter if no hardware adapter is available, with --gpu
+        #[arg(long)]
+        allow_fallback_adapter: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Size(PhysicalSize<u32>);
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = s
+            .split_once('x')
+            .ok_or_else(|| format!("expected WIDTHxHEIGHT, got \"{}\"", s))?;
+
+        let parse = |value: &str| {
+            value
+                .parse::<u32>()
+                .ok()
+                .filter(|&value| value > 0)
+                .ok_or_else(|| format!("invalid dimension \"{}\"", value))
+        };
+
+        Ok(Self(PhysicalSize::new(parse(width)?, parse(height)?)))
+    }
+}
+
+fn parse_bin_count(s: &str) -> Result<usize, String> {
+    s.parse::<usize>()
+        .ok()
+        .filter(|&bins| bins >= 2)
+        .ok_or_else(|| format!("expected at least 2 bins, got \"{}\"", s))
+}
+
+pub fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    match cli.command.unwrap_or(Command::View { scene: None }) {
+        Command::View { scene } => {
+            let scene = scene.map(Scene::load).transpose()?;
+            engine::run(scene);
+        }
+        Command::Render {
+            scene,
+            spp,
+            size,
+            output,
+            allow_fallback_adapter,
+        } => {
+            let scene = Scene::load(scene)?;
+
+            let settings = OfflineRenderSettings {
+                size: size.map_or(scene.render_settings.size(), |size| size.0),
+                samples: spp.unwrap_or(scene.render_settings.samples),
+                output_path: output,
+                allow_fallback_adapter,
+            };
+
+            let mut engine_state = EngineState::from_scene(scene);
+            engine_state.camera.reconfigure_aspect(settings.size);
+
+            headless::render(&settings, &engine_state)?;
+        }
+        Command::Convert { scene, output } => {
+            Scene::load(scene)?.save(&output)?;
+            log::info!("Wrote scene to {:?}", output);
+        }
+        Command::BvhStats {
+            mesh,
+            bins,
+            max_leaf_size,
+            traversal_cost,
+            intersection_cost,
+            gpu,
+            allow_fallback_adapter,
+        } => {
+            let default = BvhBuildSettings::default();
+
+            let settings = BvhBuildSettings {
+                bin_count: bins.unwrap_or(default.bin_count),
+                max_leaf_size: max_leaf_size.unwrap_or(default.max_leaf_size),
+                traversal_cost: traversal_cost.unwrap_or(default.traversal_cost),
+                intersection_cost: intersection_cost.unwrap_or(default.intersection_cost),
+                ..default
+            };
+
+            let mut object_list = ObjectList::new();
+            object_list.push_mesh(mesh, Transform::default(), None)?;
+
+            let stats = if gpu {
+                lbvh::validate(&mut object_list, settings, allow_fallback_adapter)?
+            } else {
+                AccelerationStructure::from_objects(&mut object_list, settings, false).blas_stats()
+            };
+
+            for stats in stats {
+                println!("{}", stats);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,19 +1,26 @@
 use glam::{Quat, Vec3};
-use winit::keyboard::KeyCode;
+use winit::{dpi::PhysicalSize, keyboard::KeyCode};
 
 use crate::{
     state::{
-        bvh::BoundingVolumeHierarchy,
+        bvh::{AccelerationStructure, BvhBuildSettings},
         camera::Camera,
         material::Material,
         object::{ObjectList, Sphere},
+        raycast::{Hit, Ray},
+        scene::{Scene, SkySettings},
+        wide_bvh::BvhLayout,
     },
     util,
 };
 
-use super::{input::Input, render_state::RenderState, time::Time};
+use super::{input::Input, time::Time};
 
 pub const RANDOM_SCENE_KEY: KeyCode = KeyCode::KeyK;
+pub const BVH_LAYOUT_KEY: KeyCode = KeyCode::KeyB;
+pub const PICK_KEY: KeyCode = KeyCode::KeyP;
+pub const LIGHT_SAMPLING_KEY: KeyCode = KeyCode::KeyN;
+pub const RANDOM_MATERIAL_KEY: KeyCode = KeyCode::KeyM;
 
 pub struct EngineState {
     pub input: Input,
@@ -21,23 +28,22 @@ pub struct EngineState {
 
     pub camera: Camera,
     pub object_list: ObjectList,
-    pub bounding_volume_hierarchy: BoundingVolumeHierarchy,
+    pub acceleration_structure: AccelerationStructure,
+    /// The layout the raytracer traverses the acceleration structure in.
+    pub bvh_layout: BvhLayout,
+    /// Whether the raytracer samples emissive objects and the sky directly, see
+    /// `RenderSettings::light_sampling`.
+    pub light_sampling: bool,
+
+    pub sky: SkySettings,
 }
 
 impl EngineState {
-    pub fn new(render_state: &RenderState) -> Self {
+    pub fn new(window_size: PhysicalSize<u32>) -> Self {
         let input = Input::new();
         let time = Time::new();
 
-        let mut camera = Camera::new(
-            Vec3::ZERO,
-            Vec3::NEG_Z,
-            45.0,
-            render_state.size,
-            1.0,
-            100.0,
-            10.0,
-        );
+        let mut camera = Camera::new(Vec3::ZERO, Vec3::NEG_Z, 45.0, window_size, 1.0, 100.0, 10.0);
 
         camera.position = Vec3::new(0.0, 0.0, 10.0);
         camera.look_at(Vec3::ZERO);
@@ -45,14 +51,51 @@ impl EngineState {
         let mut object_list = ObjectList::new();
         object_list.random_scene();
 
-        let bounding_volume_hierarchy = BoundingVolumeHierarchy::from_objects(&mut object_list);
+        let acceleration_structure = AccelerationStructure::from_objects(
+            &mut object_list,
+            BvhBuildSettings::default(),
+            true,
+        );
 
         Self {
             input,
             time,
             camera,
             object_list,
-            bounding_volume_hierarchy,
+            acceleration_structure,
+            bvh_layout: BvhLayout::default(),
+            light_sampling: true,
+            sky: SkySettings::default(),
+        }
+    }
+
+    pub fn from_scene(scene: Scene) -> Self {
+        let Scene {
+            camera,
+            mut object_list,
+            sky,
+            render_settings,
+            ..
+        } = scene;
+
+        let acceleration_structure = AccelerationStructure::from_objects(
+            &mut object_list,
+            BvhBuildSettings {
+                gpu_build_threshold: render_settings.gpu_bvh_threshold,
+                ..Default::default()
+            },
+            true,
+        );
+
+        Self {
+            input: Input::new(),
+            time: Time::new(),
+            camera,
+            object_list,
+            acceleration_structure,
+            bvh_layout: render_settings.bvh_layout,
+            light_sampling: render_settings.light_sampling,
+            sky,
         }
     }
 
@@ -61,16 +104,64 @@ impl EngineState {
             self.object_list.random_scene();
         }
 
-        if self.bounding_volume_hierarchy.version != self.object_list.version() {
-            log::info!("Rebuilding BVH");
+        if self.input.keys.just_pressed(BVH_LAYOUT_KEY) {
+            self.bvh_layout = self.bvh_layout.toggled();
+            log::info!("Traversing the BVH in the {:?} layout", self.bvh_layout);
+        }
 
-            self.bounding_volume_hierarchy =
-                BoundingVolumeHierarchy::from_objects(&mut self.object_list);
+        if self.input.keys.just_pressed(LIGHT_SAMPLING_KEY) {
+            self.light_sampling = !self.light_sampling;
+            log::info!("Light sampling enabled: {}", self.light_sampling);
+        }
+
+        self.acceleration_structure.update(&mut self.object_list);
+
+        if self.input.keys.just_pressed(RANDOM_MATERIAL_KEY) {
+            self.randomize_center_material();
+
+            // a mesh that became emissive can't be built on the gpu anymore
+            self.acceleration_structure.update(&mut self.object_list);
+        }
+
+        if self.input.keys.just_pressed(PICK_KEY) {
+            match self.center_hit() {
+                Some(hit) => log::info!(
+                    "Picked {:?} (triangle {:?}, material {}) at distance {}",
+                    hit.object,
+                    hit.primitive,
+                    hit.material,
+                    hit.distance
+                ),
+                None => log::info!("Picked nothing"),
+            }
         }
 
         self.camera.update_position(&self.input, &self.time);
     }
 
+    /// The closest hit of a ray through the center of the screen.
+    fn center_hit(&self) -> Option<Hit> {
+        let ray = Ray::new(self.camera.position, self.camera.forward());
+        self.object_list.raycast(&self.acceleration_structure, ray)
+    }
+
+    /// Replaces the material of the object in the center of the screen with a random one. Every
+    /// other object with the same material changes too.
+    fn randomize_center_material(&mut self) {
+        let Some(hit) = self.center_hit() else {
+            log::info!("No object to change the material of");
+            return;
+        };
+
+        match self.object_list.materials_mut().get_mut(hit.material) {
+            Some(material) => {
+                *material = Material::random();
+                log::info!("Randomized material {} of {:?}", hit.material, hit.object);
+            }
+            None => log::info!("{:?} has no material to change", hit.object),
+        }
+    }
+
     pub fn post_frame_update(&mut self) {
         self.input.update();
         self.time.update();
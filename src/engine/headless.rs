@@ -0,0 +1,150 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    renderer::{
+        buffer::{screen::ScreenBuffer, ObjectBuffers},
+        raytrace::RaytraceRenderContext,
+    },
+    state::scene::SkySettings,
+    util::image::ImageWriteError,
+};
+
+use super::{engine_state::EngineState, render_state::GpuState};
+
+#[derive(Debug)]
+#[allow(unused)]
+pub enum OfflineRenderError {
+    NoAdapter,
+    ImageWriteError(ImageWriteError),
+}
+
+impl From<ImageWriteError> for OfflineRenderError {
+    fn from(value: ImageWriteError) -> Self {
+        Self::ImageWriteError(value)
+    }
+}
+
+impl Display for OfflineRenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for OfflineRenderError {}
+
+#[derive(Debug, Clone)]
+pub struct OfflineRenderSettings {
+    pub size: PhysicalSize<u32>,
+    /// Number of accumulated frames; each frame traces one path per pixel.
+    pub samples: u32,
+    pub output_path: PathBuf,
+    /// Use a software adapter if no hardware adapter is available, e.g. on CI runners without a gpu.
+    pub allow_fallback_adapter: bool,
+}
+
+/// Drives the raytracer without a window, accumulating frames into the color texture of the
+/// raytrace pass so that it can be read back to the cpu.
+pub struct HeadlessRenderer<'a> {
+    pub raytrace_render_context: RaytraceRenderContext<'a>,
+
+    pub screen_buffer: ScreenBuffer,
+    pub object_buffers: ObjectBuffers,
+
+    size: PhysicalSize<u32>,
+    gpu_state: GpuState,
+}
+
+impl<'a> HeadlessRenderer<'a> {
+    pub fn new(gpu_state: &GpuState, size: PhysicalSize<u32>, sky: &SkySettings) -> Self {
+        let screen_buffer = ScreenBuffer::new(gpu_state);
+        let object_buffers = ObjectBuffers::new(gpu_state);
+
+        let raytrace_render_context =
+            RaytraceRenderContext::new(gpu_state, size, &screen_buffer, &object_buffers, sky);
+
+        Self {
+            raytrace_render_context,
+            screen_buffer,
+            object_buffers,
+            size,
+            gpu_state: gpu_state.clone(),
+        }
+    }
+
+    /// Accumulates `samples` frames of the current scene. The camera must stay still between calls,
+    /// otherwise the raytracer discards the accumulated image.
+    pub fn render(&mut self, engine_state: &EngineState, samples: u32) {
+        if self.object_buffers.update(engine_state) {
+            self.raytrace_render_context
+                .on_object_update(&self.object_buffers);
+        }
+
+        let instant = std::time::Instant::now();
+
+        for sample in 0..samples {
+            let mut encoder =
+                self.gpu_state
+                    .device
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                        label: Some("Headless Command Encoder"),
+                    });
+
+            self.screen_buffer.update(self.size, engine_state);
+            self.raytrace_render_context.draw(&mut encoder);
+
+            self.gpu_state
+                .queue
+                .submit(std::iter::once(encoder.finish()));
+
+            // wait for every frame, so we don't queue up thousands of submissions at once
+            self.gpu_state.device.poll(wgpu::Maintain::Wait);
+
+            if (sample + 1) % 64 == 0 {
+                log::info!("Rendered {}/{} samples", sample + 1, samples);
+            }
+        }
+
+        log::info!(
+            "Rendered {} samples in {} seconds",
+            samples,
+            instant.elapsed().as_secs_f64()
+        );
+    }
+
+    /// Reads back the accumulated image as linear rgba floats, row by row from the top.
+    pub fn read_color(&self) -> Vec<f32> {
+        self.raytrace_render_context
+            .color_texture
+            .read_to_bytes()
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect()
+    }
+
+    pub fn write_color<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageWriteError> {
+        crate::util::image::write_image(path, self.size.width, self.size.height, &self.read_color())
+    }
+}
+
+/// Renders the engine state's scene to an image file without opening a window.
+pub fn render(
+    settings: &OfflineRenderSettings,
+    engine_state: &EngineState,
+) -> Result<(), OfflineRenderError> {
+    let gpu_state = pollster::block_on(GpuState::new_headless(settings.allow_fallback_adapter))
+        .ok_or(OfflineRenderError::NoAdapter)?;
+
+    let mut renderer = HeadlessRenderer::new(&gpu_state, settings.size, &engine_state.sky);
+    renderer.render(engine_state, settings.samples);
+    renderer.write_color(&settings.output_path)?;
+
+    log::info!("Wrote render to {:?}", settings.output_path);
+
+    Ok(())
+}
@@ -4,6 +4,8 @@ use engine_state::EngineState;
 use profiler_state::ProfilerState;
 use render_state::RenderState;
 use renderer::Renderer;
+
+use crate::state::scene::Scene;
 use winit::{
     application::ApplicationHandler,
     event::{DeviceEvent, WindowEvent},
@@ -12,6 +14,7 @@ use winit::{
 };
 
 pub mod engine_state;
+pub mod headless;
 pub mod input;
 pub mod profiler_state;
 pub mod render_state;
@@ -34,12 +37,15 @@ pub enum AppState<'a> {
 
 pub struct App<'a> {
     state: AppState<'a>,
+    // the scene to show once the window is created; a random scene is generated if there is none
+    scene: Option<Scene>,
 }
 
 impl<'a> App<'a> {
-    pub fn new() -> Self {
+    pub fn new(scene: Option<Scene>) -> Self {
         Self {
             state: AppState::Uninit,
+            scene,
         }
     }
 }
@@ -58,9 +64,14 @@ impl<'a> ApplicationHandler for App<'a> {
             let window = Arc::new(window);
 
             let render_state = pollster::block_on(RenderState::new(window.clone()));
-            let engine_state = EngineState::new(&render_state);
+            let mut engine_state = match self.scene.take() {
+                Some(scene) => EngineState::from_scene(scene),
+                None => EngineState::new(render_state.size),
+            };
+            engine_state.camera.reconfigure_aspect(render_state.size);
+
             let profiler_state = ProfilerState::new(60);
-            let renderer = Renderer::init(&render_state, &profiler_state);
+            let renderer = Renderer::init(&render_state, &profiler_state, &engine_state.sky);
 
             self.state = AppState::Init {
                 window,
@@ -192,9 +203,9 @@ impl<'a> ApplicationHandler for App<'a> {
     }
 }
 
-pub fn run() {
+pub fn run(scene: Option<Scene>) {
     let event_loop = EventLoop::new().expect("Couldn't create window event loop");
-    let mut app = App::new();
+    let mut app = App::new(scene);
 
     event_loop.run_app(&mut app).unwrap();
 }
@@ -10,6 +10,25 @@ pub const WGPU_FEATURES: wgpu::Features = wgpu::Features::FLOAT32_FILTERABLE
     .union(wgpu::Features::ADDRESS_MODE_CLAMP_TO_ZERO)
     .union(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
 
+async fn request_device(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                required_features: WGPU_FEATURES,
+                required_limits: wgpu::Limits {
+                    max_push_constant_size: 128,
+                    max_storage_buffers_per_shader_stage: 16,
+                    ..Default::default()
+                },
+                memory_hints: wgpu::MemoryHints::Performance,
+            },
+            None,
+        )
+        .await
+        .unwrap()
+}
+
 #[derive(Clone)]
 pub struct GpuState {
     pub instance: Arc<wgpu::Instance>,
@@ -18,6 +37,52 @@ pub struct GpuState {
     pub queue: Arc<wgpu::Queue>,
 }
 
+impl GpuState {
+    /// Creates a gpu state that isn't tied to a window or surface, for offline rendering.
+    ///
+    /// A hardware adapter is preferred; if none is available and `allow_fallback_adapter` is set,
+    /// a software adapter is used instead. Returns `None` if no suitable adapter could be found.
+    pub async fn new_headless(allow_fallback_adapter: bool) -> Option<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+
+        let mut adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: None,
+                force_fallback_adapter: false,
+            })
+            .await;
+
+        if adapter.is_none() && allow_fallback_adapter {
+            log::warn!("No hardware adapter found, falling back to a software adapter");
+
+            adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::HighPerformance,
+                    compatible_surface: None,
+                    force_fallback_adapter: true,
+                })
+                .await;
+        }
+
+        let adapter = adapter?;
+
+        log::info!("Using adapter {:?}", adapter.get_info());
+
+        let (device, queue) = request_device(&adapter).await;
+
+        Some(Self {
+            instance: Arc::new(instance),
+            adapter: Arc::new(adapter),
+            device: Arc::new(device),
+            queue: Arc::new(queue),
+        })
+    }
+}
+
 pub struct RenderState {
     pub surface: wgpu::Surface<'static>,
     pub instance: Arc<wgpu::Instance>,
@@ -49,21 +114,7 @@ impl RenderState {
             .await
             .unwrap();
 
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    required_features: WGPU_FEATURES,
-                    required_limits: wgpu::Limits {
-                        max_push_constant_size: 128,
-                        ..Default::default()
-                    },
-                    memory_hints: wgpu::MemoryHints::Performance,
-                },
-                None,
-            )
-            .await
-            .unwrap();
+        let (device, queue) = request_device(&adapter).await;
 
         let surface_caps = surface.get_capabilities(&adapter);
 
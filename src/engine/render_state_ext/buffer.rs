@@ -103,6 +103,14 @@ impl Buffer {
         }
     }
 
+    /// Writes raw bytes at an offset into the buffer, leaving the rest of it as it is. The offset and
+    /// the number of bytes must be multiples of 4.
+    pub fn write_bytes(&self, offset: usize, bytes: &[u8]) {
+        self.gpu_state
+            .queue
+            .write_buffer(self, offset as u64, bytes);
+    }
+
     pub fn buffer_type(&self) -> BufferType {
         self.ty
     }
@@ -110,6 +118,48 @@ impl Buffer {
     pub fn len(&self) -> usize {
         self.len
     }
+
+    /// Copies the whole buffer back from the gpu, blocking until it's done. The buffer needs the
+    /// `COPY_SRC` usage.
+    pub fn read_to_bytes(&self) -> Vec<u8> {
+        let device = &self.gpu_state.device;
+        let queue = &self.gpu_state.queue;
+
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Buffer Readback Buffer"),
+            size: self.len as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Buffer Readback Command Encoder"),
+        });
+
+        encoder.copy_buffer_to_buffer(&self.buffer, 0, &staging_buffer, 0, self.len as u64);
+
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        let buffer_slice = staging_buffer.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+
+        device.poll(wgpu::Maintain::Wait);
+
+        receiver
+            .recv()
+            .unwrap()
+            .expect("Couldn't map buffer readback buffer");
+
+        let bytes = buffer_slice.get_mapped_range().to_vec();
+
+        staging_buffer.unmap();
+
+        bytes
+    }
 }
 
 impl Deref for Buffer {
@@ -1,8 +1,4 @@
-use std::{
-    fmt::Debug,
-    ops::{Deref, Range},
-    path::Path,
-};
+use std::ops::{Deref, Range};
 
 use crate::{
     engine::render_state::{GpuState, RenderState},
@@ -193,6 +189,86 @@ impl<'a> Texture<'a> {
     pub fn view_dimension(&self) -> wgpu::TextureViewDimension {
         self.ty.view_dimension()
     }
+
+    /// Copies the first mip level and layer of this texture back to the cpu, blocking until the copy
+    /// has finished. The texture must have been created with `COPY_SRC` usage.
+    pub fn read_to_bytes(&self) -> Vec<u8> {
+        let device = &self.gpu_state.device;
+        let queue = &self.gpu_state.queue;
+
+        let width = self.texture_descriptor.size.width;
+        let height = self.texture_descriptor.size.height;
+
+        let bytes_per_pixel = self
+            .texture_descriptor
+            .format
+            .target_pixel_byte_cost()
+            .unwrap();
+
+        // rows in the staging buffer need to be padded to the copy alignment
+        let unpadded_bytes_per_row = width * bytes_per_pixel;
+        let padded_bytes_per_row = unpadded_bytes_per_row
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{} Readback Buffer", self.name)),
+            size: (padded_bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Texture Readback Command Encoder"),
+        });
+
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &staging_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        let buffer_slice = staging_buffer.slice(..);
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+
+        device.poll(wgpu::Maintain::Wait);
+
+        receiver
+            .recv()
+            .unwrap()
+            .expect("Couldn't map texture readback buffer");
+
+        let mut bytes = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+
+        {
+            let mapped = buffer_slice.get_mapped_range();
+
+            for row in mapped.chunks(padded_bytes_per_row as usize) {
+                bytes.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            }
+        }
+
+        staging_buffer.unmap();
+
+        bytes
+    }
 }
 
 impl<'a> Deref for Texture<'a> {
@@ -203,23 +279,14 @@ impl<'a> Deref for Texture<'a> {
     }
 }
 
-pub fn create_cubemap_texture<'a, P: AsRef<Path> + Debug>(
+pub fn create_cubemap_texture<'a>(
     gpu_state: &GpuState,
     name: &'a str,
-    path: P,
+    images: &[Vec<u8>],
     size: u32,
     format: wgpu::TextureFormat,
     usage: wgpu::TextureUsages,
-) -> Result<Texture<'a>, std::io::Error> {
-    let parent_path = std::env::current_dir().unwrap();
-    let path = parent_path.join(&path);
-
-    let faces = ["px", "nx", "py", "ny", "pz", "nz"];
-    let paths = faces.map(|f| path.join(f));
-
-    let images: Result<Vec<Vec<u8>>, _> = paths.into_iter().map(std::fs::read).collect();
-    let images = images?;
-
+) -> Texture<'a> {
     let bytes_per_pixel = format.target_pixel_byte_cost().unwrap();
 
     let texture = Texture::new(
@@ -264,5 +331,5 @@ pub fn create_cubemap_texture<'a, P: AsRef<Path> + Debug>(
         );
     }
 
-    Ok(texture)
+    texture
 }
@@ -1,17 +1,19 @@
 use winit::{dpi::PhysicalSize, keyboard::KeyCode};
 
-use crate::renderer::{
-    bloom::BloomRenderContext,
-    buffer::{
-        bvh::BvhBuffer,
-        object::{AabbListBuffer, PlaneListBuffer, SphereListBuffer, TriangleListBuffer},
-        profiler::{ProfilerBuffer, PROFILER_STEP_SIZE},
-        screen::ScreenBuffer,
+use crate::{
+    renderer::{
+        bloom::BloomRenderContext,
+        buffer::{
+            profiler::{ProfilerBuffer, PROFILER_STEP_SIZE},
+            screen::ScreenBuffer,
+            ObjectBuffers,
+        },
+        debug::DebugRenderContext,
+        final_pass::FinalRenderContext,
+        raytrace::RaytraceRenderContext,
+        screen_quad::ScreenQuad,
     },
-    debug::DebugRenderContext,
-    final_pass::FinalRenderContext,
-    raytrace::RaytraceRenderContext,
-    screen_quad::ScreenQuad,
+    state::scene::SkySettings,
 };
 
 use super::{engine_state::EngineState, profiler_state::ProfilerState, render_state::RenderState};
@@ -29,12 +31,7 @@ pub struct Renderer<'a> {
 
     pub screen_buffer: ScreenBuffer,
 
-    pub object_buffer_version: u32,
-    pub sphere_list_buffer: SphereListBuffer,
-    pub plane_list_buffer: PlaneListBuffer,
-    pub aabb_list_buffer: AabbListBuffer,
-    pub triangle_list_buffer: TriangleListBuffer,
-    pub bvh_buffer: BvhBuffer,
+    pub object_buffers: ObjectBuffers,
 
     pub profiler_buffer: ProfilerBuffer,
 
@@ -42,29 +39,27 @@ pub struct Renderer<'a> {
 }
 
 impl<'a> Renderer<'a> {
-    pub fn init(render_state: &RenderState, profiler_state: &ProfilerState) -> Self {
-        let screen_buffer = ScreenBuffer::new(render_state);
+    pub fn init(
+        render_state: &RenderState,
+        profiler_state: &ProfilerState,
+        sky: &SkySettings,
+    ) -> Self {
+        let gpu_state = render_state.get_gpu_state();
 
-        let object_buffer_version = 0;
-        let sphere_list_buffer = SphereListBuffer::new("Sphere List Buffer", render_state);
-        let plane_list_buffer = PlaneListBuffer::new("Plane List Buffer", render_state);
-        let aabb_list_buffer = AabbListBuffer::new("AABB List Buffer", render_state);
-        let triangle_list_buffer = TriangleListBuffer::new("Triangle List Buffer", render_state);
+        let screen_buffer = ScreenBuffer::new(&gpu_state);
 
-        let bvh_buffer = BvhBuffer::new(render_state);
+        let object_buffers = ObjectBuffers::new(&gpu_state);
 
         let profiler_buffer = ProfilerBuffer::new("Debug Profiler Data Buffer", render_state);
 
         let screen_quad = ScreenQuad::new(render_state);
 
         let raytrace_render_context = RaytraceRenderContext::new(
-            render_state,
+            &gpu_state,
+            render_state.size,
             &screen_buffer,
-            &sphere_list_buffer,
-            &plane_list_buffer,
-            &aabb_list_buffer,
-            &triangle_list_buffer,
-            &bvh_buffer,
+            &object_buffers,
+            sky,
         );
 
         let bloom_render_context = BloomRenderContext::new(
@@ -95,44 +90,20 @@ impl<'a> Renderer<'a> {
             final_render_context,
             _screen_quad: screen_quad,
             screen_buffer,
-            object_buffer_version,
-            sphere_list_buffer,
-            plane_list_buffer,
-            aabb_list_buffer,
-            triangle_list_buffer,
-            bvh_buffer,
+            object_buffers,
             profiler_buffer,
             debug_render_enabled,
         }
     }
 
     pub fn update_object_buffers(&mut self, engine_state: &EngineState) {
-        // If the object buffers don't reflect the current object list, update those
-        if self.object_buffer_version != engine_state.object_list.version() {
-            log::info!("Updating object buffers");
-
-            let update_object_bindings = self.sphere_list_buffer.update(&engine_state.object_list)
-                | self.plane_list_buffer.update(&engine_state.object_list)
-                | self.aabb_list_buffer.update(&engine_state.object_list)
-                | self.triangle_list_buffer.update(&engine_state.object_list)
-                | self
-                    .bvh_buffer
-                    .update(&engine_state.bounding_volume_hierarchy);
-
-            // if updating the object buffers caused a reallocation, update the bindings so the raytracer
-            // has access to the new buffers
-            if update_object_bindings {
-                self.raytrace_render_context.on_object_update(
-                    &self.sphere_list_buffer,
-                    &self.plane_list_buffer,
-                    &self.aabb_list_buffer,
-                    &self.triangle_list_buffer,
-                    &self.bvh_buffer,
-                );
-            }
-
-            // update the version to match
-            self.object_buffer_version = engine_state.object_list.version();
+        let update_object_bindings = self.object_buffers.update(engine_state);
+
+        // if updating the object buffers caused a reallocation, update the bindings so the raytracer
+        // has access to the new buffers
+        if update_object_bindings {
+            self.raytrace_render_context
+                .on_object_update(&self.object_buffers);
         }
     }
 
@@ -182,8 +153,7 @@ impl<'a> Renderer<'a> {
             self.update_profiler_buffer(profiler_state);
         }
 
-        self.screen_buffer
-            .update(render_state, &engine_state.camera);
+        self.screen_buffer.update(render_state.size, engine_state);
 
         self.raytrace_render_context.draw(encoder);
         self.bloom_render_context.draw(encoder);
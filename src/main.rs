@@ -1,5 +1,7 @@
+use clap::Parser;
 use env_logger::Env;
 
+mod cli;
 mod engine;
 mod renderer;
 mod state;
@@ -10,5 +12,8 @@ fn main() {
         .filter_module("goldenrod", log::LevelFilter::Info)
         .init();
 
-    engine::run();
+    if let Err(error) = cli::run(cli::Cli::parse()) {
+        log::error!("{}", error);
+        std::process::exit(1);
+    }
 }
@@ -1,28 +1,68 @@
+use std::ops::Range;
+
 use gpu_bytes::AsStd430;
 use gpu_bytes_derive::{AsStd140, AsStd430};
 
 use crate::{
     engine::{
-        render_state::{GpuState, RenderState},
+        render_state::GpuState,
         render_state_ext::{
             buffer::{Buffer, BufferConfig, BufferData, BufferType},
             RenderStateExt,
         },
     },
-    state::bvh::{BoundingVolumeHierarchy, BvhNode},
+    state::{
+        bvh::{AccelerationStructure, BvhInstance, BvhNode, BvhPrimitive},
+        wide_bvh::{WideBvhNode, NO_WIDE_ROOT},
+    },
 };
 
-use super::MIN_DYNAMIC_BUFFER_CAPACITY;
+use super::{DynamicBuffer, UpdateFromSource, MIN_DYNAMIC_BUFFER_CAPACITY};
+
+/// Where the nodes start in both BVH buffers, after the node count and the top-level root, aligned to
+/// 16 bytes.
+const NODES_OFFSET: usize = 16;
+
+/// Writes the node count, the top-level root and the nodes in `ranges`, leaving the other nodes as
+/// they are. Returns false without writing anything if the buffer can't hold every node.
+fn write_node_ranges<T: AsStd430 + Default>(
+    buffer: &Buffer,
+    tlas_root: u32,
+    nodes: &[T],
+    ranges: &[Range<usize>],
+) -> bool {
+    let node_size = T::default().as_std430().align().as_slice().len();
+
+    if buffer.len() < NODES_OFFSET + node_size * nodes.len() {
+        return false;
+    }
+
+    buffer.write_bytes(0, bytemuck::cast_slice(&[nodes.len() as u32, tlas_root]));
+
+    for range in ranges.iter().filter(|range| !range.is_empty()) {
+        let mut bytes = Vec::with_capacity(node_size * range.len());
+
+        for node in &nodes[range.clone()] {
+            bytes.extend_from_slice(node.as_std430().align().as_slice());
+        }
+
+        buffer.write_bytes(NODES_OFFSET + node_size * range.start, &bytes);
+    }
+
+    true
+}
 
 #[derive(AsStd140, AsStd430)]
 pub struct BvhUniform {
     num_nodes: u32,
+    tlas_root: u32,
     nodes: Vec<BvhNode>,
 }
 
 impl BvhUniform {
-    pub fn update(&mut self, bvh: &BoundingVolumeHierarchy) {
+    pub fn update(&mut self, bvh: &AccelerationStructure) {
         self.num_nodes = bvh.nodes().len() as u32;
+        self.tlas_root = bvh.tlas_root();
 
         self.nodes = Vec::with_capacity(MIN_DYNAMIC_BUFFER_CAPACITY);
         self.nodes.extend_from_slice(bvh.nodes());
@@ -33,11 +73,98 @@ impl Default for BvhUniform {
     fn default() -> Self {
         Self {
             num_nodes: 0,
+            tlas_root: 0,
             nodes: Vec::with_capacity(MIN_DYNAMIC_BUFFER_CAPACITY),
         }
     }
 }
 
+#[derive(AsStd140, AsStd430)]
+pub struct InstanceListUniform {
+    pub num_instances: u32,
+    pub list: Vec<BvhInstance>,
+}
+
+impl UpdateFromSource<AccelerationStructure> for InstanceListUniform {
+    fn update(&mut self, bvh: &AccelerationStructure) {
+        self.num_instances = bvh.instances().len() as u32;
+
+        self.list = Vec::with_capacity(self.list.capacity());
+        self.list.extend_from_slice(bvh.instances());
+    }
+}
+
+impl Default for InstanceListUniform {
+    fn default() -> Self {
+        Self {
+            num_instances: 0,
+            list: Vec::with_capacity(MIN_DYNAMIC_BUFFER_CAPACITY),
+        }
+    }
+}
+
+#[derive(AsStd140, AsStd430)]
+pub struct PrimitiveListUniform {
+    pub num_primitives: u32,
+    pub list: Vec<BvhPrimitive>,
+}
+
+impl UpdateFromSource<AccelerationStructure> for PrimitiveListUniform {
+    fn update(&mut self, bvh: &AccelerationStructure) {
+        self.num_primitives = bvh.primitives().len() as u32;
+
+        self.list = Vec::with_capacity(self.list.capacity());
+        self.list.extend_from_slice(bvh.primitives());
+    }
+}
+
+impl Default for PrimitiveListUniform {
+    fn default() -> Self {
+        Self {
+            num_primitives: 0,
+            list: Vec::with_capacity(MIN_DYNAMIC_BUFFER_CAPACITY),
+        }
+    }
+}
+
+#[derive(AsStd140, AsStd430)]
+pub struct WideBvhUniform {
+    pub num_nodes: u32,
+    pub tlas_root: u32,
+    pub nodes: Vec<WideBvhNode>,
+}
+
+impl UpdateFromSource<AccelerationStructure> for WideBvhUniform {
+    fn update(&mut self, bvh: &AccelerationStructure) {
+        self.num_nodes = bvh.wide_nodes().len() as u32;
+        self.tlas_root = bvh.wide_tlas_root();
+
+        self.nodes = Vec::with_capacity(self.nodes.capacity());
+        self.nodes.extend_from_slice(bvh.wide_nodes());
+    }
+}
+
+impl Default for WideBvhUniform {
+    fn default() -> Self {
+        Self {
+            num_nodes: 0,
+            tlas_root: NO_WIDE_ROOT,
+            nodes: Vec::with_capacity(MIN_DYNAMIC_BUFFER_CAPACITY),
+        }
+    }
+}
+
+pub type InstanceListBuffer = DynamicBuffer<InstanceListUniform, AccelerationStructure>;
+pub type PrimitiveListBuffer = DynamicBuffer<PrimitiveListUniform, AccelerationStructure>;
+pub type WideBvhBuffer = DynamicBuffer<WideBvhUniform, AccelerationStructure>;
+
+impl WideBvhBuffer {
+    /// Writes only the wide nodes in `ranges`, see `BvhBuffer::update_ranges`.
+    pub fn update_ranges(&mut self, bvh: &AccelerationStructure, ranges: &[Range<usize>]) -> bool {
+        write_node_ranges(&self.buffer, bvh.wide_tlas_root(), bvh.wide_nodes(), ranges)
+    }
+}
+
 pub struct BvhBuffer {
     pub data: BvhUniform,
     pub buffer: Buffer,
@@ -45,8 +172,8 @@ pub struct BvhBuffer {
 }
 
 impl BvhBuffer {
-    pub fn new(render_state: &RenderState) -> Self {
-        let gpu_state = render_state.get_gpu_state();
+    pub fn new(gpu_state: &impl RenderStateExt) -> Self {
+        let gpu_state = gpu_state.as_gpu_state();
 
         let data = BvhUniform::default();
         let buffer_size = data.as_std430().align().as_slice().len();
@@ -59,14 +186,14 @@ impl BvhBuffer {
                 BufferConfig {
                     data: BufferData::Uninit(buffer_size),
                     ty: BufferType::Storage,
-                    usage: wgpu::BufferUsages::COPY_DST,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
                 },
             ),
             gpu_state,
         }
     }
 
-    pub fn update(&mut self, bvh: &BoundingVolumeHierarchy) -> bool {
+    pub fn update(&mut self, bvh: &AccelerationStructure) -> bool {
         self.data.update(bvh);
 
         let mut data = self.data.as_std430();
@@ -83,7 +210,7 @@ impl BvhBuffer {
                 BufferConfig {
                     data: BufferData::Init(data.as_slice()),
                     ty: BufferType::Storage,
-                    usage: wgpu::BufferUsages::COPY_DST,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
                 },
             );
 
@@ -94,4 +221,11 @@ impl BvhBuffer {
             false
         }
     }
+
+    /// Writes the node count, the top-level root and only the nodes in `ranges`, for when the other
+    /// nodes haven't changed since the last upload. Returns false without writing anything if the
+    /// buffer is too small for the nodes, in which case it has to be updated as a whole.
+    pub fn update_ranges(&mut self, bvh: &AccelerationStructure, ranges: &[Range<usize>]) -> bool {
+        write_node_ranges(&self.buffer, bvh.tlas_root(), bvh.nodes(), ranges)
+    }
 }
@@ -0,0 +1,159 @@
+use gpu_bytes_derive::{AsStd140, AsStd430};
+
+use crate::{
+    engine::{
+        render_state::GpuState,
+        render_state_ext::{
+            texture::{Texture, TextureConfig, TextureType},
+            RenderStateExt,
+        },
+    },
+    state::{
+        grid::{GpuGridVolume, GridList},
+        object::ObjectList,
+    },
+};
+
+use super::{DynamicBuffer, UpdateFromSource, MIN_DYNAMIC_BUFFER_CAPACITY};
+
+#[derive(AsStd140, AsStd430)]
+pub struct GridVolumeListUniform {
+    pub num_grid_volumes: u32,
+    pub list: Vec<GpuGridVolume>,
+}
+
+impl UpdateFromSource<ObjectList> for GridVolumeListUniform {
+    fn update(&mut self, object_list: &ObjectList) {
+        self.num_grid_volumes = object_list.grid_volumes().len() as u32;
+
+        self.list = Vec::with_capacity(self.list.capacity());
+        self.list.extend(
+            object_list
+                .grid_volumes()
+                .iter()
+                .map(|volume| volume.gpu_grid_volume(object_list.grids())),
+        );
+    }
+}
+
+impl Default for GridVolumeListUniform {
+    fn default() -> Self {
+        Self {
+            num_grid_volumes: 0,
+            list: Vec::with_capacity(MIN_DYNAMIC_BUFFER_CAPACITY),
+        }
+    }
+}
+
+pub type GridVolumeListBuffer = DynamicBuffer<GridVolumeListUniform, ObjectList>;
+
+/// The object list's voxel grids, stacked along z in one 3d texture, with the density of every voxel
+/// in the red channel and its emission in the green channel.
+pub struct GridAtlas {
+    pub version: u32,
+    pub texture: Texture<'static>,
+    gpu_state: GpuState,
+}
+
+impl GridAtlas {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg32Float;
+
+    pub fn new(gpu_state: &impl RenderStateExt) -> Self {
+        let gpu_state = gpu_state.as_gpu_state();
+
+        Self {
+            version: 0,
+            // textures can't be empty, so this one is a placeholder until grids are added
+            texture: Self::create_texture(&gpu_state, 1, 1, 1),
+            gpu_state,
+        }
+    }
+
+    fn create_texture(
+        gpu_state: &GpuState,
+        width: u32,
+        height: u32,
+        depth: u32,
+    ) -> Texture<'static> {
+        Texture::new(
+            gpu_state,
+            "Grid Atlas",
+            TextureConfig {
+                ty: TextureType::Texture3d,
+                format: Self::FORMAT,
+                width,
+                height,
+                depth,
+                mips: 1,
+                address_mode: wgpu::AddressMode::ClampToEdge,
+                filter_mode: wgpu::FilterMode::Linear,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            },
+        )
+    }
+
+    /// Uploads the grids if they changed since the last update. Returns true if the texture was
+    /// recreated, which is always the case when anything changed.
+    pub fn update(&mut self, grids: &GridList) -> bool {
+        if self.version == grids.version() {
+            return false;
+        }
+
+        log::info!("Uploading {} voxel grids", grids.grids().len());
+
+        self.version = grids.version();
+
+        if grids.is_empty() {
+            self.texture = Self::create_texture(&self.gpu_state, 1, 1, 1);
+            return true;
+        }
+
+        let width = grids.grids().iter().map(|grid| grid.resolution.x).max();
+        let height = grids.grids().iter().map(|grid| grid.resolution.y).max();
+
+        self.texture = Self::create_texture(
+            &self.gpu_state,
+            width.unwrap(),
+            height.unwrap(),
+            grids.depth(),
+        );
+
+        for (index, grid) in grids.grids().iter().enumerate() {
+            // density and emission are interleaved, and grids without emission don't glow
+            let texels: Vec<f32> = grid
+                .density
+                .iter()
+                .enumerate()
+                .flat_map(|(voxel, &density)| {
+                    [density, grid.emission.get(voxel).copied().unwrap_or(0.0)]
+                })
+                .collect();
+
+            self.gpu_state.queue.write_texture(
+                wgpu::ImageCopyTextureBase {
+                    texture: &self.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: grids.offset(index as u32),
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                bytemuck::cast_slice(&texels),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(grid.resolution.x * 8),
+                    rows_per_image: Some(grid.resolution.y),
+                },
+                wgpu::Extent3d {
+                    width: grid.resolution.x,
+                    height: grid.resolution.y,
+                    depth_or_array_layers: grid.resolution.z,
+                },
+            );
+        }
+
+        true
+    }
+}
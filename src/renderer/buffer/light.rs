@@ -0,0 +1,66 @@
+use gpu_bytes_derive::{AsStd140, AsStd430};
+
+use crate::state::{
+    light::{Emitter, EmitterList, GpuLight},
+    object::ObjectList,
+};
+
+use super::{DynamicBuffer, UpdateFromSource, MIN_DYNAMIC_BUFFER_CAPACITY};
+
+#[derive(AsStd140, AsStd430)]
+pub struct EmitterListUniform {
+    pub num_emitters: u32,
+    pub total_power: f32,
+    pub list: Vec<Emitter>,
+}
+
+impl UpdateFromSource<ObjectList> for EmitterListUniform {
+    fn update(&mut self, object_list: &ObjectList) {
+        let emitter_list = EmitterList::new(object_list);
+
+        self.num_emitters = emitter_list.emitters().len() as u32;
+        self.total_power = emitter_list.total_power();
+
+        self.list = Vec::with_capacity(self.list.capacity());
+        self.list.extend_from_slice(emitter_list.emitters());
+    }
+}
+
+impl Default for EmitterListUniform {
+    fn default() -> Self {
+        Self {
+            num_emitters: 0,
+            total_power: 0.0,
+            list: Vec::with_capacity(MIN_DYNAMIC_BUFFER_CAPACITY),
+        }
+    }
+}
+
+pub type EmitterListBuffer = DynamicBuffer<EmitterListUniform, ObjectList>;
+
+#[derive(AsStd140, AsStd430)]
+pub struct LightListUniform {
+    pub num_lights: u32,
+    pub list: Vec<GpuLight>,
+}
+
+impl UpdateFromSource<ObjectList> for LightListUniform {
+    fn update(&mut self, object_list: &ObjectList) {
+        self.num_lights = object_list.lights().len() as u32;
+
+        self.list = Vec::with_capacity(self.list.capacity());
+        self.list
+            .extend(object_list.lights().iter().map(|light| light.gpu_light()));
+    }
+}
+
+impl Default for LightListUniform {
+    fn default() -> Self {
+        Self {
+            num_lights: 0,
+            list: Vec::with_capacity(MIN_DYNAMIC_BUFFER_CAPACITY),
+        }
+    }
+}
+
+pub type LightListBuffer = DynamicBuffer<LightListUniform, ObjectList>;
@@ -0,0 +1,31 @@
+use gpu_bytes_derive::{AsStd140, AsStd430};
+
+use crate::state::material::{Material, MaterialList};
+
+use super::{DynamicBuffer, UpdateFromSource, MIN_DYNAMIC_BUFFER_CAPACITY};
+
+#[derive(AsStd140, AsStd430)]
+pub struct MaterialListUniform {
+    pub num_materials: u32,
+    pub list: Vec<Material>,
+}
+
+impl UpdateFromSource<MaterialList> for MaterialListUniform {
+    fn update(&mut self, material_list: &MaterialList) {
+        self.num_materials = material_list.materials().len() as u32;
+
+        self.list = Vec::with_capacity(self.list.capacity());
+        self.list.extend_from_slice(material_list.materials());
+    }
+}
+
+impl Default for MaterialListUniform {
+    fn default() -> Self {
+        Self {
+            num_materials: 0,
+            list: Vec::with_capacity(MIN_DYNAMIC_BUFFER_CAPACITY),
+        }
+    }
+}
+
+pub type MaterialListBuffer = DynamicBuffer<MaterialListUniform, MaterialList>;
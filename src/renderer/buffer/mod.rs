@@ -1,19 +1,42 @@
-use std::marker::PhantomData;
+use std::{marker::PhantomData, ops::Range};
 
 use gpu_bytes::{AsStd140, AsStd430};
 
-use crate::engine::{
-    render_state::GpuState,
-    render_state_ext::{
-        buffer::{Buffer, BufferConfig, BufferData, BufferType},
-        RenderStateExt,
+use crate::{
+    engine::{
+        engine_state::EngineState,
+        render_state::GpuState,
+        render_state_ext::{
+            buffer::{Buffer, BufferConfig, BufferData, BufferType},
+            RenderStateExt,
+        },
+    },
+    state::{
+        bvh::{AccelerationStructure, GpuBlas},
+        object::ObjectList,
     },
 };
 
+use super::lbvh::LbvhBuilder;
+
+use bvh::{BvhBuffer, InstanceListBuffer, PrimitiveListBuffer, WideBvhBuffer};
+use grid::{GridAtlas, GridVolumeListBuffer};
+use light::{EmitterListBuffer, LightListBuffer};
+use material::MaterialListBuffer;
+use object::{
+    AabbListBuffer, PlaneListBuffer, SphereListBuffer, TriangleListBuffer, VertexListBuffer,
+};
+use texture::MaterialTextureArray;
+
 pub mod bvh;
+pub mod grid;
+pub mod light;
+pub mod material;
 pub mod object;
 pub mod profiler;
 pub mod screen;
+pub mod sky;
+pub mod texture;
 
 /// Runtime-size arrays in storage buffers will allocate at least this many elements to avoid allocating
 /// buffers on the gpu with zero size.
@@ -51,7 +74,7 @@ where
                 BufferConfig {
                     data: BufferData::Uninit(buffer_size),
                     ty: BufferType::Storage,
-                    usage: wgpu::BufferUsages::COPY_DST,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
                 },
             ),
             gpu_state: gpu_state.as_gpu_state(),
@@ -77,7 +100,7 @@ where
                 BufferConfig {
                     data: BufferData::Init(data.as_slice()),
                     ty: BufferType::Storage,
-                    usage: wgpu::BufferUsages::COPY_DST,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
                 },
             );
 
@@ -90,3 +113,321 @@ where
         }
     }
 }
+
+/// The versions of the parts of an object list that are uploaded separately.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectVersions {
+    pub objects: u32,
+    pub meshes: u32,
+    pub vertices: u32,
+    pub materials: u32,
+}
+
+impl ObjectVersions {
+    pub fn of(object_list: &ObjectList) -> Self {
+        Self {
+            objects: object_list.version(),
+            meshes: object_list.mesh_version(),
+            vertices: object_list.vertex_version(),
+            materials: object_list.materials().version(),
+        }
+    }
+
+    /// What changed between these versions and the current ones.
+    pub fn changes(&self, current: &Self) -> ObjectChanges {
+        ObjectChanges {
+            meshes: self.meshes != current.meshes,
+            vertices: self.vertices != current.vertices,
+            objects: self.objects != current.objects,
+            materials: self.materials != current.materials,
+        }
+    }
+}
+
+/// What has to be uploaded again since the last update. Changing meshes or vertices changes the
+/// object version too, but editing materials doesn't.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectChanges {
+    /// Meshes were loaded or their triangles changed, so the triangles, the vertices and every BVH
+    /// node are uploaded, and the BVHs built on the gpu are built again.
+    pub meshes: bool,
+    /// Vertices moved, refitting the bottom-level BVHs of their meshes. The vertices and only the
+    /// nodes of those meshes are uploaded, and only the ones built on the gpu are built again.
+    pub vertices: bool,
+    /// Objects or mesh instances were added, removed or moved. Only the top-level BVH changes.
+    pub objects: bool,
+    /// Only the material buffer and the emitters depend on the materials.
+    pub materials: bool,
+}
+
+/// All the buffers that hold scene geometry, materials, material textures and voxel grids, kept in sync
+/// with the engine state's object list.
+pub struct ObjectBuffers {
+    /// The versions of the object list when it was last uploaded. Materials, meshes and the objects
+    /// placing them are versioned separately, so that moving objects or editing materials doesn't
+    /// upload the meshes again.
+    pub versions: ObjectVersions,
+    pub sphere_list_buffer: SphereListBuffer,
+    pub plane_list_buffer: PlaneListBuffer,
+    pub aabb_list_buffer: AabbListBuffer,
+    pub triangle_list_buffer: TriangleListBuffer,
+    pub vertex_list_buffer: VertexListBuffer,
+    pub bvh_buffer: BvhBuffer,
+    pub wide_bvh_buffer: WideBvhBuffer,
+    pub instance_list_buffer: InstanceListBuffer,
+    pub primitive_list_buffer: PrimitiveListBuffer,
+    pub material_list_buffer: MaterialListBuffer,
+    /// Depends on both the objects and their materials.
+    pub emitter_list_buffer: EmitterListBuffer,
+    pub light_list_buffer: LightListBuffer,
+    pub material_texture_array: MaterialTextureArray,
+    pub grid_volume_list_buffer: GridVolumeListBuffer,
+    pub grid_atlas: GridAtlas,
+    /// Builds the BVHs of the meshes the acceleration structure leaves to the gpu.
+    pub lbvh_builder: LbvhBuilder,
+}
+
+impl ObjectBuffers {
+    pub fn new(gpu_state: &impl RenderStateExt) -> Self {
+        let triangle_list_buffer =
+            TriangleListBuffer::new("Triangle List Buffer", gpu_state.as_gpu_state());
+        let vertex_list_buffer =
+            VertexListBuffer::new("Vertex List Buffer", gpu_state.as_gpu_state());
+        let bvh_buffer = BvhBuffer::new(gpu_state);
+
+        let lbvh_builder = LbvhBuilder::new(
+            gpu_state,
+            &triangle_list_buffer.buffer,
+            &vertex_list_buffer.buffer,
+            &bvh_buffer.buffer,
+        );
+
+        Self {
+            versions: ObjectVersions::default(),
+            sphere_list_buffer: SphereListBuffer::new(
+                "Sphere List Buffer",
+                gpu_state.as_gpu_state(),
+            ),
+            plane_list_buffer: PlaneListBuffer::new("Plane List Buffer", gpu_state.as_gpu_state()),
+            aabb_list_buffer: AabbListBuffer::new("AABB List Buffer", gpu_state.as_gpu_state()),
+            triangle_list_buffer,
+            vertex_list_buffer,
+            bvh_buffer,
+            wide_bvh_buffer: WideBvhBuffer::new("Wide BVH Buffer", gpu_state.as_gpu_state()),
+            instance_list_buffer: InstanceListBuffer::new(
+                "Instance List Buffer",
+                gpu_state.as_gpu_state(),
+            ),
+            primitive_list_buffer: PrimitiveListBuffer::new(
+                "Primitive List Buffer",
+                gpu_state.as_gpu_state(),
+            ),
+            material_list_buffer: MaterialListBuffer::new(
+                "Material List Buffer",
+                gpu_state.as_gpu_state(),
+            ),
+            emitter_list_buffer: EmitterListBuffer::new(
+                "Emitter List Buffer",
+                gpu_state.as_gpu_state(),
+            ),
+            light_list_buffer: LightListBuffer::new("Light List Buffer", gpu_state.as_gpu_state()),
+            material_texture_array: MaterialTextureArray::new(gpu_state),
+            grid_volume_list_buffer: GridVolumeListBuffer::new(
+                "Grid Volume List Buffer",
+                gpu_state.as_gpu_state(),
+            ),
+            grid_atlas: GridAtlas::new(gpu_state),
+            lbvh_builder,
+        }
+    }
+
+    /// Uploads the object list and its materials if they changed since the last update. Returns true
+    /// if any of the buffers were reallocated, in which case bindings referencing them need to be
+    /// recreated.
+    pub fn update(&mut self, engine_state: &EngineState) -> bool {
+        self.update_from(
+            &engine_state.object_list,
+            &engine_state.acceleration_structure,
+        )
+    }
+
+    /// Uploads the object list and the acceleration structure built over it, see `update`.
+    pub fn update_from(
+        &mut self,
+        object_list: &ObjectList,
+        acceleration_structure: &AccelerationStructure,
+    ) -> bool {
+        let versions = ObjectVersions::of(object_list);
+        let changes = self.versions.changes(&versions);
+
+        let mut reallocated = false;
+
+        if changes.objects || changes.materials {
+            reallocated |= self.emitter_list_buffer.update(object_list);
+        }
+
+        if changes.objects {
+            log::info!("Updating object buffers");
+
+            // refitting doesn't reorder triangles, so they only change with the meshes
+            if changes.meshes {
+                reallocated |= self.triangle_list_buffer.update(object_list);
+            }
+
+            if changes.meshes || changes.vertices {
+                reallocated |= self.vertex_list_buffer.update(object_list);
+            }
+
+            reallocated |= self.sphere_list_buffer.update(object_list)
+                | self.plane_list_buffer.update(object_list)
+                | self.aabb_list_buffer.update(object_list)
+                | self.instance_list_buffer.update(acceleration_structure)
+                | self.primitive_list_buffer.update(acceleration_structure)
+                | self.light_list_buffer.update(object_list)
+                | self.material_texture_array.update(object_list.textures())
+                | self.grid_volume_list_buffer.update(object_list)
+                | self.grid_atlas.update(object_list.grids())
+                | self.update_bvh(object_list, acceleration_structure, changes);
+        }
+
+        if changes.materials {
+            log::info!("Updating material buffer");
+
+            reallocated |= self.material_list_buffer.update(object_list.materials());
+        }
+
+        self.versions = versions;
+
+        reallocated
+    }
+
+    /// Uploads the nodes of the acceleration structure that changed, and builds the BVHs that are
+    /// built on the gpu if their meshes moved or their nodes were overwritten. Returns true if a
+    /// buffer was reallocated.
+    fn update_bvh(
+        &mut self,
+        object_list: &ObjectList,
+        acceleration_structure: &AccelerationStructure,
+        changes: ObjectChanges,
+    ) -> bool {
+        let mut reallocated = false;
+
+        let uploaded_vertex_version = self.versions.vertices;
+        let moved =
+            |mesh: u32| object_list.meshes()[mesh as usize].moved_since(uploaded_vertex_version);
+
+        let node_ranges: Option<Vec<Range<usize>>> = match changes {
+            ObjectChanges { meshes: true, .. } => None,
+            // the bottom-level BVHs of the meshes that moved were refitted, except the ones built on
+            // the gpu, which are built again below
+            ObjectChanges { vertices: true, .. } => Some(
+                (0..object_list.meshes().len() as u32)
+                    .filter(|&mesh| moved(mesh) && !acceleration_structure.builds_on_gpu(mesh))
+                    .map(|mesh| acceleration_structure.blas_node_range(mesh))
+                    .chain([acceleration_structure.tlas_node_range()])
+                    .collect(),
+            ),
+            // when only objects moved, the bottom-level BVHs stay the same
+            _ => Some(vec![acceleration_structure.tlas_node_range()]),
+        };
+
+        let nodes_written = node_ranges.is_some_and(|ranges| {
+            self.bvh_buffer
+                .update_ranges(acceleration_structure, &ranges)
+        });
+
+        if !nodes_written {
+            reallocated |= self.bvh_buffer.update(acceleration_structure);
+        }
+
+        // refitting collapses the wide bottom-level BVHs again, which can change how many nodes
+        // they have, so only the wide top-level BVH can be written on its own
+        let wide_nodes_written = !changes.meshes
+            && !changes.vertices
+            && self.wide_bvh_buffer.update_ranges(
+                acceleration_structure,
+                &[acceleration_structure.wide_tlas_node_range()],
+            );
+
+        if !wide_nodes_written {
+            reallocated |= self.wide_bvh_buffer.update(acceleration_structure);
+        }
+
+        // writing every node replaces the nodes of gpu-built meshes with placeholders, otherwise only
+        // the meshes that moved are out of date
+        let gpu_blas: Vec<GpuBlas> = acceleration_structure
+            .gpu_blas()
+            .iter()
+            .filter(|blas| !nodes_written || moved(blas.mesh))
+            .copied()
+            .collect();
+
+        self.lbvh_builder.build(
+            &gpu_blas,
+            &self.triangle_list_buffer.buffer,
+            &self.vertex_list_buffer.buffer,
+            &self.bvh_buffer.buffer,
+        );
+
+        reallocated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use crate::state::{material::Material, object::Sphere};
+
+    use super::*;
+
+    #[test]
+    fn editing_a_material_only_changes_materials() {
+        let mut object_list = ObjectList::new();
+        let material = object_list.materials_mut().push(Material::default());
+        object_list.push_sphere(Sphere::new(Vec3::ZERO, 1.0, material));
+
+        let uploaded = ObjectVersions::of(&object_list);
+
+        object_list
+            .materials_mut()
+            .get_mut(material)
+            .unwrap()
+            .albedo = Vec3::new(1.0, 0.0, 0.0);
+
+        assert_eq!(
+            uploaded.changes(&ObjectVersions::of(&object_list)),
+            ObjectChanges {
+                materials: true,
+                ..Default::default()
+            }
+        );
+
+        // a material that doesn't exist can't be edited, so nothing changes
+        let uploaded = ObjectVersions::of(&object_list);
+
+        assert!(object_list.materials_mut().get_mut(material + 1).is_none());
+        assert_eq!(
+            uploaded.changes(&ObjectVersions::of(&object_list)),
+            ObjectChanges::default()
+        );
+    }
+
+    #[test]
+    fn moving_an_object_doesnt_change_meshes_or_materials() {
+        let mut object_list = ObjectList::new();
+        object_list.push_sphere(Sphere::new(Vec3::ZERO, 1.0, 0));
+
+        let uploaded = ObjectVersions::of(&object_list);
+
+        object_list.spheres_mut()[0] = Sphere::new(Vec3::new(0.0, 2.0, 0.0), 1.0, 0);
+
+        assert_eq!(
+            uploaded.changes(&ObjectVersions::of(&object_list)),
+            ObjectChanges {
+                objects: true,
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use gpu_bytes_derive::{AsStd140, AsStd430};
 
-use crate::state::object::{Aabb, ObjectList, Plane, Sphere, Triangle};
+use crate::state::object::{Aabb, ObjectList, Plane, Sphere, Triangle, Vertex};
 
 use super::{DynamicBuffer, UpdateFromSource, MIN_DYNAMIC_BUFFER_CAPACITY};
 
@@ -100,7 +100,32 @@ impl Default for TriangleListUniform {
     }
 }
 
+#[derive(AsStd140, AsStd430)]
+pub struct VertexListUniform {
+    pub num_vertices: u32,
+    pub list: Vec<Vertex>,
+}
+
+impl UpdateFromSource<ObjectList> for VertexListUniform {
+    fn update(&mut self, object_list: &ObjectList) {
+        self.num_vertices = object_list.vertices().len() as u32;
+
+        self.list = Vec::with_capacity(self.list.capacity());
+        self.list.extend_from_slice(object_list.vertices());
+    }
+}
+
+impl Default for VertexListUniform {
+    fn default() -> Self {
+        Self {
+            num_vertices: 0,
+            list: Vec::with_capacity(MIN_DYNAMIC_BUFFER_CAPACITY),
+        }
+    }
+}
+
 pub type SphereListBuffer = DynamicBuffer<SphereListUniform, ObjectList>;
 pub type PlaneListBuffer = DynamicBuffer<PlaneListUniform, ObjectList>;
 pub type AabbListBuffer = DynamicBuffer<AabbListUniform, ObjectList>;
 pub type TriangleListBuffer = DynamicBuffer<TriangleListUniform, ObjectList>;
+pub type VertexListBuffer = DynamicBuffer<VertexListUniform, ObjectList>;
@@ -1,16 +1,17 @@
 use glam::{Mat4, Vec3};
 use gpu_bytes::AsStd430;
 use gpu_bytes_derive::{AsStd140, AsStd430};
+use winit::dpi::PhysicalSize;
 
 use crate::{
     engine::{
-        render_state::RenderState,
+        engine_state::EngineState,
         render_state_ext::{
             buffer::{Buffer, BufferConfig, BufferData, BufferType},
             RenderStateExt,
         },
     },
-    state::camera::Camera,
+    state::{camera::Camera, scene::SkyEnvironment},
 };
 
 #[derive(AsStd140, AsStd430, Default)]
@@ -67,13 +68,38 @@ pub struct ViewUniform {
     width: u32,
     height: u32,
     frame_count: u32,
+    bvh_layout: u32,
+    light_sampling: u32,
+    sky_rotation: f32,
+    sky_intensity: f32,
+    sky_visible: u32,
+    physical_sky: u32,
+    sun_direction: Vec3,
+    sun_cone: f32,
+    turbidity: f32,
+    ground_albedo: f32,
 }
 
 impl ViewUniform {
-    pub fn update(&mut self, render_state: &RenderState) {
-        self.width = render_state.size.width;
-        self.height = render_state.size.height;
+    pub fn update(&mut self, size: PhysicalSize<u32>, engine_state: &EngineState) {
+        self.width = size.width;
+        self.height = size.height;
         self.frame_count = self.frame_count.wrapping_add(1);
+        self.bvh_layout = engine_state.bvh_layout as u32;
+        self.light_sampling = engine_state.light_sampling as u32;
+        self.sky_rotation = engine_state.sky.rotation.to_radians();
+        self.sky_intensity = engine_state.sky.intensity;
+        self.sky_visible = engine_state.sky.visible as u32;
+
+        if let SkyEnvironment::Physical(physical_sky) = &engine_state.sky.environment {
+            self.physical_sky = 1;
+            self.sun_direction = physical_sky.sun_direction();
+            self.sun_cone = physical_sky.sun_cone();
+            self.turbidity = physical_sky.turbidity;
+            self.ground_albedo = physical_sky.ground_albedo;
+        } else {
+            self.physical_sky = 0;
+        }
     }
 }
 
@@ -84,9 +110,9 @@ pub struct ScreenUniform {
 }
 
 impl ScreenUniform {
-    pub fn update(&mut self, camera: &Camera, render_state: &RenderState) {
-        self.camera.update(camera);
-        self.view.update(render_state);
+    pub fn update(&mut self, engine_state: &EngineState, size: PhysicalSize<u32>) {
+        self.camera.update(&engine_state.camera);
+        self.view.update(size, engine_state);
     }
 }
 
@@ -96,7 +122,7 @@ pub struct ScreenBuffer {
 }
 
 impl ScreenBuffer {
-    pub fn new(render_state: &RenderState) -> Self {
+    pub fn new(gpu_state: &impl RenderStateExt) -> Self {
         let data = ScreenUniform::default();
         let mut std430 = data.as_std430();
         let buffer_size = std430.align().as_slice().len();
@@ -104,7 +130,7 @@ impl ScreenBuffer {
         Self {
             data,
             buffer: Buffer::new(
-                &render_state,
+                gpu_state,
                 "Screen Uniforms Buffer",
                 BufferConfig {
                     data: BufferData::Uninit(buffer_size),
@@ -115,8 +141,8 @@ impl ScreenBuffer {
         }
     }
 
-    pub fn update(&mut self, render_state: &RenderState, camera: &Camera) {
-        self.data.update(camera, render_state);
+    pub fn update(&mut self, size: PhysicalSize<u32>, engine_state: &EngineState) {
+        self.data.update(engine_state, size);
         self.buffer.write(&self.data);
     }
 }
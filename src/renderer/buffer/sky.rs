@@ -0,0 +1,33 @@
+use gpu_bytes_derive::{AsStd140, AsStd430};
+
+use crate::state::sky::SkyDistribution;
+
+use super::{DynamicBuffer, UpdateFromSource, MIN_DYNAMIC_BUFFER_CAPACITY};
+
+#[derive(AsStd140, AsStd430)]
+pub struct SkyDistributionUniform {
+    pub size: u32,
+    /// The marginal cdf, followed by the conditional cdfs of every row.
+    pub cdf: Vec<f32>,
+}
+
+impl UpdateFromSource<SkyDistribution> for SkyDistributionUniform {
+    fn update(&mut self, distribution: &SkyDistribution) {
+        self.size = distribution.size() as u32;
+
+        self.cdf = Vec::with_capacity(self.cdf.capacity());
+        self.cdf.extend_from_slice(distribution.marginal_cdf());
+        self.cdf.extend_from_slice(distribution.conditional_cdf());
+    }
+}
+
+impl Default for SkyDistributionUniform {
+    fn default() -> Self {
+        Self {
+            size: 0,
+            cdf: Vec::with_capacity(MIN_DYNAMIC_BUFFER_CAPACITY),
+        }
+    }
+}
+
+pub type SkyDistributionBuffer = DynamicBuffer<SkyDistributionUniform, SkyDistribution>;
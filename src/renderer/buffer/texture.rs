@@ -0,0 +1,102 @@
+use crate::{
+    engine::{
+        render_state::GpuState,
+        render_state_ext::{
+            texture::{Texture, TextureConfig, TextureType},
+            RenderStateExt,
+        },
+    },
+    state::texture::TextureList,
+};
+
+/// The object list's material textures, uploaded as one texture array with a layer per texture.
+pub struct MaterialTextureArray {
+    pub version: u32,
+    pub texture: Texture<'static>,
+    gpu_state: GpuState,
+}
+
+impl MaterialTextureArray {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    pub fn new(gpu_state: &impl RenderStateExt) -> Self {
+        let gpu_state = gpu_state.as_gpu_state();
+
+        Self {
+            version: 0,
+            // texture arrays can't be empty, so this one is a placeholder until textures are added
+            texture: Self::create_texture(&gpu_state, 1, 1),
+            gpu_state,
+        }
+    }
+
+    fn create_texture(gpu_state: &GpuState, size: u32, layers: u32) -> Texture<'static> {
+        Texture::new(
+            gpu_state,
+            "Material Texture Array",
+            TextureConfig {
+                ty: TextureType::Texture2dArray,
+                format: Self::FORMAT,
+                width: size,
+                height: size,
+                depth: layers,
+                mips: 1,
+                address_mode: wgpu::AddressMode::Repeat,
+                filter_mode: wgpu::FilterMode::Linear,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            },
+        )
+    }
+
+    pub fn layers(&self) -> u32 {
+        self.texture.depth_or_array_layers()
+    }
+
+    /// Uploads the textures if they changed since the last update. Returns true if the texture was
+    /// recreated, which is always the case when anything changed.
+    pub fn update(&mut self, textures: &TextureList) -> bool {
+        if self.version == textures.version() {
+            return false;
+        }
+
+        log::info!("Uploading {} material textures", textures.len());
+
+        self.version = textures.version();
+
+        if textures.is_empty() {
+            self.texture = Self::create_texture(&self.gpu_state, 1, 1);
+            return true;
+        }
+
+        let size = TextureList::LAYER_SIZE;
+        self.texture = Self::create_texture(&self.gpu_state, size, textures.len() as u32);
+
+        for (index, layer) in textures.layers().iter().enumerate() {
+            self.gpu_state.queue.write_texture(
+                wgpu::ImageCopyTextureBase {
+                    texture: &self.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: index as u32,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                layer.as_raw(),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(size * 4),
+                    rows_per_image: None,
+                },
+                wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        true
+    }
+}
@@ -2,5 +2,7 @@ pub mod bloom;
 pub mod buffer;
 pub mod debug;
 pub mod final_pass;
+pub mod lbvh;
 pub mod raytrace;
 pub mod screen_quad;
+pub mod sky;
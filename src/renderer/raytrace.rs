@@ -1,22 +1,27 @@
 use glam::UVec3;
 use winit::dpi::PhysicalSize;
 
-use crate::engine::{
-    render_state::{GpuState, RenderState},
-    render_state_ext::{
-        binding::{Binding, BindingData, BindingEntry},
-        pass::ComputePass,
-        pipeline::{ComputePipelineConfig, PipelineLayoutConfig, PushConstantConfig},
-        shader::{Shader, ShaderSource},
-        texture::{self, Texture, TextureConfig, TextureType},
-        RenderStateExt,
+use crate::{
+    engine::{
+        render_state::GpuState,
+        render_state_ext::{
+            binding::{Binding, BindingData, BindingEntry},
+            pass::ComputePass,
+            pipeline::{ComputePipelineConfig, PipelineLayoutConfig, PushConstantConfig},
+            shader::{Shader, ShaderSource},
+            texture::{Texture, TextureConfig, TextureType},
+            RenderStateExt,
+        },
+    },
+    state::{
+        scene::SkySettings,
+        sky::{SkyDistribution, SkySource},
     },
 };
 
-use super::buffer::{
-    bvh::BvhBuffer,
-    object::{AabbListBuffer, PlaneListBuffer, SphereListBuffer, TriangleListBuffer},
-    screen::ScreenBuffer,
+use super::{
+    buffer::{screen::ScreenBuffer, sky::SkyDistributionBuffer, ObjectBuffers},
+    sky::create_sky_cubemap,
 };
 
 pub struct RaytraceRenderContext<'a> {
@@ -39,21 +44,19 @@ impl<'a> RaytraceRenderContext<'a> {
     pub const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
 
     pub fn new(
-        render_state: &RenderState,
+        gpu_state: &GpuState,
+        size: PhysicalSize<u32>,
         screen_buffer: &ScreenBuffer,
-        sphere_list_buffer: &SphereListBuffer,
-        plane_list_buffer: &PlaneListBuffer,
-        aabb_list_buffer: &AabbListBuffer,
-        triangle_list_buffer: &TriangleListBuffer,
-        bvh_buffer: &BvhBuffer,
+        object_buffers: &ObjectBuffers,
+        sky: &SkySettings,
     ) -> Self {
-        let gpu_state = render_state.get_gpu_state();
+        let gpu_state = gpu_state.clone();
 
         let color_texture_config = TextureConfig {
             ty: TextureType::Texture2d,
             format: Self::TEXTURE_FORMAT,
-            width: render_state.size.width,
-            height: render_state.size.height,
+            width: size.width,
+            height: size.height,
             depth: 1,
             mips: 1,
             address_mode: wgpu::AddressMode::ClampToEdge,
@@ -81,8 +84,8 @@ impl<'a> RaytraceRenderContext<'a> {
             },
         );
 
-        let (wavelength_to_xyz_lut, rgb_to_spectral_intensity_lut, cubemap) =
-            Self::load_luts(&gpu_state);
+        let (wavelength_to_xyz_lut, rgb_to_spectral_intensity_lut, cubemap, sky_distribution) =
+            Self::load_luts(&gpu_state, sky);
 
         let screen_binding = Binding::new(
             &gpu_state,
@@ -96,14 +99,7 @@ impl<'a> RaytraceRenderContext<'a> {
             }],
         );
 
-        let object_binding = Self::create_object_binding(
-            &gpu_state,
-            sphere_list_buffer,
-            plane_list_buffer,
-            aabb_list_buffer,
-            triangle_list_buffer,
-            bvh_buffer,
-        );
+        let object_binding = Self::create_object_binding(&gpu_state, object_buffers);
 
         let lut_binding = Binding::new(
             &gpu_state,
@@ -142,6 +138,14 @@ impl<'a> RaytraceRenderContext<'a> {
                     },
                     count: None,
                 },
+                BindingEntry {
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    binding_data: BindingData::Buffer {
+                        buffer_type: wgpu::BufferBindingType::Storage { read_only: true },
+                        buffer: &sky_distribution.buffer,
+                    },
+                    count: None,
+                },
             ],
         );
 
@@ -149,11 +153,11 @@ impl<'a> RaytraceRenderContext<'a> {
             Self::create_texture_binding(&gpu_state, &color_texture, &color_texture_copy);
 
         let shader = Shader::new(
-            &render_state,
+            &gpu_state,
             ShaderSource::load_wgsl("assets/shaders/raytrace.wgsl"),
         );
 
-        let pipeline_layout = render_state.create_pipeline_layout(PipelineLayoutConfig {
+        let pipeline_layout = gpu_state.create_pipeline_layout(PipelineLayoutConfig {
             bind_group_layouts: &[
                 screen_binding.bind_group_layout(),
                 object_binding.bind_group_layout(),
@@ -163,7 +167,7 @@ impl<'a> RaytraceRenderContext<'a> {
             push_constant_config: PushConstantConfig::default(),
         });
 
-        let pipeline = render_state.create_compute_pipeline(
+        let pipeline = gpu_state.create_compute_pipeline(
             "Raytrace Compute Pipeline",
             ComputePipelineConfig {
                 layout: &pipeline_layout,
@@ -181,11 +185,14 @@ impl<'a> RaytraceRenderContext<'a> {
             object_binding,
             lut_binding,
             texture_binding,
-            gpu_state: render_state.get_gpu_state(),
+            gpu_state,
         }
     }
 
-    pub fn load_luts(gpu_state: &GpuState) -> (Texture, Texture, Texture) {
+    pub fn load_luts<'b>(
+        gpu_state: &'b GpuState,
+        sky: &SkySettings,
+    ) -> (Texture<'b>, Texture<'b>, Texture<'b>, SkyDistributionBuffer) {
         let wavelength_to_xyz_path = std::env::current_dir()
             .unwrap()
             .join("assets/textures/lut/wavelength_to_xyz");
@@ -279,20 +286,28 @@ impl<'a> RaytraceRenderContext<'a> {
             },
         );
 
-        let cubemap = texture::create_cubemap_texture(
-            gpu_state,
-            "Sky Cubemap",
-            "assets/textures/cubemap/meadow",
-            4096,
-            wgpu::TextureFormat::Rgba32Float,
-            wgpu::TextureUsages::TEXTURE_BINDING,
-        )
-        .unwrap();
+        let sky_source = SkySource::new(&sky.environment).unwrap_or_else(|err| {
+            panic!(
+                "Couldn't load sky from {:?}; expected a .hdr or .exr panorama, or a directory \
+                 with px, nx, py, ny, pz, nz: {}",
+                sky.environment, err
+            );
+        });
+
+        let cubemap = create_sky_cubemap(gpu_state, &sky_source);
+
+        // the sky is importance sampled in proportion to its brightness
+        let sky_distribution = SkyDistribution::new(&sky_source);
+
+        let mut sky_distribution_buffer =
+            SkyDistributionBuffer::new("Sky Distribution Buffer", gpu_state.as_gpu_state());
+        sky_distribution_buffer.update(&sky_distribution);
 
         (
             wavelength_to_xyz_lut,
             rgb_to_spectral_intensity_lut,
             cubemap,
+            sky_distribution_buffer,
         )
     }
 
@@ -326,14 +341,11 @@ impl<'a> RaytraceRenderContext<'a> {
         )
     }
 
-    fn create_object_binding(
-        gpu_state: &GpuState,
-        sphere_list_buffer: &SphereListBuffer,
-        plane_list_buffer: &PlaneListBuffer,
-        aabb_list_buffer: &AabbListBuffer,
-        triangle_list_buffer: &TriangleListBuffer,
-        bvh_buffer: &BvhBuffer,
-    ) -> Binding {
+    fn create_object_binding(gpu_state: &GpuState, object_buffers: &ObjectBuffers) -> Binding {
+        let material_textures = &object_buffers.material_texture_array.texture;
+        let material_texture_layers = object_buffers.material_texture_array.layers();
+        let grid_atlas = &object_buffers.grid_atlas.texture;
+
         Binding::new(
             gpu_state,
             &[
@@ -341,7 +353,7 @@ impl<'a> RaytraceRenderContext<'a> {
                     visibility: wgpu::ShaderStages::COMPUTE,
                     binding_data: BindingData::Buffer {
                         buffer_type: wgpu::BufferBindingType::Storage { read_only: true },
-                        buffer: &sphere_list_buffer.buffer,
+                        buffer: &object_buffers.sphere_list_buffer.buffer,
                     },
                     count: None,
                 },
@@ -349,7 +361,7 @@ impl<'a> RaytraceRenderContext<'a> {
                     visibility: wgpu::ShaderStages::COMPUTE,
                     binding_data: BindingData::Buffer {
                         buffer_type: wgpu::BufferBindingType::Storage { read_only: true },
-                        buffer: &plane_list_buffer.buffer,
+                        buffer: &object_buffers.plane_list_buffer.buffer,
                     },
                     count: None,
                 },
@@ -357,7 +369,7 @@ impl<'a> RaytraceRenderContext<'a> {
                     visibility: wgpu::ShaderStages::COMPUTE,
                     binding_data: BindingData::Buffer {
                         buffer_type: wgpu::BufferBindingType::Storage { read_only: true },
-                        buffer: &aabb_list_buffer.buffer,
+                        buffer: &object_buffers.aabb_list_buffer.buffer,
                     },
                     count: None,
                 },
@@ -365,7 +377,7 @@ impl<'a> RaytraceRenderContext<'a> {
                     visibility: wgpu::ShaderStages::COMPUTE,
                     binding_data: BindingData::Buffer {
                         buffer_type: wgpu::BufferBindingType::Storage { read_only: true },
-                        buffer: &triangle_list_buffer.buffer,
+                        buffer: &object_buffers.triangle_list_buffer.buffer,
                     },
                     count: None,
                 },
@@ -373,7 +385,103 @@ impl<'a> RaytraceRenderContext<'a> {
                     visibility: wgpu::ShaderStages::COMPUTE,
                     binding_data: BindingData::Buffer {
                         buffer_type: wgpu::BufferBindingType::Storage { read_only: true },
-                        buffer: &bvh_buffer.buffer,
+                        buffer: &object_buffers.vertex_list_buffer.buffer,
+                    },
+                    count: None,
+                },
+                BindingEntry {
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    binding_data: BindingData::Buffer {
+                        buffer_type: wgpu::BufferBindingType::Storage { read_only: true },
+                        buffer: &object_buffers.material_list_buffer.buffer,
+                    },
+                    count: None,
+                },
+                BindingEntry {
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    binding_data: BindingData::Buffer {
+                        buffer_type: wgpu::BufferBindingType::Storage { read_only: true },
+                        buffer: &object_buffers.bvh_buffer.buffer,
+                    },
+                    count: None,
+                },
+                BindingEntry {
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    binding_data: BindingData::Buffer {
+                        buffer_type: wgpu::BufferBindingType::Storage { read_only: true },
+                        buffer: &object_buffers.instance_list_buffer.buffer,
+                    },
+                    count: None,
+                },
+                BindingEntry {
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    binding_data: BindingData::Buffer {
+                        buffer_type: wgpu::BufferBindingType::Storage { read_only: true },
+                        buffer: &object_buffers.primitive_list_buffer.buffer,
+                    },
+                    count: None,
+                },
+                BindingEntry {
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    binding_data: BindingData::TextureView {
+                        texture: material_textures,
+                        texture_view: &material_textures.view(0..1, 0..material_texture_layers),
+                    },
+                    count: None,
+                },
+                BindingEntry {
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    binding_data: BindingData::TextureSampler {
+                        sampler_type: wgpu::SamplerBindingType::Filtering,
+                        texture: material_textures,
+                    },
+                    count: None,
+                },
+                BindingEntry {
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    binding_data: BindingData::Buffer {
+                        buffer_type: wgpu::BufferBindingType::Storage { read_only: true },
+                        buffer: &object_buffers.wide_bvh_buffer.buffer,
+                    },
+                    count: None,
+                },
+                BindingEntry {
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    binding_data: BindingData::Buffer {
+                        buffer_type: wgpu::BufferBindingType::Storage { read_only: true },
+                        buffer: &object_buffers.emitter_list_buffer.buffer,
+                    },
+                    count: None,
+                },
+                BindingEntry {
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    binding_data: BindingData::Buffer {
+                        buffer_type: wgpu::BufferBindingType::Storage { read_only: true },
+                        buffer: &object_buffers.light_list_buffer.buffer,
+                    },
+                    count: None,
+                },
+                BindingEntry {
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    binding_data: BindingData::Buffer {
+                        buffer_type: wgpu::BufferBindingType::Storage { read_only: true },
+                        buffer: &object_buffers.grid_volume_list_buffer.buffer,
+                    },
+                    count: None,
+                },
+                BindingEntry {
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    binding_data: BindingData::TextureView {
+                        texture: grid_atlas,
+                        texture_view: &grid_atlas.view(0..1, 0..1),
+                    },
+                    count: None,
+                },
+                BindingEntry {
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    binding_data: BindingData::TextureSampler {
+                        sampler_type: wgpu::SamplerBindingType::Filtering,
+                        texture: grid_atlas,
                     },
                     count: None,
                 },
@@ -405,22 +513,8 @@ impl<'a> RaytraceRenderContext<'a> {
         );
     }
 
-    fn recreate_object_binding(
-        &mut self,
-        sphere_list_buffer: &SphereListBuffer,
-        plane_list_buffer: &PlaneListBuffer,
-        aabb_list_buffer: &AabbListBuffer,
-        triangle_list_buffer: &TriangleListBuffer,
-        bvh_buffer: &BvhBuffer,
-    ) {
-        self.object_binding = Self::create_object_binding(
-            &self.gpu_state,
-            sphere_list_buffer,
-            plane_list_buffer,
-            aabb_list_buffer,
-            triangle_list_buffer,
-            bvh_buffer,
-        );
+    fn recreate_object_binding(&mut self, object_buffers: &ObjectBuffers) {
+        self.object_binding = Self::create_object_binding(&self.gpu_state, object_buffers);
     }
 
     pub fn recompile_shaders(&mut self) {
@@ -432,21 +526,8 @@ impl<'a> RaytraceRenderContext<'a> {
         self.recreate_textures(new_size);
     }
 
-    pub fn on_object_update(
-        &mut self,
-        sphere_list_buffer: &SphereListBuffer,
-        plane_list_buffer: &PlaneListBuffer,
-        aabb_list_buffer: &AabbListBuffer,
-        triangle_list_buffer: &TriangleListBuffer,
-        bvh_buffer: &BvhBuffer,
-    ) {
-        self.recreate_object_binding(
-            sphere_list_buffer,
-            plane_list_buffer,
-            aabb_list_buffer,
-            triangle_list_buffer,
-            bvh_buffer,
-        );
+    pub fn on_object_update(&mut self, object_buffers: &ObjectBuffers) {
+        self.recreate_object_binding(object_buffers);
     }
 
     pub fn draw(&self, encoder: &mut wgpu::CommandEncoder) {
@@ -0,0 +1,190 @@
+use glam::UVec3;
+
+use crate::{
+    engine::{
+        render_state::GpuState,
+        render_state_ext::{
+            binding::{Binding, BindingData, BindingEntry},
+            pass::ComputePass,
+            pipeline::{ComputePipelineConfig, PipelineLayoutConfig, PushConstantConfig},
+            shader::{Shader, ShaderSource},
+            texture::{self, Texture, TextureConfig, TextureType},
+            RenderStateExt,
+        },
+    },
+    state::sky::SkySource,
+};
+
+/// Must match the workgroup size in `equirect_to_cubemap.wgsl`.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Creates the cubemap the raytracer samples the sky from. Raw faces are uploaded as they are, and
+/// panoramas are projected onto the faces on the gpu. Physical skies get a black cubemap, since they
+/// aren't read from it.
+pub fn create_sky_cubemap<'a>(gpu_state: &GpuState, source: &SkySource) -> Texture<'a> {
+    let format = wgpu::TextureFormat::Rgba32Float;
+    let size = source.cubemap_size();
+
+    let panorama = match source {
+        SkySource::Cubemap { faces, size } => {
+            return texture::create_cubemap_texture(
+                gpu_state,
+                "Sky Cubemap",
+                faces,
+                *size,
+                format,
+                wgpu::TextureUsages::TEXTURE_BINDING,
+            );
+        }
+        SkySource::Panorama(panorama) => panorama,
+        SkySource::Physical(_) => {
+            let texel_size = format.target_pixel_byte_cost().unwrap() as usize;
+
+            return texture::create_cubemap_texture(
+                gpu_state,
+                "Sky Cubemap",
+                &vec![vec![0; texel_size * (size * size) as usize]; 6],
+                size,
+                format,
+                wgpu::TextureUsages::TEXTURE_BINDING,
+            );
+        }
+    };
+
+    let panorama_texture = Texture::new(
+        gpu_state,
+        "Sky Panorama",
+        TextureConfig {
+            ty: TextureType::Texture2d,
+            format,
+            width: panorama.width(),
+            height: panorama.height(),
+            depth: 1,
+            mips: 1,
+            // the panorama wraps around horizontally
+            address_mode: wgpu::AddressMode::Repeat,
+            filter_mode: wgpu::FilterMode::Linear,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        },
+    );
+
+    gpu_state.queue.write_texture(
+        panorama_texture.as_image_copy(),
+        bytemuck::cast_slice(panorama.as_raw()),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(panorama.width() * format.target_pixel_byte_cost().unwrap()),
+            rows_per_image: None,
+        },
+        wgpu::Extent3d {
+            width: panorama.width(),
+            height: panorama.height(),
+            depth_or_array_layers: 1,
+        },
+    );
+
+    let face_config = TextureConfig {
+        ty: TextureType::Texture2dArray,
+        format,
+        width: size,
+        height: size,
+        depth: 6,
+        mips: 1,
+        address_mode: wgpu::AddressMode::ClampToEdge,
+        filter_mode: wgpu::FilterMode::Linear,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+    };
+
+    // storage textures can't be cubes, so the faces are written as layers and copied into the cubemap
+    let faces = Texture::new(gpu_state, "Sky Cubemap Faces", face_config.clone());
+
+    let cubemap = Texture::new(
+        gpu_state,
+        "Sky Cubemap",
+        TextureConfig {
+            ty: TextureType::TextureCube,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            ..face_config
+        },
+    );
+
+    let binding = Binding::new(
+        gpu_state,
+        &[
+            BindingEntry {
+                visibility: wgpu::ShaderStages::COMPUTE,
+                binding_data: BindingData::TextureView {
+                    texture: &panorama_texture,
+                    texture_view: &panorama_texture.view(0..1, 0..1),
+                },
+                count: None,
+            },
+            BindingEntry {
+                visibility: wgpu::ShaderStages::COMPUTE,
+                binding_data: BindingData::TextureSampler {
+                    sampler_type: wgpu::SamplerBindingType::Filtering,
+                    texture: &panorama_texture,
+                },
+                count: None,
+            },
+            BindingEntry {
+                visibility: wgpu::ShaderStages::COMPUTE,
+                binding_data: BindingData::TextureStorage {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    texture_view: &faces.view(0..1, 0..6),
+                    texture: &faces,
+                },
+                count: None,
+            },
+        ],
+    );
+
+    let pipeline_layout = gpu_state.create_pipeline_layout(PipelineLayoutConfig {
+        bind_group_layouts: &[binding.bind_group_layout()],
+        push_constant_config: PushConstantConfig::default(),
+    });
+
+    let pipeline = gpu_state.create_compute_pipeline(
+        "Equirect to Cubemap Compute Pipeline",
+        ComputePipelineConfig {
+            layout: &pipeline_layout,
+            shader: &Shader::new(
+                gpu_state,
+                ShaderSource::load_wgsl("assets/shaders/equirect_to_cubemap.wgsl"),
+            ),
+        },
+    );
+
+    let mut encoder = gpu_state
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Equirect to Cubemap Command Encoder"),
+        });
+
+    ComputePass {
+        name: "Equirect to Cubemap Pass",
+        workgroups: UVec3::new(
+            size.div_ceil(WORKGROUP_SIZE),
+            size.div_ceil(WORKGROUP_SIZE),
+            6,
+        ),
+        pipeline: &pipeline,
+        bindings: &[&binding],
+        push_constants: None,
+    }
+    .draw(&mut encoder);
+
+    encoder.copy_texture_to_texture(
+        faces.as_image_copy(),
+        cubemap.as_image_copy(),
+        wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6,
+        },
+    );
+
+    gpu_state.queue.submit(std::iter::once(encoder.finish()));
+
+    cubemap
+}
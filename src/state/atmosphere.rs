@@ -0,0 +1,266 @@
+use std::f32::consts::PI;
+
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use super::light::blackbody;
+
+// The model and its constants must match `atmosphere.wgsl`. Distances are in km.
+
+const GROUND_RADIUS: f32 = 6360.0;
+const ATMOSPHERE_RADIUS: f32 = 6460.0;
+/// The sky is seen from just above the ground, high enough for the altitude to be precise.
+const OBSERVER_ALTITUDE: f32 = 0.01;
+
+const RAYLEIGH_SCALE_HEIGHT: f32 = 8.0;
+const MIE_SCALE_HEIGHT: f32 = 1.2;
+/// Scattering coefficient of clean air at 550 nm, per km.
+const RAYLEIGH_SCATTERING_550: f32 = 13.558e-3;
+const MIE_SINGLE_SCATTERING_ALBEDO: f32 = 0.9;
+const MIE_ANGSTROM_EXPONENT: f32 = 1.3;
+const MIE_ASYMMETRY: f32 = 0.76;
+/// Absorption of ozone at the peak of the Chappuis band around 590 nm, per km, where the ozone layer
+/// is the densest.
+const OZONE_ABSORPTION_PEAK: f32 = 2.44e-3;
+
+const SUN_TEMPERATURE: f32 = 5778.0;
+
+const VIEW_SAMPLES: usize = 24;
+const SUN_SAMPLES: usize = 8;
+
+/// A sky computed from how sunlight scatters in the atmosphere for every wavelength, instead of being
+/// read from an image. Light is scattered once by air and aerosols on its way from the sun, and
+/// absorbed by the ozone layer, after Nishita et al., "Display of the Earth Taking into Account
+/// Atmospheric Scattering" (1993). The sun's disc is part of the sky, with the spectrum of a
+/// blackbody dimmed by the air in front of it.
+///
+/// The sun's irradiance above the atmosphere is normalized like blackbody lights are, so that a white
+/// surface facing it there would be as bright as an emission of one at 560 nm.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhysicalSky {
+    /// Angle of the sun above the horizon, in degrees.
+    pub sun_elevation: f32,
+    /// Angle of the sun around the y axis, in degrees, from -z towards +x.
+    pub sun_azimuth: f32,
+    pub sun_angular_diameter: f32,
+    /// How hazy the air is, as the optical depth of the atmosphere relative to clean air. One is
+    /// perfectly clean air, two to three a clear day and higher values a hazy one.
+    pub turbidity: f32,
+    /// Fraction of light reflected by the ground below the horizon.
+    pub ground_albedo: f32,
+}
+
+impl Default for PhysicalSky {
+    fn default() -> Self {
+        Self {
+            sun_elevation: 45.0,
+            sun_azimuth: 0.0,
+            sun_angular_diameter: 0.53,
+            turbidity: 2.5,
+            ground_albedo: 0.3,
+        }
+    }
+}
+
+/// Scattering and absorption coefficients at a wavelength, per km.
+struct Coefficients {
+    rayleigh: f32,
+    mie_scattering: f32,
+    mie_extinction: f32,
+    ozone: f32,
+}
+
+impl Coefficients {
+    fn extinction(&self, density: Vec3) -> f32 {
+        Vec3::new(self.rayleigh, self.mie_extinction, self.ozone).dot(density)
+    }
+}
+
+impl PhysicalSky {
+    /// Checks that the sun and the atmosphere make sense, returning the reason if they don't.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if !(-90.0..=90.0).contains(&self.sun_elevation) {
+            Err("sun elevation must be between -90 and 90 degrees")
+        } else if self.sun_angular_diameter <= 0.0 || self.sun_angular_diameter >= 180.0 {
+            Err("sun angular diameter must be between 0 and 180 degrees")
+        } else if self.turbidity < 1.0 {
+            Err("turbidity must be at least one")
+        } else if !(0.0..=1.0).contains(&self.ground_albedo) {
+            Err("ground albedo must be between 0 and 1")
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The direction towards the center of the sun's disc.
+    pub fn sun_direction(&self) -> Vec3 {
+        let elevation = self.sun_elevation.to_radians();
+        let azimuth = self.sun_azimuth.to_radians();
+
+        Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        )
+    }
+
+    /// One minus the cosine of the angular radius of the sun's disc, which stays precise for the
+    /// small sun.
+    pub fn sun_cone(&self) -> f32 {
+        // 1 - cos(x) = 2 * sin^2(x / 2)
+        2.0 * (self.sun_angular_diameter.to_radians() / 4.0).sin().powi(2)
+    }
+
+    fn coefficients(&self, wavelength: f32) -> Coefficients {
+        let rayleigh = RAYLEIGH_SCATTERING_550 * (550.0 / wavelength).powi(4);
+
+        let mie_optical_depth =
+            (self.turbidity - 1.0) * RAYLEIGH_SCATTERING_550 * RAYLEIGH_SCALE_HEIGHT;
+        let mie_extinction = mie_optical_depth / MIE_SCALE_HEIGHT
+            * (wavelength / 550.0).powf(-MIE_ANGSTROM_EXPONENT);
+
+        let delta = wavelength - 590.0;
+
+        Coefficients {
+            rayleigh,
+            mie_scattering: mie_extinction * MIE_SINGLE_SCATTERING_ALBEDO,
+            mie_extinction,
+            ozone: OZONE_ABSORPTION_PEAK * (-delta * delta / 6120.0).exp(),
+        }
+    }
+
+    /// The light arriving from the sky in the direction on a single wavelength, not counting the sun's
+    /// disc. Must match `atmosphere_radiance` in `atmosphere.wgsl`.
+    pub fn radiance(&self, dir: Vec3, wavelength: f32) -> f32 {
+        let coefficients = self.coefficients(wavelength);
+        let sun_direction = self.sun_direction();
+
+        let origin = Vec3::new(0.0, GROUND_RADIUS + OBSERVER_ALTITUDE, 0.0);
+
+        let ground_distance = sphere_distance(origin, dir, GROUND_RADIUS);
+        let hits_ground = ground_distance > 0.0;
+
+        let segment = match hits_ground {
+            true => ground_distance,
+            false => sphere_distance(origin, dir, ATMOSPHERE_RADIUS).max(0.0),
+        };
+
+        let cos_theta = dir.dot(sun_direction);
+        let rayleigh_phase = rayleigh_phase(cos_theta);
+        let mie_phase = mie_phase(cos_theta);
+
+        let mut optical_depth = 0.0;
+        let mut radiance = 0.0;
+
+        let samples = VIEW_SAMPLES as f32;
+
+        for i in 0..VIEW_SAMPLES {
+            // samples are denser close to the observer, where the air is the densest
+            let t = segment * ((i as f32 + 0.5) / samples).powi(2);
+            let step = segment * (2.0 * i as f32 + 1.0) / (samples * samples);
+
+            let point = origin + dir * t;
+            let density = density(point.length() - GROUND_RADIUS);
+
+            let extinction = coefficients.extinction(density) * step;
+            let transmittance = (-(optical_depth + 0.5 * extinction)).exp();
+            optical_depth += extinction;
+
+            let scattering = coefficients.rayleigh * density.x * rayleigh_phase
+                + coefficients.mie_scattering * density.y * mie_phase;
+
+            radiance +=
+                transmittance * scattering * self.sun_transmittance(&coefficients, point) * step;
+        }
+
+        radiance *= sun_irradiance(wavelength);
+
+        if hits_ground {
+            // lift the point off the ground, so the ground doesn't shadow itself
+            let normal = (origin + dir * segment).normalize();
+            let point = normal * (GROUND_RADIUS + OBSERVER_ALTITUDE);
+
+            radiance += (-optical_depth).exp() * self.ground_albedo / PI
+                * sun_irradiance(wavelength)
+                * self.sun_transmittance(&coefficients, point)
+                * normal.dot(sun_direction).max(0.0);
+        }
+
+        radiance
+    }
+
+    /// How much sunlight reaches the point through the atmosphere, or zero if the planet is in the
+    /// way.
+    fn sun_transmittance(&self, coefficients: &Coefficients, point: Vec3) -> f32 {
+        let sun_direction = self.sun_direction();
+
+        if sphere_distance(point, sun_direction, GROUND_RADIUS) > 0.0 {
+            return 0.0;
+        }
+
+        let step =
+            sphere_distance(point, sun_direction, ATMOSPHERE_RADIUS).max(0.0) / SUN_SAMPLES as f32;
+
+        let optical_depth: f32 = (0..SUN_SAMPLES)
+            .map(|i| {
+                let sample = point + sun_direction * (i as f32 + 0.5) * step;
+                coefficients.extinction(density(sample.length() - GROUND_RADIUS)) * step
+            })
+            .sum();
+
+        (-optical_depth).exp()
+    }
+}
+
+/// The spectral irradiance of the sun above the atmosphere.
+fn sun_irradiance(wavelength: f32) -> f32 {
+    PI * blackbody(wavelength, SUN_TEMPERATURE)
+}
+
+/// Densities of air, aerosols and ozone at the altitude, relative to where they're the densest.
+fn density(altitude: f32) -> Vec3 {
+    Vec3::new(
+        (-altitude / RAYLEIGH_SCALE_HEIGHT).exp(),
+        (-altitude / MIE_SCALE_HEIGHT).exp(),
+        (1.0 - (altitude - 25.0).abs() / 15.0).max(0.0),
+    )
+}
+
+fn rayleigh_phase(cos_theta: f32) -> f32 {
+    3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta)
+}
+
+/// The Cornette-Shanks phase function.
+fn mie_phase(cos_theta: f32) -> f32 {
+    let g = MIE_ASYMMETRY;
+    let g2 = g * g;
+
+    3.0 / (8.0 * PI) * (1.0 - g2) * (1.0 + cos_theta * cos_theta)
+        / ((2.0 + g2) * (1.0 + g2 - 2.0 * g * cos_theta).powf(1.5))
+}
+
+/// The distance along the ray to the nearest point in front of it on a sphere around the planet's
+/// center, or a negative distance if there's none.
+fn sphere_distance(origin: Vec3, dir: Vec3, radius: f32) -> f32 {
+    let b = origin.dot(dir);
+    let distance = origin.length();
+
+    // factored, since both squares are huge compared to their difference
+    let c = (distance - radius) * (distance + radius);
+    let discriminant = b * b - c;
+
+    if discriminant < 0.0 {
+        return -1.0;
+    }
+
+    let root = discriminant.sqrt();
+
+    if -b - root > 0.0 {
+        -b - root
+    } else if -b + root > 0.0 {
+        -b + root
+    } else {
+        -1.0
+    }
+}
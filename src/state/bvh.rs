@@ -1,9 +1,16 @@
-use glam::Vec3;
+use std::{fmt::Display, ops::Range, time::Duration};
+
+use glam::{Mat4, Vec3};
 use gpu_bytes::{AsStd140, AsStd430, Std140Bytes, Std430Bytes};
 use gpu_bytes_derive::{AsStd140, AsStd430};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
-use super::object::ObjectList;
+use super::{
+    bvh_cache::BvhCache,
+    material::NO_MATERIAL,
+    object::{MeshInstance, ObjectList},
+    wide_bvh::{self, WideBvhNode, NO_WIDE_ROOT},
+};
 
 pub trait AsBoundingVolume {
     fn bounding_volume(&self) -> BoundingVolume;
@@ -84,6 +91,10 @@ impl BoundingVolume {
     pub fn grow<T: AsBoundingVolume>(&mut self, object: &T) {
         let bounds = object.bounding_volume();
 
+        if bounds.empty {
+            return;
+        }
+
         if !self.empty {
             self.min = self.min.min(bounds.min);
             self.max = self.max.max(bounds.max);
@@ -92,8 +103,25 @@ impl BoundingVolume {
         }
     }
 
-    pub fn is_empty(self) -> bool {
-        self.min.distance_squared(self.max) < 0.00001
+    /// The bounds of this volume's corners after transforming them by `matrix`.
+    pub fn transform(self, matrix: Mat4) -> Self {
+        if self.empty {
+            return self;
+        }
+
+        let mut bounds = Self::EMPTY;
+
+        for corner in 0..8 {
+            let point = Vec3::select(
+                glam::BVec3::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0),
+                self.max,
+                self.min,
+            );
+
+            bounds.grow(&Self::from_point(matrix.transform_point3(point)));
+        }
+
+        bounds
     }
 }
 
@@ -105,32 +133,37 @@ impl AsBoundingVolume for BoundingVolume {
 
 #[derive(Default, Clone, Copy, AsStd140, AsStd430)]
 pub struct BvhNode {
-    bounds: BoundingVolume,
-    start_index: u32,
-    len: u32,
-    child_node: u32,
+    pub(super) bounds: BoundingVolume,
+    pub(super) start_index: u32,
+    pub(super) len: u32,
+    pub(super) child_node: u32,
 }
 
 impl BvhNode {
-    pub const NODE_COST: f32 = 0.0;
-    pub const OBJECT_COST: f32 = 2.0;
-
-    pub fn root<T: AsBoundingVolume>(list: &mut [T]) -> Self {
-        let mut bounds = BoundingVolume::new(Vec3::ZERO, Vec3::ZERO);
-
-        for item in list.iter() {
-            bounds.grow(item);
-        }
-
+    pub fn new(bounds: BoundingVolume, start_index: u32, len: u32, child_node: u32) -> Self {
         Self {
-            // The root node's bounding volume encompasses all objects
             bounds,
-            // The root node includes all objects in the list
-            start_index: 0,
-            len: list.len() as u32,
+            start_index,
+            len,
+            child_node,
+        }
+    }
+
+    /// A leaf node holding `len` objects of the list, starting at `start_index`.
+    fn leaf<T: AsBoundingVolume>(list: &[T], start_index: u32, len: u32) -> Self {
+        let mut node = Self {
+            bounds: BoundingVolume::EMPTY,
+            start_index,
+            len,
             // 0 represents no child nodes (yet)
             child_node: 0,
+        };
+
+        for object in node.slice(list) {
+            node.bounds.grow(object);
         }
+
+        node
     }
 
     pub fn slice<T>(self, list: &[T]) -> &[T] {
@@ -139,241 +172,1137 @@ impl BvhNode {
         &list[start..end]
     }
 
-    fn cost(&self) -> f32 {
-        Self::NODE_COST + Self::OBJECT_COST * self.bounds.surface_area() * self.len as f32
+    pub fn slice_mut<T>(self, list: &mut [T]) -> &mut [T] {
+        let start = self.start_index as usize;
+        let end = start + self.len as usize;
+        &mut list[start..end]
+    }
+}
+
+/// Settings of the binned SAH builder. The costs are only meaningful relative to each other.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BvhBuildSettings {
+    /// Number of bins along each axis that object centroids are sorted into to find a split.
+    pub bin_count: usize,
+    /// Nodes with more objects than this are always split, even if the surface area heuristic
+    /// estimates that a leaf would be cheaper.
+    pub max_leaf_size: u32,
+    /// Estimated cost of testing a ray against the two children of a node.
+    pub traversal_cost: f32,
+    /// Estimated cost of testing a ray against one object.
+    pub intersection_cost: f32,
+    /// Refitted BVHs are rebuilt once their SAH cost grows past this multiple of the cost they were
+    /// built with. Doesn't affect how BVHs are built.
+    pub rebuild_threshold: f32,
+    /// Meshes with at least this many triangles get a linear BVH built on the gpu instead, which is
+    /// much faster to build but slower to trace. Meant for big meshes whose vertices move often.
+    pub gpu_build_threshold: Option<u32>,
+}
+
+impl Default for BvhBuildSettings {
+    fn default() -> Self {
+        Self {
+            bin_count: 16,
+            max_leaf_size: 4,
+            traversal_cost: 1.0,
+            intersection_cost: 1.0,
+            rebuild_threshold: 1.5,
+            gpu_build_threshold: None,
+        }
+    }
+}
+
+impl BvhBuildSettings {
+    /// Whether the BVH of a mesh with this many triangles is built on the gpu.
+    pub fn builds_on_gpu(&self, triangle_count: u32) -> bool {
+        self.gpu_build_threshold
+            .is_some_and(|threshold| triangle_count >= threshold.max(1))
+            && triangle_count <= MAX_GPU_BUILD_TRIANGLES
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Bin {
+    bounds: BoundingVolume,
+    count: u32,
+}
+
+/// A split of a node's objects into the bins below and above `bin` along `axis`.
+#[derive(Clone, Copy)]
+struct Split {
+    axis: usize,
+    bin: usize,
+    cost: f32,
+    centroid_min: f32,
+    bin_scale: f32,
+    /// The number of bins the split was searched with, which can differ from the settings.
+    bin_count: usize,
+}
+
+impl Split {
+    fn bin_index(
+        axis: usize,
+        centroid_min: f32,
+        bin_scale: f32,
+        bin_count: usize,
+        center: Vec3,
+    ) -> usize {
+        (((center[axis] - centroid_min) * bin_scale) as usize).min(bin_count - 1)
     }
 
-    fn evaluate_split_cost<T: AsBoundingVolume>(list: &[T], axis: usize, threshold: f32) -> f32 {
-        let mut bounds_a = BoundingVolume::EMPTY;
-        let mut bounds_b = BoundingVolume::EMPTY;
+    fn is_below<T: AsBoundingVolume>(&self, object: &T) -> bool {
+        Self::bin_index(
+            self.axis,
+            self.centroid_min,
+            self.bin_scale,
+            self.bin_count,
+            object.center(),
+        ) < self.bin
+    }
+}
 
-        let mut a_count = 0;
-        let mut b_count = 0;
+/// Nodes with fewer objects search their split axes on one thread, it's not worth the overhead.
+const PARALLEL_SPLIT_THRESHOLD: usize = 4096;
 
-        for obj in list {
-            let obj_center = obj.center();
+/// The size of the node stack of BVH traversal on the gpu. Traversal keeps the untested sibling of
+/// every node on its path on the stack, so the builder stops splitting nodes one level above this
+/// depth, and the root is at depth 0.
+pub const MAX_TRAVERSAL_DEPTH: u32 = 64;
 
-            if obj_center[axis] < threshold {
-                bounds_a.grow(obj);
-                a_count += 1;
-            } else {
-                bounds_b.grow(obj);
-                b_count += 1;
+/// The most triangles a mesh can have for its BVH to be built on the gpu, limited by how large the
+/// builder's buffers can be. Bigger meshes are built on the cpu.
+pub const MAX_GPU_BUILD_TRIANGLES: u32 = 1 << 21;
+
+pub struct BoundingVolumeHierarchy {
+    nodes: Vec<BvhNode>,
+    object_count: usize,
+    settings: BvhBuildSettings,
+    /// The SAH cost right after building, to tell how much refitting has degraded the tree.
+    built_sah_cost: f32,
+    construction_time: Duration,
+}
+
+impl BoundingVolumeHierarchy {
+    /// Builds a BVH over the objects with the binned surface area heuristic, reordering the list so
+    /// that every leaf node refers to a contiguous range of objects.
+    pub fn new<T: AsBoundingVolume + Sync>(list: &mut [T], settings: BvhBuildSettings) -> Self {
+        if list.is_empty() {
+            return Self {
+                nodes: Vec::with_capacity(1),
+                object_count: 0,
+                settings,
+                built_sah_cost: 0.0,
+                construction_time: Duration::ZERO,
+            };
+        }
+
+        let instant = std::time::Instant::now();
+
+        let mut nodes = Vec::with_capacity(list.len() * 2 / settings.max_leaf_size.max(1) as usize);
+        nodes.push(BvhNode::leaf(list, 0, list.len() as u32));
+
+        // children are pushed after their parents, which refitting relies on
+        let mut stack = vec![(0, 0)];
+
+        while let Some((index, depth)) = stack.pop() {
+            let node = nodes[index];
+
+            // both children are pushed to the traversal stack on top of the siblings along the way
+            if depth + 2 > MAX_TRAVERSAL_DEPTH {
+                continue;
+            }
+
+            // nodes whose objects all have the same centroid can't be split
+            let Some(split) = Self::find_split(node.slice(list), node.bounds, &settings) else {
+                continue;
+            };
+
+            let leaf_cost = settings.intersection_cost * node.len as f32;
+
+            if split.cost >= leaf_cost && node.len <= settings.max_leaf_size {
+                continue;
             }
+
+            let below_count =
+                partition(node.slice_mut(list), |object| split.is_below(object)) as u32;
+
+            let child_node = nodes.len();
+            nodes[index].child_node = child_node as u32;
+
+            nodes.push(BvhNode::leaf(list, node.start_index, below_count));
+            nodes.push(BvhNode::leaf(
+                list,
+                node.start_index + below_count,
+                node.len - below_count,
+            ));
+
+            stack.push((child_node, depth + 1));
+            stack.push((child_node + 1, depth + 1));
         }
 
-        // discourage empty nodes
-        if a_count == 0 || b_count == 0 {
-            //log::info!("Invalid split, axis: {}, threshold: {}")
-            return f32::MAX;
+        let mut bvh = Self {
+            nodes,
+            object_count: list.len(),
+            settings,
+            built_sah_cost: 0.0,
+            construction_time: instant.elapsed(),
+        };
+
+        let stats = bvh.stats();
+        bvh.built_sah_cost = stats.sah_cost;
+
+        log::info!("{}", stats);
+
+        bvh
+    }
+
+    /// A single leaf over every object, followed by unused nodes up to the `2n - 1` nodes of a linear
+    /// BVH over them. Reserves the nodes of a BVH that's built on the gpu, while still being a valid
+    /// BVH to traverse on the cpu.
+    pub fn placeholder<T: AsBoundingVolume>(list: &[T], settings: BvhBuildSettings) -> Self {
+        let mut nodes = vec![BvhNode::default(); (2 * list.len()).saturating_sub(1)];
+
+        if let Some(root) = nodes.first_mut() {
+            *root = BvhNode::leaf(list, 0, list.len() as u32);
         }
 
-        let a_cost = bounds_a.surface_area() * a_count as f32 * Self::OBJECT_COST;
-        let b_cost = bounds_b.surface_area() * b_count as f32 * Self::OBJECT_COST;
+        Self::from_nodes(nodes, list.len(), settings, Duration::ZERO)
+    }
+
+    /// A BVH built earlier, over objects that are already in the order it was built with.
+    pub fn from_nodes(
+        nodes: Vec<BvhNode>,
+        object_count: usize,
+        settings: BvhBuildSettings,
+        construction_time: Duration,
+    ) -> Self {
+        let mut bvh = Self {
+            nodes,
+            object_count,
+            settings,
+            built_sah_cost: 0.0,
+            construction_time,
+        };
 
-        Self::NODE_COST + a_cost + b_cost
+        bvh.built_sah_cost = bvh.sah_cost();
+        bvh
     }
 
-    // returns (cost, axis, threshold)
-    fn choose_split_axis<T: AsBoundingVolume + Clone + Sync>(
+    /// Finds the cheapest split of the objects according to the surface area heuristic, by sorting
+    /// their centroids into bins along each axis and evaluating the splits between bins.
+    fn find_split<T: AsBoundingVolume + Sync>(
+        list: &[T],
         bounds: BoundingVolume,
+        settings: &BvhBuildSettings,
+    ) -> Option<Split> {
+        let mut centroid_bounds = BoundingVolume::EMPTY;
+
+        for object in list {
+            centroid_bounds.grow(&BoundingVolume::from_point(object.center()));
+        }
+
+        // costs are relative to the node's surface area, as the probability of a ray that hits the
+        // node also hitting a child
+        let area = bounds.surface_area().max(f32::EPSILON);
+
+        let find_axis_split =
+            |axis: usize| Self::find_axis_split(list, axis, centroid_bounds, area, settings);
+
+        let splits: Vec<Option<Split>> = if list.len() >= PARALLEL_SPLIT_THRESHOLD {
+            (0..3).into_par_iter().map(find_axis_split).collect()
+        } else {
+            (0..3).map(find_axis_split).collect()
+        };
+
+        splits
+            .into_iter()
+            .flatten()
+            .min_by(|a, b| a.cost.total_cmp(&b.cost))
+    }
+
+    fn find_axis_split<T: AsBoundingVolume>(
         list: &[T],
-    ) -> (f32, usize, f32) {
-        // compute the results for all 3 axes in parallel, and then choose the best at the end
-        let results_per_axis: Vec<_> = (0..3)
-            .into_par_iter()
-            .map(|axis| {
-                // if there are fewer objects in the volume, take a more accurate search
-                let (bounds_min, bounds_max) = if list.len() < 10 {
-                    let mut min = f32::INFINITY;
-                    let mut max = f32::NEG_INFINITY;
-
-                    // find min and max positions of the objects along this axis
-                    for object in list {
-                        let object_bounds = object.bounding_volume();
-
-                        if object_bounds.min[axis] < min {
-                            min = object_bounds.min[axis];
-                        }
-                        if object_bounds.max[axis] > max {
-                            max = object_bounds.max[axis];
-                        }
-                    }
+        axis: usize,
+        centroid_bounds: BoundingVolume,
+        area: f32,
+        settings: &BvhBuildSettings,
+    ) -> Option<Split> {
+        let bin_count = settings.bin_count.max(2);
+
+        let centroid_min = centroid_bounds.min[axis];
+        let extent = centroid_bounds.max[axis] - centroid_min;
+
+        if extent <= 0.0 {
+            return None;
+        }
 
-                    (min, max)
-                } else {
-                    (bounds.min[axis], bounds.max[axis])
-                };
+        let bin_scale = bin_count as f32 / extent;
 
-                let step_count = list.len().clamp(5, 20);
-                let bounds_step = (bounds_max - bounds_min) / step_count as f32;
+        let mut bins = vec![
+            Bin {
+                bounds: BoundingVolume::EMPTY,
+                count: 0,
+            };
+            bin_count
+        ];
 
-                // Vec<(cost, threshold)>
-                // compute all the results in parallel and then choose the best one at the end
-                let results: Vec<(f32, f32)> = (0..step_count)
-                    .into_par_iter()
-                    .map(|i| {
-                        let threshold = bounds_min + bounds_step * (i as f32 + 0.5);
-                        let cost = Self::evaluate_split_cost(list, axis, threshold);
+        for object in list {
+            let bin = &mut bins
+                [Split::bin_index(axis, centroid_min, bin_scale, bin_count, object.center())];
 
-                        (cost, threshold)
-                    })
-                    .collect();
+            bin.bounds.grow(object);
+            bin.count += 1;
+        }
 
-                let mut best_cost = f32::INFINITY;
-                let mut best_threshold = 0.0;
+        // sweep from the left to find the bounds and object count below every split, then from the
+        // right to evaluate each split
+        let mut below = Vec::with_capacity(bin_count - 1);
+        let mut bounds = BoundingVolume::EMPTY;
+        let mut count = 0;
 
-                for (cost, threshold) in results {
-                    if cost < best_cost {
-                        best_cost = cost;
-                        best_threshold = threshold;
-                    }
-                }
+        for bin in &bins[..bin_count - 1] {
+            bounds.grow(&bin.bounds);
+            count += bin.count;
 
-                (best_cost, axis, best_threshold)
-            })
-            .collect();
+            below.push((bounds.surface_area(), count));
+        }
+
+        let mut best: Option<Split> = None;
+        let mut bounds = BoundingVolume::EMPTY;
+        let mut count = 0;
 
-        let mut best_cost = f32::INFINITY;
-        let mut best_axis = 0;
-        let mut best_threshold = 0.0;
+        for split_bin in (1..bin_count).rev() {
+            bounds.grow(&bins[split_bin].bounds);
+            count += bins[split_bin].count;
+
+            let (below_area, below_count) = below[split_bin - 1];
+
+            if below_count == 0 || count == 0 {
+                continue;
+            }
 
-        for (cost, axis, threshold) in results_per_axis {
-            if cost < best_cost {
-                best_cost = cost;
-                best_axis = axis;
-                best_threshold = threshold;
+            let cost = settings.traversal_cost
+                + settings.intersection_cost
+                    * (below_area * below_count as f32 + bounds.surface_area() * count as f32)
+                    / area;
+
+            if best.is_none_or(|best| cost < best.cost) {
+                best = Some(Split {
+                    axis,
+                    bin: split_bin,
+                    cost,
+                    centroid_min,
+                    bin_scale,
+                    bin_count,
+                });
             }
         }
 
-        (best_cost, best_axis, best_threshold)
+        best
     }
 
-    pub fn split<T: AsBoundingVolume + Clone + Sync>(
-        &mut self,
-        list: &mut [T],
-        nodes: &mut Vec<Self>,
-        depth: u32,
-        max_depth: u32,
-    ) {
-        if depth == max_depth || self.len <= 3 {
-            return;
+    pub fn stats(&self) -> BvhStats {
+        let leaves: Vec<u32> = self
+            .nodes
+            .iter()
+            .filter(|node| node.child_node == 0)
+            .map(|node| node.len)
+            .collect();
+
+        // walk the tree to find the deepest leaf
+        let mut max_depth = 0;
+        let mut stack = Vec::new();
+
+        if !self.nodes.is_empty() {
+            stack.push((0, 0));
         }
 
-        // the child containing objects greater than the split threshold
-        let mut child_gt = Self {
-            bounds: BoundingVolume::EMPTY,
-            start_index: self.start_index,
-            len: 0,
-            child_node: 0,
-        };
+        while let Some((index, depth)) = stack.pop() {
+            let node: &BvhNode = &self.nodes[index];
+            max_depth = max_depth.max(depth);
 
-        // the child containing objects less than the split threshold
-        let mut child_lt = Self {
-            bounds: BoundingVolume::EMPTY,
-            start_index: self.start_index,
-            len: 0,
-            child_node: 0,
+            if node.child_node != 0 {
+                stack.push((node.child_node as usize, depth + 1));
+                stack.push((node.child_node as usize + 1, depth + 1));
+            }
+        }
+
+        BvhStats {
+            object_count: self.object_count,
+            node_count: self.nodes.len(),
+            max_depth,
+            leaf_count: leaves.len(),
+            min_leaf_object_count: leaves.iter().copied().min().unwrap_or(0),
+            max_leaf_object_count: leaves.iter().copied().max().unwrap_or(0),
+            average_leaf_object_count: leaves.iter().sum::<u32>() as f32
+                / leaves.len().max(1) as f32,
+            sah_cost: self.sah_cost(),
+            construction_time: self.construction_time,
+        }
+    }
+
+    /// The expected cost of tracing a ray that hits the root node through the tree, according to the
+    /// surface area heuristic with the costs the BVH was built with.
+    pub fn sah_cost(&self) -> f32 {
+        let Some(root) = self.nodes.first() else {
+            return 0.0;
         };
 
-        let (cost, split_axis, split_threshold) =
-            Self::choose_split_axis(self.bounds, self.slice(list));
+        let root_area = root.bounds.surface_area().max(f32::EPSILON);
 
-        // don't split if the cost of the split would be greater than the current cost
-        if cost >= self.cost() {
-            return;
+        self.nodes
+            .iter()
+            .map(|node| {
+                let cost = if node.child_node != 0 {
+                    self.settings.traversal_cost
+                } else {
+                    self.settings.intersection_cost * node.len as f32
+                };
+
+                cost * node.bounds.surface_area() / root_area
+            })
+            .sum()
+    }
+
+    /// The ratio of the SAH cost to the cost the BVH was built with. Refitting keeps the tree valid
+    /// as objects move, but their nodes grow and overlap, which this measures.
+    pub fn degradation(&self) -> f32 {
+        if self.built_sah_cost <= 0.0 {
+            return 1.0;
+        }
+
+        self.sah_cost() / self.built_sah_cost
+    }
+
+    /// Whether the BVH has degraded past the rebuild threshold it was built with.
+    pub fn needs_rebuild(&self) -> bool {
+        self.degradation() > self.settings.rebuild_threshold
+    }
+
+    pub fn nodes(&self) -> &[BvhNode] {
+        &self.nodes
+    }
+
+    /// Checks that the tree is well formed: the children of every node reachable from the root exist
+    /// and are inside its bounds, and every object is in exactly one leaf, inside the leaf's bounds.
+    /// Meant for BVHs that weren't built by `new`.
+    pub fn validate<T: AsBoundingVolume>(&self, list: &[T]) -> Result<(), String> {
+        if self.nodes.is_empty() {
+            return match list.is_empty() {
+                true => Ok(()),
+                false => Err(format!("no nodes for {} objects", list.len())),
+            };
         }
 
-        let greater = |object: &T| object.center()[split_axis] > split_threshold;
+        let contains = |outer: BoundingVolume, inner: BoundingVolume| {
+            inner.empty
+                || !outer.empty
+                    && outer.min.cmple(inner.min).all()
+                    && outer.max.cmpge(inner.max).all()
+        };
+
+        let mut visited = vec![false; self.nodes.len()];
+        let mut leaf_counts = vec![0; list.len()];
+        let mut stack = vec![0];
+
+        while let Some(index) = stack.pop() {
+            if std::mem::replace(&mut visited[index], true) {
+                return Err(format!("node {} is reachable more than once", index));
+            }
+
+            let node = self.nodes[index];
+
+            if node.child_node != 0 {
+                let child_node = node.child_node as usize;
 
-        for global_index in self.start_index..(self.start_index + self.len) {
-            let global_index = global_index as usize;
-            let object = &list[global_index];
+                if child_node + 1 >= self.nodes.len() {
+                    return Err(format!("children of node {} are out of range", index));
+                }
 
-            if greater(object) {
-                child_gt.bounds.grow(object);
-                child_gt.len += 1;
+                for child in [child_node, child_node + 1] {
+                    if !contains(node.bounds, self.nodes[child].bounds) {
+                        return Err(format!("node {} isn't inside its parent {}", child, index));
+                    }
 
-                let swap_index = (child_gt.start_index + child_gt.len) as usize - 1;
-                list.swap(swap_index, global_index);
-                child_lt.start_index += 1;
+                    stack.push(child);
+                }
             } else {
-                child_lt.bounds.grow(object);
-                child_lt.len += 1;
+                if node.start_index as usize + node.len as usize > list.len() {
+                    return Err(format!("objects of leaf {} are out of range", index));
+                }
+
+                for (object_index, object) in node.slice(list).iter().enumerate() {
+                    let object_index = node.start_index as usize + object_index;
+                    leaf_counts[object_index] += 1;
+
+                    if !contains(node.bounds, object.bounding_volume()) {
+                        return Err(format!(
+                            "object {} isn't inside its leaf {}",
+                            object_index, index
+                        ));
+                    }
+                }
             }
         }
 
-        if child_gt.len > 0 && child_lt.len > 0 {
-            self.child_node = nodes.len() as u32;
-            nodes.push(child_gt);
-            nodes.push(child_lt);
+        match leaf_counts.iter().position(|&count| count != 1) {
+            Some(index) => Err(format!(
+                "object {} is in {} leaves",
+                index, leaf_counts[index]
+            )),
+            None => Ok(()),
+        }
+    }
 
-            // split the children of this node
-            child_gt.split(list, nodes, depth + 1, max_depth);
-            child_lt.split(list, nodes, depth + 1, max_depth);
+    /// Recomputes the bounds of every node from the objects, without changing the structure of the
+    /// tree. The objects must be in the order the BVH was built with.
+    pub fn refit<T: AsBoundingVolume>(&mut self, list: &[T]) {
+        // children are always stored after their parent, so walking backwards visits them first
+        for index in (0..self.nodes.len()).rev() {
+            let node = self.nodes[index];
+            let mut bounds = BoundingVolume::EMPTY;
+
+            if node.child_node != 0 {
+                bounds.grow(&self.nodes[node.child_node as usize].bounds);
+                bounds.grow(&self.nodes[node.child_node as usize + 1].bounds);
+            } else {
+                for object in node.slice(list) {
+                    bounds.grow(object);
+                }
+            }
 
-            nodes[self.child_node as usize] = child_gt;
-            nodes[self.child_node as usize + 1] = child_lt;
+            self.nodes[index].bounds = bounds;
         }
     }
+
+    /// Appends the nodes to a list holding several BVHs, offsetting their object indices by
+    /// `object_offset`. Returns the index of the root node.
+    fn append_to(&self, nodes: &mut Vec<BvhNode>, object_offset: u32) -> u32 {
+        let node_offset = nodes.len() as u32;
+
+        // an empty BVH still needs a root, so that it can be referred to
+        if self.nodes.is_empty() {
+            nodes.push(BvhNode::default());
+        }
+
+        nodes.extend(self.nodes.iter().map(|node| BvhNode {
+            start_index: node.start_index + object_offset,
+            child_node: if node.child_node != 0 {
+                node.child_node + node_offset
+            } else {
+                0
+            },
+            ..*node
+        }));
+
+        node_offset
+    }
 }
 
-pub struct BoundingVolumeHierarchy {
+/// Moves the objects matching the predicate to the start of the list, returning how many there are.
+fn partition<T>(list: &mut [T], predicate: impl Fn(&T) -> bool) -> usize {
+    let mut count = 0;
+
+    for index in 0..list.len() {
+        if predicate(&list[index]) {
+            list.swap(index, count);
+            count += 1;
+        }
+    }
+
+    count
+}
+
+/// A mesh instance as placed by the top-level BVH. Rays are moved into the mesh's space to traverse
+/// its bottom-level BVH.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct BvhInstance {
+    pub object_to_world: Mat4,
+    pub world_to_object: Mat4,
+    /// Index of the root node of the mesh's bottom-level BVH.
+    pub blas_root: u32,
+    /// Overrides the materials of the mesh's triangles, unless it's `NO_MATERIAL`.
+    pub material: u32,
+    /// Index of the root node of the mesh's wide bottom-level BVH, or `NO_WIDE_ROOT`.
+    pub wide_blas_root: u32,
+}
+
+impl AsStd140 for BvhInstance {
+    fn as_std140(&self) -> Std140Bytes {
+        let mut buf = Std140Bytes::new();
+
+        buf.write(&self.object_to_world);
+        buf.write(&self.world_to_object);
+        buf.write(&self.blas_root);
+        buf.write(&self.material);
+        buf.write(&self.wide_blas_root);
+        buf.align();
+
+        buf
+    }
+}
+
+impl AsStd430 for BvhInstance {
+    fn as_std430(&self) -> Std430Bytes {
+        let mut buf = Std430Bytes::new();
+
+        buf.write(&self.object_to_world);
+        buf.write(&self.world_to_object);
+        buf.write(&self.blas_root);
+        buf.write(&self.material);
+        buf.write(&self.wide_blas_root);
+        buf.align();
+
+        buf
+    }
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PrimitiveType {
+    #[default]
+    MeshInstance = 0,
+    Sphere = 1,
+    Aabb = 2,
+}
+
+impl AsStd140 for PrimitiveType {
+    fn as_std140(&self) -> Std140Bytes {
+        (*self as u32).as_std140()
+    }
+}
+
+impl AsStd430 for PrimitiveType {
+    fn as_std430(&self) -> Std430Bytes {
+        (*self as u32).as_std430()
+    }
+}
+
+/// An object in the top-level BVH, referring to a mesh instance, sphere or aabb by its index in the
+/// object list. Planes are unbounded, so they're traced outside of the BVH.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct BvhPrimitive {
+    pub ty: PrimitiveType,
+    pub index: u32,
+    pub bounds: BoundingVolume,
+}
+
+impl BvhPrimitive {
+    fn new(ty: PrimitiveType, index: usize, object_list: &ObjectList) -> Self {
+        let mut primitive = Self {
+            ty,
+            index: index as u32,
+            bounds: BoundingVolume::EMPTY,
+        };
+
+        primitive.update_bounds(object_list);
+        primitive
+    }
+
+    fn update_bounds(&mut self, object_list: &ObjectList) {
+        let index = self.index as usize;
+
+        self.bounds = match self.ty {
+            PrimitiveType::MeshInstance => {
+                let instance = object_list.mesh_instances()[index];
+
+                object_list.meshes()[instance.mesh as usize]
+                    .bounds
+                    .transform(instance.transform.matrix())
+            }
+            PrimitiveType::Sphere => object_list.spheres()[index].bounding_volume(),
+            PrimitiveType::Aabb => object_list.aabbs()[index].bounding_volume(),
+        };
+    }
+}
+
+impl AsStd140 for BvhPrimitive {
+    fn as_std140(&self) -> Std140Bytes {
+        let mut buf = Std140Bytes::new();
+
+        buf.write(&self.ty);
+        buf.write(&self.index);
+        buf.align();
+
+        buf
+    }
+}
+
+impl AsStd430 for BvhPrimitive {
+    fn as_std430(&self) -> Std430Bytes {
+        let mut buf = Std430Bytes::new();
+
+        buf.write(&self.ty);
+        buf.write(&self.index);
+        buf.align();
+
+        buf
+    }
+}
+
+impl AsBoundingVolume for BvhPrimitive {
+    fn bounding_volume(&self) -> BoundingVolume {
+        self.bounds
+    }
+}
+
+/// A mesh whose bottom-level BVH is built on the gpu, into the nodes reserved for it. The cpu keeps a
+/// single leaf over the whole mesh in its place, see `BoundingVolumeHierarchy::placeholder`.
+#[derive(Debug, Clone, Copy)]
+pub struct GpuBlas {
+    /// Index of the mesh in the object list.
+    pub mesh: u32,
+    /// Index of the first reserved node, where the root ends up.
+    pub root: u32,
+    pub triangle_offset: u32,
+    pub triangle_count: u32,
+    /// Bounds of the mesh, which the gpu builder quantizes triangle positions to.
+    pub bounds: BoundingVolume,
+}
+
+/// A two-level acceleration structure over the object list. Every mesh has a bottom-level BVH over
+/// its triangles in its own space, and the top-level BVH over the mesh instances, spheres and aabbs
+/// refers to them, so placing a mesh many times doesn't duplicate its triangles and moving an object
+/// only refits the top-level BVH.
+///
+/// The nodes of every bottom-level BVH are stored first, followed by the nodes of the top-level BVH.
+/// Every BVH is also collapsed into a wide BVH, whose nodes are stored in the same order.
+pub struct AccelerationStructure {
     pub version: u32,
+    pub mesh_version: u32,
+    pub vertex_version: u32,
+    /// The version of the object list's materials, which decide which meshes are emissive.
+    pub material_version: u32,
     nodes: Vec<BvhNode>,
+    blas: Vec<BoundingVolumeHierarchy>,
+    blas_roots: Vec<u32>,
+    gpu_blas: Vec<GpuBlas>,
+    /// Whether each mesh's bottom-level BVH is built on the gpu, in the same order as the meshes.
+    gpu_meshes: Vec<bool>,
+    tlas: BoundingVolumeHierarchy,
+    tlas_root: u32,
+    wide_nodes: Vec<WideBvhNode>,
+    wide_blas_roots: Vec<u32>,
+    /// The number of wide nodes of the bottom-level BVHs, where the wide top-level BVH starts.
+    wide_blas_len: usize,
+    wide_tlas_root: u32,
+    /// In the same order as the object list's mesh instances.
+    instances: Vec<BvhInstance>,
+    /// In the order of the top-level BVH.
+    primitives: Vec<BvhPrimitive>,
+    primitive_counts: [usize; 3],
+    settings: BvhBuildSettings,
+    use_cache: bool,
 }
 
-impl BoundingVolumeHierarchy {
-    pub fn new<T: AsBoundingVolume + Clone + Sync>(list: &mut [T], version: u32) -> Self {
-        if list.is_empty() {
-            return Self {
-                version,
-                nodes: Vec::with_capacity(1),
+impl AccelerationStructure {
+    /// Builds the bottom-level BVHs of every mesh and the top-level BVH. With `use_cache`, the
+    /// bottom-level BVHs are loaded from cache files next to the mesh files if they're up to date,
+    /// and written to them otherwise.
+    pub fn from_objects(
+        object_list: &mut ObjectList,
+        settings: BvhBuildSettings,
+        use_cache: bool,
+    ) -> Self {
+        // versions need to preemptively incremented because accessing triangles_mut() will increment them
+        let version = object_list.version() + 1;
+        let mesh_version = object_list.mesh_version() + 1;
+        let vertex_version = object_list.vertex_version();
+        let material_version = object_list.materials().version();
+
+        let gpu_meshes = Self::gpu_meshes(object_list, &settings);
+
+        let triangle_ranges: Vec<_> = object_list
+            .meshes()
+            .iter()
+            .zip(&gpu_meshes)
+            .map(|(mesh, &on_gpu)| {
+                let cache_path = use_cache.then(|| BvhCache::path_for(&mesh.path));
+
+                (mesh.triangle_range(), cache_path, on_gpu)
+            })
+            .collect();
+
+        let triangles = object_list.triangles_mut();
+
+        let mut blas = Vec::with_capacity(triangle_ranges.len());
+
+        for (range, cache_path, on_gpu) in triangle_ranges {
+            let hierarchy = match cache_path {
+                _ if on_gpu => BoundingVolumeHierarchy::placeholder(&triangles[range], settings),
+                Some(path) => BvhCache::build(&path, &mut triangles[range], settings),
+                None => BoundingVolumeHierarchy::new(&mut triangles[range], settings),
             };
+
+            blas.push(hierarchy);
         }
 
-        let instant = std::time::Instant::now();
+        let mut acceleration_structure = Self {
+            version,
+            mesh_version,
+            vertex_version,
+            material_version,
+            nodes: Vec::new(),
+            blas,
+            blas_roots: Vec::new(),
+            gpu_blas: Vec::new(),
+            gpu_meshes,
+            tlas: BoundingVolumeHierarchy::new::<BvhPrimitive>(&mut [], settings),
+            tlas_root: 0,
+            wide_nodes: Vec::new(),
+            wide_blas_roots: Vec::new(),
+            wide_blas_len: 0,
+            wide_tlas_root: NO_WIDE_ROOT,
+            instances: Vec::new(),
+            primitives: Vec::new(),
+            primitive_counts: [0; 3],
+            settings,
+            use_cache,
+        };
+
+        acceleration_structure.layout_blas(object_list);
+        acceleration_structure.rebuild_tlas(object_list);
+        acceleration_structure
+    }
 
-        let max_depth = f32::log2(list.len() as f32) as u32 + 2;
+    /// Whether each mesh's bottom-level BVH is built on the gpu. Meshes that emit light never are,
+    /// since the gpu builder reorders their triangles, while the emitters sampled by the raytracer
+    /// refer to triangles in the order of the object list.
+    fn gpu_meshes(object_list: &ObjectList, settings: &BvhBuildSettings) -> Vec<bool> {
+        object_list
+            .meshes()
+            .iter()
+            .enumerate()
+            .map(|(index, mesh)| {
+                settings.builds_on_gpu(mesh.triangle_count)
+                    && !object_list.mesh_is_emissive(index as u32)
+            })
+            .collect()
+    }
 
-        // create the root node
-        let mut root = BvhNode::root(list);
+    /// Places the nodes of every bottom-level BVH at the start of the node list, followed by the
+    /// top-level BVH once it's built.
+    fn layout_blas(&mut self, object_list: &ObjectList) {
+        self.nodes.clear();
+        self.blas_roots.clear();
+        self.gpu_blas.clear();
 
-        let initial_node_capacity = list.len() * 2 / 3;
-        let mut nodes = Vec::with_capacity(initial_node_capacity);
-        nodes.push(root);
+        let meshes = self
+            .blas
+            .iter()
+            .zip(object_list.meshes())
+            .zip(&self.gpu_meshes);
+
+        for (index, ((hierarchy, mesh), &on_gpu)) in meshes.enumerate() {
+            let root = hierarchy.append_to(&mut self.nodes, mesh.triangle_offset);
+
+            if on_gpu {
+                self.gpu_blas.push(GpuBlas {
+                    mesh: index as u32,
+                    root,
+                    triangle_offset: mesh.triangle_offset,
+                    triangle_count: mesh.triangle_count,
+                    bounds: mesh.bounds,
+                });
+            }
 
-        if !list.is_empty() {
-            root.split(list, &mut nodes, 0, max_depth);
-            nodes[0] = root;
+            self.blas_roots.push(root);
         }
 
-        let construction_time = instant.elapsed().as_secs_f64();
+        self.tlas_root = self.nodes.len() as u32;
 
-        let leaf_node_count = nodes.iter().filter(|node| node.child_node == 0).count();
+        self.collapse_blas();
+    }
 
-        let min_leaf_object_count = nodes[1..]
+    /// Collapses every bottom-level BVH into a wide BVH, except the ones built on the gpu, which the
+    /// cpu only has placeholders of.
+    fn collapse_blas(&mut self) {
+        self.wide_nodes.clear();
+        self.wide_blas_roots.clear();
+
+        for (&root, &on_gpu) in self.blas_roots.iter().zip(&self.gpu_meshes) {
+            let wide_root = match on_gpu {
+                true => None,
+                false => wide_bvh::collapse(&self.nodes, root, &mut self.wide_nodes),
+            };
+
+            self.wide_blas_roots.push(wide_root.unwrap_or(NO_WIDE_ROOT));
+        }
+
+        self.wide_blas_len = self.wide_nodes.len();
+    }
+
+    /// Appends the top-level BVH's nodes after the bottom-level BVHs' nodes, and collapses it into a
+    /// wide BVH.
+    fn layout_tlas(&mut self) {
+        self.nodes.truncate(self.tlas_root as usize);
+        self.tlas.append_to(&mut self.nodes, 0);
+
+        self.wide_nodes.truncate(self.wide_blas_len);
+        self.wide_tlas_root = wide_bvh::collapse(&self.nodes, self.tlas_root, &mut self.wide_nodes)
+            .unwrap_or(NO_WIDE_ROOT);
+    }
+
+    fn update_instances(&mut self, object_list: &ObjectList) {
+        self.instances = object_list
+            .mesh_instances()
             .iter()
-            .filter(|node| node.child_node == 0)
-            .map(|node| node.len)
-            .min()
-            .unwrap();
+            .map(
+                |&MeshInstance {
+                     mesh,
+                     transform,
+                     material,
+                 }| {
+                    let object_to_world = transform.matrix();
+
+                    BvhInstance {
+                        object_to_world,
+                        world_to_object: object_to_world.inverse(),
+                        blas_root: self.blas_roots[mesh as usize],
+                        material: material.unwrap_or(NO_MATERIAL),
+                        wide_blas_root: self.wide_blas_roots[mesh as usize],
+                    }
+                },
+            )
+            .collect();
+    }
+
+    /// The number of mesh instances, spheres and aabbs the top-level BVH was built over.
+    fn primitive_counts(object_list: &ObjectList) -> [usize; 3] {
+        [
+            object_list.mesh_instances().len(),
+            object_list.spheres().len(),
+            object_list.aabbs().len(),
+        ]
+    }
+
+    /// Rebuilds the top-level BVH, keeping the bottom-level BVHs. Needed when objects were added or
+    /// removed.
+    pub fn rebuild_tlas(&mut self, object_list: &ObjectList) {
+        self.update_instances(object_list);
+
+        self.primitive_counts = Self::primitive_counts(object_list);
+        let [instance_count, sphere_count, aabb_count] = self.primitive_counts;
+
+        let primitive = |ty| move |index| BvhPrimitive::new(ty, index, object_list);
+
+        self.primitives = (0..instance_count)
+            .map(primitive(PrimitiveType::MeshInstance))
+            .chain((0..sphere_count).map(primitive(PrimitiveType::Sphere)))
+            .chain((0..aabb_count).map(primitive(PrimitiveType::Aabb)))
+            .collect();
+
+        self.tlas = BoundingVolumeHierarchy::new(&mut self.primitives, self.settings);
+
+        self.layout_tlas();
+
+        self.version = object_list.version();
+    }
+
+    /// Updates the mesh instances' transforms and materials, and refits the top-level BVH to the
+    /// objects' new bounds without changing its structure. If refitting degraded it too much, it's
+    /// rebuilt instead.
+    pub fn refit_tlas(&mut self, object_list: &ObjectList) {
+        self.update_instances(object_list);
+
+        for primitive in &mut self.primitives {
+            primitive.update_bounds(object_list);
+        }
+
+        self.tlas.refit(&self.primitives);
+
+        if self.tlas.needs_rebuild() {
+            log::info!(
+                "Rebuilding top-level BVH, refitting degraded it by {}x",
+                self.tlas.degradation()
+            );
+
+            self.rebuild_tlas(object_list);
+            return;
+        }
+
+        self.layout_tlas();
 
-        let max_leaf_object_count = nodes[1..]
+        self.version = object_list.version();
+    }
+
+    /// Refits the bottom-level BVHs of the meshes whose vertices moved, rebuilding the ones that
+    /// degraded too much. The top-level BVH has to be refitted afterwards, since the meshes' bounds
+    /// changed.
+    pub fn refit_blas(&mut self, object_list: &mut ObjectList) {
+        object_list.update_triangle_bounds();
+
+        let triangle_ranges: Vec<_> = object_list
+            .meshes()
             .iter()
-            .filter(|node| node.child_node == 0)
-            .map(|node| node.len)
-            .max()
-            .unwrap();
+            .zip(&self.gpu_meshes)
+            .map(|(mesh, &on_gpu)| {
+                let moved = mesh.moved_since(self.vertex_version);
+                (mesh.triangle_range(), on_gpu, moved)
+            })
+            .collect();
+
+        let mut rebuilt = false;
+
+        for (hierarchy, (range, on_gpu, moved)) in self.blas.iter_mut().zip(triangle_ranges) {
+            if !moved {
+                continue;
+            }
+
+            hierarchy.refit(&object_list.triangles()[range.clone()]);
+
+            // meshes built on the gpu are rebuilt whenever they're uploaded
+            if !on_gpu && hierarchy.needs_rebuild() {
+                log::info!(
+                    "Rebuilding bottom-level BVH, refitting degraded it by {}x",
+                    hierarchy.degradation()
+                );
+
+                // the rebuilt BVH reorders the triangles, which doesn't change the meshes themselves
+                *hierarchy = BoundingVolumeHierarchy::new(
+                    &mut object_list.triangles_mut()[range],
+                    self.settings,
+                );
+                rebuilt = true;
+            }
+        }
+
+        if rebuilt {
+            self.mesh_version = object_list.mesh_version();
+        }
+
+        // rebuilt BVHs can have a different number of nodes, so every root can move
+        self.layout_blas(object_list);
+        self.vertex_version = object_list.vertex_version();
+    }
+
+    /// Brings the acceleration structure up to date with the object list, rebuilding only what
+    /// changed. The bottom-level BVHs are rebuilt when any mesh changed and refitted when vertices
+    /// moved, the top-level BVH is rebuilt when objects were added or removed, and otherwise it's
+    /// refitted. Refitted BVHs are rebuilt once they degrade past the settings' rebuild threshold.
+    pub fn update(&mut self, object_list: &mut ObjectList) {
+        if self.mesh_version != object_list.mesh_version() {
+            log::info!("Rebuilding BVH");
+            *self = Self::from_objects(object_list, self.settings, self.use_cache);
+            return;
+        }
+
+        // edited materials can make a mesh built on the gpu emissive
+        if self.material_version != object_list.materials().version() {
+            self.material_version = object_list.materials().version();
+
+            if self.gpu_meshes != Self::gpu_meshes(object_list, &self.settings) {
+                log::info!("Rebuilding BVH, emissive meshes changed");
+                *self = Self::from_objects(object_list, self.settings, self.use_cache);
+                return;
+            }
+        }
+
+        if self.vertex_version != object_list.vertex_version() {
+            self.refit_blas(object_list);
+        }
+
+        // moving vertices changes the object list's version too, so the top-level BVH is always
+        // brought up to date after refitting the bottom-level BVHs
+        if self.primitive_counts != Self::primitive_counts(object_list) {
+            // new instances can make a mesh built on the gpu emissive
+            if self.gpu_meshes != Self::gpu_meshes(object_list, &self.settings) {
+                log::info!("Rebuilding BVH, emissive meshes changed");
+                *self = Self::from_objects(object_list, self.settings, self.use_cache);
+                return;
+            }
+
+            log::info!("Rebuilding top-level BVH");
+            self.rebuild_tlas(object_list);
+        } else if self.version != object_list.version() {
+            self.refit_tlas(object_list);
+        }
+    }
+
+    pub fn nodes(&self) -> &[BvhNode] {
+        &self.nodes
+    }
+
+    pub fn tlas_root(&self) -> u32 {
+        self.tlas_root
+    }
+
+    /// The nodes of the bottom-level BVH of a mesh. The nodes of meshes built on the gpu are only
+    /// placeholders on the cpu.
+    pub fn blas_node_range(&self, mesh: u32) -> Range<usize> {
+        let start = self.blas_roots[mesh as usize];
+        let end = self
+            .blas_roots
+            .get(mesh as usize + 1)
+            .copied()
+            .unwrap_or(self.tlas_root);
+
+        start as usize..end as usize
+    }
+
+    /// Whether the bottom-level BVH of a mesh is built on the gpu, see `gpu_blas`.
+    pub fn builds_on_gpu(&self, mesh: u32) -> bool {
+        self.gpu_meshes[mesh as usize]
+    }
+
+    /// The nodes of the top-level BVH, the only ones that change when objects move.
+    pub fn tlas_node_range(&self) -> Range<usize> {
+        self.tlas_root as usize..self.nodes.len()
+    }
+
+    pub fn wide_nodes(&self) -> &[WideBvhNode] {
+        &self.wide_nodes
+    }
+
+    /// Index of the wide top-level BVH's root, or `NO_WIDE_ROOT` if it couldn't be collapsed.
+    pub fn wide_tlas_root(&self) -> u32 {
+        self.wide_tlas_root
+    }
 
-        let average_leaf_object_count = nodes[1..]
+    /// The wide nodes of the top-level BVH, see `tlas_node_range`.
+    pub fn wide_tlas_node_range(&self) -> Range<usize> {
+        self.wide_blas_len..self.wide_nodes.len()
+    }
+
+    pub fn instances(&self) -> &[BvhInstance] {
+        &self.instances
+    }
+
+    pub fn primitives(&self) -> &[BvhPrimitive] {
+        &self.primitives
+    }
+
+    /// The meshes whose bottom-level BVHs have to be built on the gpu after every upload.
+    pub fn gpu_blas(&self) -> &[GpuBlas] {
+        &self.gpu_blas
+    }
+
+    /// Stats of every mesh's bottom-level BVH, in the order of the object list's meshes.
+    pub fn blas_stats(&self) -> Vec<BvhStats> {
+        self.blas
             .iter()
-            .filter(|node| node.child_node == 0)
-            .map(|node| node.len)
-            .sum::<u32>() as f32
-            / leaf_node_count as f32;
+            .map(BoundingVolumeHierarchy::stats)
+            .collect()
+    }
+}
 
-        log::info!(
+#[derive(Debug, Clone, Copy)]
+pub struct BvhStats {
+    pub object_count: usize,
+    pub node_count: usize,
+    pub max_depth: u32,
+    pub leaf_count: usize,
+    pub min_leaf_object_count: u32,
+    pub max_leaf_object_count: u32,
+    pub average_leaf_object_count: f32,
+    pub sah_cost: f32,
+    pub construction_time: Duration,
+}
+
+impl Display for BvhStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
             r#"
             ---------- Bounding Volume Hierarchy Info ----------
             - Object count: {},
@@ -387,29 +1316,19 @@ impl BoundingVolumeHierarchy {
                     - Max: {}
                     - Average: {}
 
+            SAH cost: {}
             Construction time: {} seconds
             ----------------------------------------------------
             "#,
-            list.len(),
-            nodes.len(),
-            max_depth,
-            leaf_node_count,
-            min_leaf_object_count,
-            max_leaf_object_count,
-            average_leaf_object_count,
-            construction_time
-        );
-
-        Self { version, nodes }
-    }
-
-    pub fn from_objects(object_list: &mut ObjectList) -> Self {
-        // version needs to preemptively incremented because accessing spheres_mut() will increment the version
-        let version = object_list.version() + 1;
-        Self::new(object_list.triangles_mut(), version)
-    }
-
-    pub fn nodes(&self) -> &[BvhNode] {
-        &self.nodes
+            self.object_count,
+            self.node_count,
+            self.max_depth,
+            self.leaf_count,
+            self.min_leaf_object_count,
+            self.max_leaf_object_count,
+            self.average_leaf_object_count,
+            self.sah_cost,
+            self.construction_time.as_secs_f64()
+        )
     }
 }
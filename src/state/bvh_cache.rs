@@ -0,0 +1,362 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use glam::Vec3;
+
+use super::bvh::{
+    AsBoundingVolume, BoundingVolume, BoundingVolumeHierarchy, BvhBuildSettings, BvhNode,
+    MAX_TRAVERSAL_DEPTH,
+};
+
+const MAGIC: &[u8; 4] = b"GBVH";
+/// Must be incremented whenever the file layout or the builder's output changes, so that old caches
+/// aren't loaded.
+const FORMAT_VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub enum BvhCacheError {
+    InvalidFileStructure,
+    IoError(std::io::Error),
+}
+
+impl From<std::io::Error> for BvhCacheError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IoError(value)
+    }
+}
+
+impl Display for BvhCacheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for BvhCacheError {}
+
+/// A built BVH stored next to a mesh file, so that it doesn't have to be rebuilt every launch.
+///
+/// The cache is keyed by a hash of the objects' bounds and the builder settings, which is everything
+/// the builder looks at. Building reorders the objects, so the permutation from the original order is
+/// stored too, along with the key of the reordered objects: objects that were already reordered by an
+/// earlier build or cache load can use the nodes as they are.
+pub struct BvhCache {
+    input_key: u64,
+    output_key: u64,
+    /// For every object in BVH order, its index in the original order.
+    permutation: Vec<u32>,
+    nodes: Vec<BvhNode>,
+}
+
+impl BvhCache {
+    /// The cache file of a mesh, e.g. `dragon.glb.bvh` for `dragon.glb`.
+    pub fn path_for(mesh_path: &Path) -> PathBuf {
+        let mut path = mesh_path.as_os_str().to_owned();
+        path.push(".bvh");
+        path.into()
+    }
+
+    /// Loads the BVH from the cache file if its key matches the objects, and otherwise builds it and
+    /// writes the cache file. Either way the objects end up in BVH order.
+    pub fn build<T: AsBoundingVolume + Copy + Sync>(
+        path: &Path,
+        list: &mut [T],
+        settings: BvhBuildSettings,
+    ) -> BoundingVolumeHierarchy {
+        let instant = Instant::now();
+        let key = Self::key(list, &settings);
+
+        match Self::read(path) {
+            Ok(cache) if cache.output_key == key => {
+                match cache.into_hierarchy(list, settings, instant) {
+                    Ok(hierarchy) => {
+                        log::info!("Loaded BVH from {}", path.display());
+                        return hierarchy;
+                    }
+                    Err(err) => log::warn!("BVH cache {} is invalid: {}", path.display(), err),
+                }
+            }
+            Ok(cache) if cache.input_key == key && cache.permutation.len() == list.len() => {
+                let reordered: Vec<T> = cache
+                    .permutation
+                    .iter()
+                    .map(|&index| list[index as usize])
+                    .collect();
+
+                match cache.into_hierarchy(&reordered, settings, instant) {
+                    Ok(hierarchy) => {
+                        log::info!("Loaded BVH from {}", path.display());
+                        list.copy_from_slice(&reordered);
+                        return hierarchy;
+                    }
+                    Err(err) => log::warn!("BVH cache {} is invalid: {}", path.display(), err),
+                }
+            }
+            Ok(_) => log::info!("BVH cache {} is out of date", path.display()),
+            Err(BvhCacheError::IoError(err)) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => log::warn!("Couldn't read BVH cache {}: {}", path.display(), err),
+        }
+
+        // build over the objects' indices, to find out how the builder reorders them
+        let mut indexed: Vec<IndexedObject> = list
+            .iter()
+            .enumerate()
+            .map(|(index, object)| IndexedObject {
+                index: index as u32,
+                bounds: object.bounding_volume(),
+                center: object.center(),
+            })
+            .collect();
+
+        let hierarchy = BoundingVolumeHierarchy::new(&mut indexed, settings);
+
+        let original = list.to_vec();
+
+        for (object, indexed) in list.iter_mut().zip(&indexed) {
+            *object = original[indexed.index as usize];
+        }
+
+        let cache = Self {
+            input_key: key,
+            output_key: Self::key(list, &settings),
+            permutation: indexed.iter().map(|indexed| indexed.index).collect(),
+            nodes: hierarchy.nodes().to_vec(),
+        };
+
+        if let Err(err) = cache.write(path) {
+            log::warn!("Couldn't write BVH cache {}: {}", path.display(), err);
+        }
+
+        hierarchy
+    }
+
+    /// The cached BVH over the objects in BVH order, or why it can't be used. A file can have a
+    /// matching key and still be corrupted, or be too deep for the gpu to traverse.
+    fn into_hierarchy<T: AsBoundingVolume>(
+        self,
+        list: &[T],
+        settings: BvhBuildSettings,
+        instant: Instant,
+    ) -> Result<BoundingVolumeHierarchy, String> {
+        let hierarchy = BoundingVolumeHierarchy::from_nodes(
+            self.nodes,
+            list.len(),
+            settings,
+            instant.elapsed(),
+        );
+
+        hierarchy.validate(list)?;
+
+        let max_depth = hierarchy.stats().max_depth;
+
+        if max_depth >= MAX_TRAVERSAL_DEPTH {
+            return Err(format!(
+                "BVH is {} levels deep, but only {} levels can be traversed",
+                max_depth, MAX_TRAVERSAL_DEPTH
+            ));
+        }
+
+        Ok(hierarchy)
+    }
+
+    /// A hash of the objects' bounds and centroids in their current order, and the builder settings.
+    fn key<T: AsBoundingVolume>(list: &[T], settings: &BvhBuildSettings) -> u64 {
+        let mut hasher = Fnv1a::new();
+
+        hasher.write_u32(FORMAT_VERSION);
+        hasher.write_u32(settings.bin_count as u32);
+        hasher.write_u32(settings.max_leaf_size);
+        hasher.write_f32(settings.traversal_cost);
+        hasher.write_f32(settings.intersection_cost);
+        hasher.write_u32(list.len() as u32);
+
+        for object in list {
+            let bounds = object.bounding_volume();
+
+            hasher.write_vec3(bounds.min);
+            hasher.write_vec3(bounds.max);
+            hasher.write_vec3(object.center());
+        }
+
+        hasher.finish()
+    }
+
+    fn read(path: &Path) -> Result<Self, BvhCacheError> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+
+        if &magic != MAGIC || read_u32(&mut reader)? != FORMAT_VERSION {
+            return Err(BvhCacheError::InvalidFileStructure);
+        }
+
+        let input_key = read_u64(&mut reader)?;
+        let output_key = read_u64(&mut reader)?;
+        let object_count = read_u32(&mut reader)? as usize;
+        let node_count = read_u32(&mut reader)? as usize;
+
+        let mut permutation = Vec::with_capacity(object_count);
+        let mut seen = vec![false; object_count];
+
+        for _ in 0..object_count {
+            let index = read_u32(&mut reader)?;
+
+            // every object has to appear exactly once
+            if index as usize >= object_count || std::mem::replace(&mut seen[index as usize], true)
+            {
+                return Err(BvhCacheError::InvalidFileStructure);
+            }
+
+            permutation.push(index);
+        }
+
+        let mut nodes = Vec::with_capacity(node_count);
+
+        for index in 0..node_count {
+            let min = read_vec3(&mut reader)?;
+            let max = read_vec3(&mut reader)?;
+            let empty = read_u32(&mut reader)? != 0;
+
+            let node = BvhNode {
+                bounds: BoundingVolume { min, max, empty },
+                start_index: read_u32(&mut reader)?,
+                len: read_u32(&mut reader)?,
+                child_node: read_u32(&mut reader)?,
+            };
+
+            // children are stored after their parent, which also rules out cycles
+            let out_of_range = node.start_index as u64 + node.len as u64 > object_count as u64
+                || node.child_node != 0
+                    && (node.child_node as usize <= index
+                        || node.child_node as usize + 1 >= node_count);
+
+            if out_of_range {
+                return Err(BvhCacheError::InvalidFileStructure);
+            }
+
+            nodes.push(node);
+        }
+
+        Ok(Self {
+            input_key,
+            output_key,
+            permutation,
+            nodes,
+        })
+    }
+
+    fn write(&self, path: &Path) -> Result<(), BvhCacheError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&self.input_key.to_le_bytes())?;
+        writer.write_all(&self.output_key.to_le_bytes())?;
+        writer.write_all(&(self.permutation.len() as u32).to_le_bytes())?;
+        writer.write_all(&(self.nodes.len() as u32).to_le_bytes())?;
+
+        for index in &self.permutation {
+            writer.write_all(&index.to_le_bytes())?;
+        }
+
+        for node in &self.nodes {
+            for value in node
+                .bounds
+                .min
+                .to_array()
+                .into_iter()
+                .chain(node.bounds.max.to_array())
+            {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+
+            writer.write_all(&(node.bounds.empty as u32).to_le_bytes())?;
+            writer.write_all(&node.start_index.to_le_bytes())?;
+            writer.write_all(&node.len.to_le_bytes())?;
+            writer.write_all(&node.child_node.to_le_bytes())?;
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Stands in for an object while building, remembering where it was in the original order.
+#[derive(Clone, Copy)]
+struct IndexedObject {
+    index: u32,
+    bounds: BoundingVolume,
+    center: Vec3,
+}
+
+impl AsBoundingVolume for IndexedObject {
+    fn bounding_volume(&self) -> BoundingVolume {
+        self.bounds
+    }
+
+    fn center(&self) -> Vec3 {
+        self.center
+    }
+}
+
+/// 64-bit FNV-1a. The standard library's hasher isn't guaranteed to be stable between releases, which
+/// a key stored on disk needs.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_f32(&mut self, value: f32) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_vec3(&mut self, value: Vec3) {
+        for component in value.to_array() {
+            self.write_f32(component);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, BvhCacheError> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64, BvhCacheError> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_vec3(reader: &mut impl Read) -> Result<Vec3, BvhCacheError> {
+    Ok(Vec3::new(
+        f32::from_bits(read_u32(reader)?),
+        f32::from_bits(read_u32(reader)?),
+        f32::from_bits(read_u32(reader)?),
+    ))
+}
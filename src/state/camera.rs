@@ -46,6 +46,14 @@ impl Camera {
         }
     }
 
+    pub fn near(&self) -> f32 {
+        self.near
+    }
+
+    pub fn far(&self) -> f32 {
+        self.far
+    }
+
     pub fn reconfigure_aspect(&mut self, window_size: PhysicalSize<u32>) {
         self.aspect = window_size.width as f32 / window_size.height as f32;
     }
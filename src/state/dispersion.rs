@@ -0,0 +1,110 @@
+use glam::Vec3;
+use gpu_bytes::{AsStd140, AsStd430};
+use gpu_bytes_derive::{AsStd140, AsStd430};
+use serde::{Deserialize, Serialize};
+
+/// How the index of refraction of a dielectric changes with the wavelength, which splits white light
+/// into its colors. Wavelengths are in micrometers in every model.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Dispersion {
+    /// The material's ior is the same on every wavelength.
+    #[default]
+    None,
+    /// The Abbe number of the material, which describes how much its ior changes between the blue F
+    /// line and the red C line, with the material's ior being the one on the yellow d line. Lower
+    /// numbers disperse light more, e.g. 64 for crown glass and 30 for flint glass.
+    Abbe(f32),
+    /// The Cauchy equation, `n = a + b / λ²`.
+    Cauchy { a: f32, b: f32 },
+    /// The Sellmeier equation, `n² = 1 + Σ b λ² / (λ² - c)`, which glass manufacturers measure their
+    /// glasses with.
+    Sellmeier { b: Vec3, c: Vec3 },
+    /// A measured material from the catalog.
+    Glass(Glass),
+}
+
+/// Dielectrics with measured dispersion.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Glass {
+    /// Schott N-BK7, the most common optical crown glass.
+    Bk7,
+    /// After Malitson, "Interspecimen Comparison of the Refractive Index of Fused Silica" (1965).
+    FusedSilica,
+    /// After Peter, "Über Brechungsindizes und Absorptionskonstanten des Diamanten zwischen 644 und
+    /// 226 mμ" (1923).
+    Diamond,
+    /// Fresh water at 20 °C, fitted to its ior on the F, d and C lines.
+    Water,
+    /// Schott SF11, a dense flint glass.
+    DenseFlint,
+}
+
+impl Glass {
+    pub fn dispersion(self) -> Dispersion {
+        match self {
+            Glass::Bk7 => Dispersion::Sellmeier {
+                b: Vec3::new(1.039_612, 0.231_792_34, 1.010_469_5),
+                c: Vec3::new(0.006_000_699, 0.020_017_914, 103.560_65),
+            },
+            Glass::FusedSilica => Dispersion::Sellmeier {
+                b: Vec3::new(0.696_166_3, 0.407_942_6, 0.897_479_4),
+                c: Vec3::new(0.004_679_148, 0.013_512_06, 97.934),
+            },
+            Glass::Diamond => Dispersion::Sellmeier {
+                b: Vec3::new(0.3306, 4.3356, 0.0),
+                c: Vec3::new(0.030_625, 0.011_236, 0.0),
+            },
+            Glass::Water => Dispersion::Cauchy {
+                a: 1.324,
+                b: 0.003_06,
+            },
+            Glass::DenseFlint => Dispersion::Sellmeier {
+                b: Vec3::new(1.737_597, 0.313_747_35, 1.898_781),
+                c: Vec3::new(0.013_188_707, 0.062_306_814, 155.236_3),
+            },
+        }
+    }
+}
+
+/// A dispersion model laid out for the raytracer, see `dispersion.wgsl`. Must match the
+/// `DISPERSION_*` constants there.
+#[derive(AsStd140, AsStd430, Default, Debug, Clone, Copy)]
+struct GpuDispersion {
+    /// The Abbe number, Cauchy's `a` and `b`, or Sellmeier's `b`.
+    b: Vec3,
+    model: u32,
+    /// Sellmeier's `c`.
+    c: Vec3,
+}
+
+impl From<Dispersion> for GpuDispersion {
+    fn from(dispersion: Dispersion) -> Self {
+        match dispersion {
+            Dispersion::None => Self::default(),
+            Dispersion::Abbe(number) => Self {
+                b: Vec3::new(number, 0.0, 0.0),
+                model: 1,
+                c: Vec3::ZERO,
+            },
+            Dispersion::Cauchy { a, b } => Self {
+                b: Vec3::new(a, b, 0.0),
+                model: 2,
+                c: Vec3::ZERO,
+            },
+            Dispersion::Sellmeier { b, c } => Self { b, model: 3, c },
+            Dispersion::Glass(glass) => glass.dispersion().into(),
+        }
+    }
+}
+
+impl AsStd140 for Dispersion {
+    fn as_std140(&self) -> gpu_bytes::Std140Bytes {
+        GpuDispersion::from(*self).as_std140()
+    }
+}
+
+impl AsStd430 for Dispersion {
+    fn as_std430(&self) -> gpu_bytes::Std430Bytes {
+        GpuDispersion::from(*self).as_std430()
+    }
+}
@@ -0,0 +1,245 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use glam::{Mat4, UVec3, Vec3};
+use gpu_bytes_derive::{AsStd140, AsStd430};
+use serde::Deserialize;
+
+use super::object::Transform;
+
+#[derive(Debug)]
+#[allow(unused)]
+pub enum GridLoadError {
+    IoError(std::io::Error),
+    ParseError(ron::error::SpannedError),
+    InvalidGrid(String),
+}
+
+impl From<std::io::Error> for GridLoadError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IoError(value)
+    }
+}
+
+impl From<ron::error::SpannedError> for GridLoadError {
+    fn from(value: ron::error::SpannedError) -> Self {
+        Self::ParseError(value)
+    }
+}
+
+impl Display for GridLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for GridLoadError {}
+
+/// The header of a voxel grid file, written in RON, e.g. `(resolution: (64, 64, 64), density:
+/// "smoke.raw")`. The raw files it refers to are relative to the header, and hold one little-endian
+/// f32 per voxel, with x changing the fastest and z the slowest.
+#[derive(Debug, Deserialize)]
+struct GridHeader {
+    resolution: UVec3,
+    density: PathBuf,
+    #[serde(default)]
+    emission: Option<PathBuf>,
+}
+
+/// A dense grid of voxels holding the density of a volume, and how brightly it glows.
+#[derive(Debug, Clone)]
+pub struct VoxelGrid {
+    pub path: PathBuf,
+    pub resolution: UVec3,
+    pub density: Vec<f32>,
+    /// Empty if the grid doesn't glow.
+    pub emission: Vec<f32>,
+    /// The highest density in the grid, which no point inside of it can exceed, since the voxels are
+    /// interpolated.
+    pub max_density: f32,
+}
+
+impl VoxelGrid {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, GridLoadError> {
+        let path = path.as_ref();
+
+        let header: GridHeader = ron::Options::default()
+            .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+            .from_str(&std::fs::read_to_string(path)?)?;
+
+        let resolution = header.resolution;
+
+        if resolution.cmpeq(UVec3::ZERO).any() || resolution.max_element() > GridList::ATLAS_SIZE {
+            return Err(GridLoadError::InvalidGrid(format!(
+                "resolution must be between 1 and {} voxels on every axis",
+                GridList::ATLAS_SIZE
+            )));
+        }
+
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let voxels = resolution.x as usize * resolution.y as usize * resolution.z as usize;
+
+        let read = |raw: &Path| -> Result<Vec<f32>, GridLoadError> {
+            let bytes = std::fs::read(directory.join(raw))?;
+
+            if bytes.len() != voxels * 4 {
+                return Err(GridLoadError::InvalidGrid(format!(
+                    "{:?} has {} bytes, expected {} for a {}x{}x{} grid",
+                    raw,
+                    bytes.len(),
+                    voxels * 4,
+                    resolution.x,
+                    resolution.y,
+                    resolution.z
+                )));
+            }
+
+            Ok(bytes
+                .chunks_exact(4)
+                .map(|voxel| f32::from_le_bytes([voxel[0], voxel[1], voxel[2], voxel[3]]).max(0.0))
+                .collect())
+        };
+
+        let density = read(&header.density)?;
+        let emission = match &header.emission {
+            Some(emission) => read(emission)?,
+            None => Vec::new(),
+        };
+
+        let max_density = density.iter().copied().fold(0.0, f32::max);
+
+        Ok(Self {
+            path: path.to_owned(),
+            resolution,
+            density,
+            emission,
+            max_density,
+        })
+    }
+
+    /// The size of the grid before it's transformed. It's centered on the origin, and its longest side
+    /// is one unit long.
+    pub fn extent(&self) -> Vec3 {
+        self.resolution.as_vec3() / self.resolution.max_element() as f32
+    }
+}
+
+/// Every voxel grid used by the object list's volumes. The grids are stacked along z in one 3d texture
+/// on the gpu, so their combined depth can't exceed the atlas size.
+pub struct GridList {
+    grids: Vec<VoxelGrid>,
+    /// The first layer of every grid in the atlas.
+    offsets: Vec<u32>,
+
+    version: u32,
+}
+
+impl GridList {
+    /// The largest size of a 3d texture that every device supports.
+    pub const ATLAS_SIZE: u32 = 2048;
+
+    pub fn new() -> Self {
+        Self {
+            grids: Vec::new(),
+            offsets: Vec::new(),
+            version: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.version += 1;
+
+        self.grids.clear();
+        self.offsets.clear();
+    }
+
+    /// Loads a grid file, relative to the working directory, and returns its index. Files that were
+    /// already loaded aren't loaded again.
+    pub fn push_file<P: AsRef<Path>>(&mut self, path: P) -> Result<u32, GridLoadError> {
+        let path = path.as_ref();
+
+        if let Some(index) = self.grids.iter().position(|grid| grid.path == path) {
+            return Ok(index as u32);
+        }
+
+        let grid = VoxelGrid::load(path)?;
+
+        if self.depth() + grid.resolution.z > Self::ATLAS_SIZE {
+            return Err(GridLoadError::InvalidGrid(format!(
+                "the grids of a scene can't be more than {} voxels deep combined",
+                Self::ATLAS_SIZE
+            )));
+        }
+
+        self.version += 1;
+
+        self.offsets.push(self.depth());
+        self.grids.push(grid);
+
+        Ok(self.grids.len() as u32 - 1)
+    }
+
+    /// The combined depth of every grid, which is the depth of the atlas.
+    pub fn depth(&self) -> u32 {
+        self.grids.iter().map(|grid| grid.resolution.z).sum()
+    }
+
+    pub fn grids(&self) -> &[VoxelGrid] {
+        &self.grids
+    }
+
+    pub fn offset(&self, index: u32) -> u32 {
+        self.offsets[index as usize]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.grids.is_empty()
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+}
+
+/// A placement of a voxel grid in the scene, filled with a participating medium. The material's
+/// absorption and scattering are per unit of density, and its emission is scaled by the grid's
+/// emission. Unlike volume materials, grid volumes have no surface, so they can overlap anything.
+#[derive(Debug, Clone, Copy)]
+pub struct GridVolume {
+    /// Index of the grid in the object list's grids.
+    pub grid: u32,
+    pub transform: Transform,
+    pub material: u32,
+}
+
+#[derive(AsStd140, AsStd430, Default, Debug, Clone, Copy)]
+pub struct GpuGridVolume {
+    /// Maps world space onto the grid, which spans from zero to one on every axis.
+    pub world_to_grid: Mat4,
+    pub resolution: UVec3,
+    /// The first layer of the grid in the atlas.
+    pub offset: u32,
+    pub max_density: f32,
+    pub material: u32,
+}
+
+impl GridVolume {
+    pub fn gpu_grid_volume(&self, grids: &GridList) -> GpuGridVolume {
+        let grid = &grids.grids()[self.grid as usize];
+
+        let grid_to_world = self.transform.matrix()
+            * Mat4::from_scale(grid.extent())
+            * Mat4::from_translation(Vec3::splat(-0.5));
+
+        GpuGridVolume {
+            world_to_grid: grid_to_world.inverse(),
+            resolution: grid.resolution,
+            offset: grids.offset(self.grid),
+            max_density: grid.max_density,
+            material: self.material,
+        }
+    }
+}
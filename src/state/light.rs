@@ -0,0 +1,376 @@
+use std::f32::consts::{PI, TAU};
+
+use glam::Vec3;
+use gpu_bytes::{AsStd140, AsStd430};
+use gpu_bytes_derive::{AsStd140, AsStd430};
+use serde::{Deserialize, Serialize};
+
+use super::{bvh::PrimitiveType, object::ObjectList};
+
+/// The luminance of a linear rgb color, which lights are weighted by. Must match `luminance` in
+/// `light.wgsl`.
+pub fn luminance(color: Vec3) -> f32 {
+    color.dot(Vec3::new(0.2126, 0.7152, 0.0722))
+}
+
+/// Planck's law for a temperature in kelvin, normalized to one at 560 nm. Must match `blackbody` in
+/// `light.wgsl`.
+pub fn blackbody(wavelength: f32, temperature: f32) -> f32 {
+    // the second radiation constant in nm K
+    let c2 = 1.4387769e7;
+    let a = c2 / (560.0 * temperature);
+    let b = c2 / (wavelength * temperature);
+
+    (560.0 / wavelength).powi(5) * (a - b).exp() * (1.0 - (-a).exp()) / (1.0 - (-b).exp())
+}
+
+/// An emissive sphere, aabb or mesh triangle that the raytracer samples light from directly.
+#[derive(AsStd140, AsStd430, Default, Debug, Clone, Copy)]
+pub struct Emitter {
+    pub ty: PrimitiveType,
+    /// Index of the sphere, aabb or mesh instance in the object list.
+    pub index: u32,
+    /// Index of the triangle in the object list's triangles, if the emitter is part of a mesh
+    /// instance.
+    pub triangle: u32,
+    /// The probability of sampling this emitter or any before it.
+    pub cdf: f32,
+}
+
+/// Every emissive object in the object list, sampled in proportion to the power it emits. Planes are
+/// unbounded, so they're never sampled and only light the scene when paths hit them.
+///
+/// The raytracer recomputes an emitter's power from its material and surface area to weigh hits on
+/// it, so the power has to be computed the same way as `emitter_power` in `raytrace.wgsl`.
+#[derive(Debug, Clone, Default)]
+pub struct EmitterList {
+    emitters: Vec<Emitter>,
+    total_power: f32,
+}
+
+impl EmitterList {
+    pub fn new(object_list: &ObjectList) -> Self {
+        let materials = object_list.materials();
+        let emission = |material: u32| {
+            materials
+                .get(material)
+                .map_or(0.0, |material| luminance(material.emission))
+        };
+
+        let mut emitters = Vec::new();
+        let mut powers = Vec::new();
+
+        let mut push = |ty, index: usize, triangle: usize, power: f32| {
+            if power > 0.0 {
+                emitters.push(Emitter {
+                    ty,
+                    index: index as u32,
+                    triangle: triangle as u32,
+                    cdf: 0.0,
+                });
+                powers.push(power);
+            }
+        };
+
+        for (index, sphere) in object_list.spheres().iter().enumerate() {
+            let area = 4.0 * PI * sphere.radius() * sphere.radius();
+            push(
+                PrimitiveType::Sphere,
+                index,
+                0,
+                emission(sphere.material()) * area,
+            );
+        }
+
+        for (index, aabb) in object_list.aabbs().iter().enumerate() {
+            let size = aabb.max() - aabb.min();
+            let area = 2.0 * (size.x * size.y + size.y * size.z + size.z * size.x);
+            push(
+                PrimitiveType::Aabb,
+                index,
+                0,
+                emission(aabb.material()) * area,
+            );
+        }
+
+        for (index, instance) in object_list.mesh_instances().iter().enumerate() {
+            let mesh = &object_list.meshes()[instance.mesh as usize];
+            let scale_squared = instance.transform.scale * instance.transform.scale;
+
+            for triangle_index in mesh.triangle_range() {
+                let triangle = object_list.triangles()[triangle_index];
+                let [a, b, c] = triangle
+                    .indices()
+                    .map(|vertex| object_list.vertices()[vertex as usize].position);
+
+                let area = 0.5 * (b - a).cross(c - a).length() * scale_squared;
+                let material = instance.material.unwrap_or(triangle.material);
+
+                push(
+                    PrimitiveType::MeshInstance,
+                    index,
+                    triangle_index,
+                    emission(material) * area,
+                );
+            }
+        }
+
+        let total_power: f32 = powers.iter().sum();
+        let mut cumulative_power = 0.0;
+
+        for (emitter, power) in emitters.iter_mut().zip(powers) {
+            cumulative_power += power;
+            emitter.cdf = cumulative_power / total_power;
+        }
+
+        // make sure the last emitter is always picked for the largest random numbers
+        if let Some(last) = emitters.last_mut() {
+            last.cdf = 1.0;
+        }
+
+        Self {
+            emitters,
+            total_power,
+        }
+    }
+
+    pub fn emitters(&self) -> &[Emitter] {
+        &self.emitters
+    }
+
+    pub fn total_power(&self) -> f32 {
+        self.total_power
+    }
+}
+
+/// The spectrum a light emits, scaled by its intensity.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LightColor {
+    /// A linear rgb color, turned into a spectrum like material colors are.
+    Rgb(Vec3),
+    /// Planck's law for a temperature in kelvin, normalized to one at 560 nm where the eye is most
+    /// sensitive, so the temperature only changes the hue and not the brightness.
+    Blackbody(f32),
+}
+
+impl Default for LightColor {
+    fn default() -> Self {
+        Self::Rgb(Vec3::ONE)
+    }
+}
+
+/// Where a light is and how it spreads its light. Angles are in degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LightShape {
+    /// Emits evenly in every direction.
+    Point { position: Vec3 },
+    /// A point light limited to a cone around the direction it shines in, which falls off smoothly
+    /// from the inner angle to the outer angle. Both are measured from the direction.
+    Spot {
+        position: Vec3,
+        direction: Vec3,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+    /// Light arriving from infinitely far away, like the sun. With an angular diameter it's a disc in
+    /// the sky which casts soft shadows, otherwise all of its light arrives from a single direction.
+    Directional {
+        direction: Vec3,
+        #[serde(default)]
+        angular_diameter: f32,
+    },
+    /// A rectangle spanned by two edges from its corner, emitting on the side the cross product of
+    /// the edges points to. It's invisible from the other side.
+    Area { corner: Vec3, u: Vec3, v: Vec3 },
+}
+
+/// A light source that isn't an object, so it's only seen through the light it casts, except for area
+/// lights and directional lights with an angular diameter, which can be seen directly too.
+///
+/// The intensity is in physical units: the power in watts of point, spot and area lights, and the
+/// irradiance in watts per square meter that directional lights cast on a surface facing them. An
+/// emission of one on a material is a radiance of one watt per steradian and square meter.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Light {
+    pub shape: LightShape,
+    #[serde(default)]
+    pub color: LightColor,
+    pub intensity: f32,
+}
+
+impl Light {
+    /// Checks that the light's directions, angles and intensity make sense, returning the reason if
+    /// they don't.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.intensity < 0.0 {
+            return Err("light intensity must not be negative");
+        }
+
+        if let LightColor::Blackbody(temperature) = self.color {
+            if temperature <= 0.0 {
+                return Err("blackbody temperature must be positive");
+            }
+        }
+
+        match self.shape {
+            LightShape::Point { .. } => Ok(()),
+            LightShape::Spot {
+                direction,
+                inner_angle,
+                outer_angle,
+                ..
+            } => {
+                if direction.try_normalize().is_none() {
+                    Err("spot light direction must be non-zero")
+                } else if !(0.0..=outer_angle).contains(&inner_angle) || outer_angle > 180.0 {
+                    Err("spot light angles must satisfy 0 <= inner_angle <= outer_angle <= 180")
+                } else {
+                    Ok(())
+                }
+            }
+            LightShape::Directional {
+                direction,
+                angular_diameter,
+            } => {
+                if direction.try_normalize().is_none() {
+                    Err("directional light direction must be non-zero")
+                } else if !(0.0..180.0).contains(&angular_diameter) {
+                    Err("directional light angular diameter must be between 0 and 180 degrees")
+                } else {
+                    Ok(())
+                }
+            }
+            LightShape::Area { u, v, .. } => match u.cross(v).length_squared() > 0.0 {
+                true => Ok(()),
+                false => Err("area light edges must be non-zero and not parallel"),
+            },
+        }
+    }
+
+    /// The light as the raytracer samples it, with its intensity converted to what the raytracer
+    /// scales its spectrum by.
+    pub fn gpu_light(&self) -> GpuLight {
+        let (color, temperature) = match self.color {
+            LightColor::Rgb(color) => (color, 0.0),
+            LightColor::Blackbody(temperature) => (Vec3::ONE, temperature),
+        };
+
+        let light = GpuLight {
+            color,
+            temperature,
+            ..Default::default()
+        };
+
+        match self.shape {
+            LightShape::Point { position } => GpuLight {
+                ty: LightType::Point,
+                position,
+                intensity: self.intensity / (4.0 * PI),
+                ..light
+            },
+            LightShape::Spot {
+                position,
+                direction,
+                inner_angle,
+                outer_angle,
+            } => {
+                let cos_inner = inner_angle.to_radians().cos();
+                let cos_outer = outer_angle.to_radians().cos();
+
+                // the solid angle of the cone, counting the falloff as half lit
+                let solid_angle = TAU * (1.0 - (cos_inner + cos_outer) / 2.0);
+
+                GpuLight {
+                    ty: LightType::Spot,
+                    position,
+                    direction: direction.normalize(),
+                    intensity: self.intensity / solid_angle.max(f32::EPSILON),
+                    cone_inner: cos_inner,
+                    cone_outer: cos_outer,
+                    ..light
+                }
+            }
+            LightShape::Directional {
+                direction,
+                angular_diameter,
+            } => {
+                let half_angle = (angular_diameter / 2.0).to_radians();
+                let sin_half_angle = half_angle.sin();
+
+                // a disc of uniform radiance casts an irradiance of pi * sin^2 times its radiance
+                let intensity = match angular_diameter > 0.0 {
+                    true => self.intensity / (PI * sin_half_angle * sin_half_angle),
+                    false => self.intensity,
+                };
+
+                GpuLight {
+                    ty: LightType::Directional,
+                    direction: direction.normalize(),
+                    intensity,
+                    // 1 - cos(x) = 2 * sin^2(x / 2), which stays precise for small discs
+                    cone_outer: 2.0 * (half_angle / 2.0).sin().powi(2),
+                    ..light
+                }
+            }
+            LightShape::Area { corner, u, v } => {
+                let area = u.cross(v).length();
+
+                GpuLight {
+                    ty: LightType::Area,
+                    position: corner,
+                    direction: u.cross(v).normalize(),
+                    intensity: self.intensity / (PI * area),
+                    edge_u: u,
+                    edge_v: v,
+                    ..light
+                }
+            }
+        }
+    }
+}
+
+/// Must match the `LIGHT_*` constants in `light.wgsl`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LightType {
+    #[default]
+    Point = 0,
+    Spot = 1,
+    Directional = 2,
+    Area = 3,
+}
+
+impl AsStd140 for LightType {
+    fn as_std140(&self) -> gpu_bytes::Std140Bytes {
+        (*self as u32).as_std140()
+    }
+}
+
+impl AsStd430 for LightType {
+    fn as_std430(&self) -> gpu_bytes::Std430Bytes {
+        (*self as u32).as_std430()
+    }
+}
+
+/// A light laid out for the raytracer, see `Light::gpu_light`.
+#[derive(AsStd140, AsStd430, Default, Debug, Clone, Copy)]
+pub struct GpuLight {
+    /// The position of point and spot lights, and the corner of area lights.
+    pub position: Vec3,
+    pub ty: LightType,
+    /// The direction spot and directional lights shine in, and the normal of area lights.
+    pub direction: Vec3,
+    /// The radiant intensity of point and spot lights in W/sr, the irradiance of directional lights
+    /// without an angular diameter in W/m², and otherwise the radiance in W/(sr·m²).
+    pub intensity: f32,
+    pub color: Vec3,
+    /// The blackbody temperature in kelvin, or zero if the light has an rgb color.
+    pub temperature: f32,
+    pub edge_u: Vec3,
+    /// The cosine of the outer angle of spot lights, and one minus the cosine of the angular radius
+    /// of directional lights.
+    pub cone_outer: f32,
+    pub edge_v: Vec3,
+    /// The cosine of the inner angle of spot lights.
+    pub cone_inner: f32,
+}
@@ -1,16 +1,24 @@
+use std::collections::BTreeMap;
+
 use glam::Vec3;
 use gpu_bytes::{AsStd140, AsStd430};
 use gpu_bytes_derive::{AsStd140, AsStd430};
 use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::dispersion::Dispersion;
 
 #[repr(u32)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MaterialType {
     #[default]
     Lambertian = 0,
     Metal = 1,
     Dielectric = 2,
     Volume = 3,
+    /// Layers clearcoat, sheen, specular reflection, diffuse reflection and transmission over each
+    /// other, blended with metal by the metalness, like glTF's metallic-roughness materials.
+    Principled = 4,
 }
 
 impl AsStd140 for MaterialType {
@@ -25,14 +33,70 @@ impl AsStd430 for MaterialType {
     }
 }
 
-#[derive(AsStd140, AsStd430, Debug, Clone, Copy)]
+/// Texture index of materials without a texture in that slot.
+pub const NO_TEXTURE: u32 = u32::MAX;
+
+/// Material id of mesh instances that keep the materials of their mesh.
+pub const NO_MATERIAL: u32 = u32::MAX;
+
+#[derive(AsStd140, AsStd430, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Material {
     pub albedo: Vec3,
     pub ty: MaterialType,
     pub emission: Vec3,
     pub roughness: f32,
+    /// Light absorbed per unit of distance travelled inside the material, for volumes and the media
+    /// inside dielectrics.
+    pub absorption: Vec3,
+    /// Of dielectrics and the dielectric base of principled materials, on the yellow d line at 587.6nm
+    /// if it's dispersed.
     pub ior: f32,
+    /// Light scattered per unit of distance travelled inside the material, see `absorption`.
+    pub scattering: Vec3,
+    /// How much light scattered inside the material keeps going forwards, between -1 and 1. Zero
+    /// scatters light evenly in every direction.
     pub g: f32,
+    /// How the ior of dielectrics and the dielectric base of principled materials changes with the
+    /// wavelength. Materials without any refract every wavelength the same way.
+    pub dispersion: Dispersion,
+    /// The complex index of refraction `n + ik` of metals, per color channel, see `conductor`.
+    /// Metals without one reflect their albedo when seen head on, and get whiter towards grazing
+    /// angles. Otherwise the albedo tints the reflection.
+    pub metal_n: Vec3,
+    pub metal_k: Vec3,
+
+    // The layers of principled materials. Metalness blends between metal and a dielectric base,
+    // which is diffuse except for the part of it that's transmissive.
+    pub metallic: f32,
+    /// Tints the specular reflection of the dielectric base head on.
+    pub specular_tint: Vec3,
+    /// Scales the specular reflection of the dielectric base, zero turning it off.
+    pub specular: f32,
+    /// The color of the fuzz on top of the base, like the sheen of cloth. Zero has none.
+    pub sheen: Vec3,
+    pub sheen_roughness: f32,
+    /// How much of a smooth, colorless dielectric coat with an ior of 1.5 is on top of everything else.
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    /// Stretches the highlights of the base along the tangent, between 0 and 1, like
+    /// KHR_materials_anisotropy. Only meshes with texture coordinates have tangents.
+    pub anisotropy: f32,
+    /// Rotates the direction of the anisotropy from the tangent towards the bitangent, in radians.
+    pub anisotropy_rotation: f32,
+    pub transmission: f32,
+
+    // Indices into the object list's textures. Scene files refer to textures by path instead, so
+    // these aren't serialized.
+    #[serde(skip)]
+    pub albedo_texture: u32,
+    /// Roughness in the green channel and metalness in the blue channel, like glTF.
+    #[serde(skip)]
+    pub roughness_metalness_texture: u32,
+    #[serde(skip)]
+    pub normal_texture: u32,
+    #[serde(skip)]
+    pub emission_texture: u32,
 }
 
 impl Default for Material {
@@ -42,8 +106,27 @@ impl Default for Material {
             ty: MaterialType::Lambertian,
             emission: Vec3::ZERO,
             roughness: 0.0,
-            ior: 0.0,
+            absorption: Vec3::ZERO,
+            ior: 1.5,
+            scattering: Vec3::ZERO,
             g: 0.0,
+            dispersion: Dispersion::None,
+            metal_n: Vec3::ZERO,
+            metal_k: Vec3::ZERO,
+            metallic: 0.0,
+            specular_tint: Vec3::ONE,
+            specular: 1.0,
+            sheen: Vec3::ZERO,
+            sheen_roughness: 0.0,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.0,
+            anisotropy: 0.0,
+            anisotropy_rotation: 0.0,
+            transmission: 0.0,
+            albedo_texture: NO_TEXTURE,
+            roughness_metalness_texture: NO_TEXTURE,
+            normal_texture: NO_TEXTURE,
+            emission_texture: NO_TEXTURE,
         }
     }
 }
@@ -66,35 +149,160 @@ impl Material {
         }
     }
 
+    /// A metal with a measured complex index of refraction, e.g. `(0.18, 0.42, 1.37)` and `(3.42,
+    /// 2.35, 1.77)` for gold, sampled at the red, green and blue wavelengths.
+    ///
+    /// `n` and `k` aren't colors, so they aren't turned into spectra. The raytracer evaluates the
+    /// Fresnel equations for every color channel instead, and turns the resulting reflectance into a
+    /// spectrum, which smooths over how the reflectance varies between the three wavelengths.
+    pub fn conductor(n: Vec3, k: Vec3, roughness: f32) -> Self {
+        Self {
+            ty: MaterialType::Metal,
+            roughness,
+            metal_n: n,
+            metal_k: k,
+            ..Default::default()
+        }
+    }
+
+    /// A dielectric that disperses light about as much as flint glass, see `with_dispersion` to
+    /// change it.
     pub fn dielectric(albedo: Vec3, roughness: f32, ior: f32) -> Self {
         Self {
             albedo,
             ty: MaterialType::Dielectric,
             roughness,
             ior,
+            dispersion: Dispersion::Abbe(30.0),
             ..Default::default()
         }
     }
 
+    /// A principled material without any of the optional layers, which the `with_*` methods add.
+    pub fn principled(albedo: Vec3, roughness: f32, metallic: f32) -> Self {
+        Self {
+            albedo,
+            ty: MaterialType::Principled,
+            roughness,
+            metallic,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_clearcoat(self, clearcoat: f32, clearcoat_roughness: f32) -> Self {
+        Self {
+            clearcoat,
+            clearcoat_roughness,
+            ..self
+        }
+    }
+
+    pub fn with_sheen(self, sheen: Vec3, sheen_roughness: f32) -> Self {
+        Self {
+            sheen,
+            sheen_roughness,
+            ..self
+        }
+    }
+
+    pub fn with_transmission(self, transmission: f32) -> Self {
+        Self {
+            transmission,
+            ..self
+        }
+    }
+
+    /// A participating medium whose surface only bounds it, without reflecting or refracting any
+    /// light. Volumes must be closed, and may be nested in each other and in dielectrics, but must not
+    /// otherwise overlap.
+    pub fn volume(absorption: Vec3, scattering: Vec3, g: f32) -> Self {
+        Self {
+            ty: MaterialType::Volume,
+            absorption,
+            scattering,
+            g,
+            ..Default::default()
+        }
+    }
+
+    /// Fills the inside of a dielectric with a participating medium, like murky water.
+    pub fn with_medium(self, absorption: Vec3, scattering: Vec3, g: f32) -> Self {
+        Self {
+            absorption,
+            scattering,
+            g,
+            ..self
+        }
+    }
+
+    pub fn with_dispersion(self, dispersion: Dispersion) -> Self {
+        Self { dispersion, ..self }
+    }
+
     pub fn with_emission(self, emission: Vec3) -> Self {
         Self { emission, ..self }
     }
 
+    pub fn has_textures(&self) -> bool {
+        [
+            self.albedo_texture,
+            self.roughness_metalness_texture,
+            self.normal_texture,
+            self.emission_texture,
+        ]
+        .into_iter()
+        .any(|texture| texture != NO_TEXTURE)
+    }
+
     pub fn random() -> Self {
         let mut rng = rand::thread_rng();
 
+        let ty = match rng.gen_range(0..5) {
+            0 => MaterialType::Lambertian,
+            1 => MaterialType::Metal,
+            2 => MaterialType::Dielectric,
+            3 => MaterialType::Volume,
+            4 => MaterialType::Principled,
+            _ => unreachable!(),
+        };
+
+        let albedo = Vec3::new(
+            rng.gen::<f32>().powf(2.2),
+            rng.gen::<f32>().powf(2.2),
+            rng.gen::<f32>().powf(2.2),
+        );
+
+        // volumes take their color from the light they scatter, and absorb the rest
+        let (absorption, scattering) = match ty {
+            MaterialType::Volume => {
+                let density = rng.gen_range(0.5f32..5.0);
+                ((Vec3::ONE - albedo) * density, albedo * density)
+            }
+            _ => (Vec3::ZERO, Vec3::ZERO),
+        };
+
+        // principled materials get some of their layers
+        let layer = |rng: &mut rand::rngs::ThreadRng| match ty {
+            MaterialType::Principled if rng.gen_bool(0.3) => rng.gen_range(0.0f32..1.0),
+            _ => 0.0,
+        };
+
+        // anything that refracts splits light into its colors, between dense flint and crown glass
+        let dispersion = match ty {
+            MaterialType::Dielectric | MaterialType::Principled => {
+                Dispersion::Abbe(rng.gen_range(20.0f32..65.0))
+            }
+            _ => Dispersion::None,
+        };
+
+        let metallic = layer(&mut rng);
+        let clearcoat = layer(&mut rng);
+        let sheen = Vec3::splat(layer(&mut rng));
+        let transmission = layer(&mut rng);
+
         Self {
-            ty: match rng.gen_range(0..3) {
-                0 => MaterialType::Lambertian,
-                1 => MaterialType::Metal,
-                2 => MaterialType::Dielectric,
-                _ => unreachable!(),
-            },
-            albedo: Vec3::new(
-                rng.gen::<f32>().powf(2.2),
-                rng.gen::<f32>().powf(2.2),
-                rng.gen::<f32>().powf(2.2),
-            ),
+            ty,
+            albedo,
             emission: match rng.gen_bool(0.1) {
                 // less emission is more common
                 true => Vec3::new(
@@ -105,8 +313,96 @@ impl Material {
                 false => Vec3::ZERO,
             },
             roughness: rng.gen_range(0.0f32..1.0).powi(3),
+            absorption,
             ior: rng.gen_range(0.5f32..3.0f32).powf(0.5),
-            g: 0.0,
+            scattering,
+            g: match ty {
+                MaterialType::Volume => rng.gen_range(-0.5f32..0.9),
+                _ => 0.0,
+            },
+            dispersion,
+            metallic,
+            sheen,
+            sheen_roughness: rng.gen_range(0.2f32..1.0),
+            clearcoat,
+            clearcoat_roughness: rng.gen_range(0.0f32..0.3),
+            transmission,
+            ..Default::default()
         }
     }
 }
+
+/// Every material in the scene. Objects refer to materials by their index in this list, so editing a
+/// material changes all of its users without touching any geometry.
+pub struct MaterialList {
+    materials: Vec<Material>,
+    names: BTreeMap<String, u32>,
+
+    version: u32,
+}
+
+impl MaterialList {
+    pub fn new() -> Self {
+        Self {
+            materials: Vec::new(),
+            names: BTreeMap::new(),
+            version: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.version += 1;
+
+        self.materials.clear();
+        self.names.clear();
+    }
+
+    /// Adds a material and returns its id.
+    pub fn push(&mut self, material: Material) -> u32 {
+        self.version += 1;
+
+        self.materials.push(material);
+        self.materials.len() as u32 - 1
+    }
+
+    /// Adds a material that can be looked up by name, replacing the name of any previous material
+    /// with the same name.
+    pub fn push_named(&mut self, name: &str, material: Material) -> u32 {
+        let id = self.push(material);
+        self.names.insert(name.to_owned(), id);
+
+        id
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Material> {
+        self.materials.get(id as usize)
+    }
+
+    /// Editing a material changes the list's version, so that the material buffer is uploaded
+    /// again.
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut Material> {
+        let material = self.materials.get_mut(id as usize)?;
+        self.version += 1;
+
+        Some(material)
+    }
+
+    pub fn find(&self, name: &str) -> Option<u32> {
+        self.names.get(name).copied()
+    }
+
+    pub fn name(&self, id: u32) -> Option<&str> {
+        self.names
+            .iter()
+            .find(|(_, &named_id)| named_id == id)
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn materials(&self) -> &[Material] {
+        &self.materials
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+}
@@ -1,4 +1,14 @@
+pub mod atmosphere;
 pub mod bvh;
+pub mod bvh_cache;
 pub mod camera;
+pub mod dispersion;
+pub mod grid;
+pub mod light;
 pub mod material;
 pub mod object;
+pub mod raycast;
+pub mod scene;
+pub mod sky;
+pub mod texture;
+pub mod wide_bvh;
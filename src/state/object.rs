@@ -1,16 +1,26 @@
 use core::f32;
-use std::path::Path;
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
 
-use glam::{Quat, Vec2, Vec3};
+use glam::{Mat4, Quat, Vec2, Vec3};
 use gpu_bytes::{AsStd430, Std430Bytes};
 use gpu_bytes_derive::{AsStd140, AsStd430};
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-use crate::util::{self, gltf::GltfLoadError};
+use crate::util::{
+    self,
+    gltf::{GltfCamera, GltfLoadError, GltfScene},
+};
 
 use super::{
     bvh::{AsBoundingVolume, BoundingVolume},
-    material::{Material, MaterialType},
+    grid::{GridList, GridLoadError, GridVolume},
+    light::{self, Light},
+    material::{Material, MaterialList, MaterialType},
+    texture::TextureList,
 };
 
 const PAD_THICKNESS: f32 = 0.00025;
@@ -19,11 +29,11 @@ const PAD_THICKNESS: f32 = 0.00025;
 pub struct Sphere {
     center: Vec3,
     radius: f32,
-    material: Material,
+    material: u32,
 }
 
 impl Sphere {
-    pub fn new(center: Vec3, radius: f32, material: Material) -> Self {
+    pub fn new(center: Vec3, radius: f32, material: u32) -> Self {
         Self {
             center,
             radius,
@@ -39,6 +49,11 @@ impl Sphere {
         self.radius
     }
 
+    /// Id of the material in the object list's materials.
+    pub fn material(&self) -> u32 {
+        self.material
+    }
+
     pub fn pad(self) -> Self {
         Self {
             radius: self.radius - PAD_THICKNESS,
@@ -57,28 +72,41 @@ impl AsBoundingVolume for Sphere {
 pub struct Plane {
     normal: Vec3,
     point: Vec3,
-    material: Material,
+    material: u32,
 }
 
 impl Plane {
-    pub fn new(normal: Vec3, point: Vec3, material: Material) -> Self {
+    pub fn new(normal: Vec3, point: Vec3, material: u32) -> Self {
         Self {
             normal,
             point,
             material,
         }
     }
+
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+
+    pub fn point(&self) -> Vec3 {
+        self.point
+    }
+
+    /// Id of the material in the object list's materials.
+    pub fn material(&self) -> u32 {
+        self.material
+    }
 }
 
 #[derive(AsStd140, AsStd430, Default, Debug, Clone, Copy)]
 pub struct Aabb {
     min: Vec3,
     max: Vec3,
-    material: Material,
+    material: u32,
 }
 
 impl Aabb {
-    pub fn new(min: Vec3, max: Vec3, material: Material) -> Self {
+    pub fn new(min: Vec3, max: Vec3, material: u32) -> Self {
         Self { min, max, material }
     }
 
@@ -90,6 +118,11 @@ impl Aabb {
         self.max
     }
 
+    /// Id of the material in the object list's materials.
+    pub fn material(&self) -> u32 {
+        self.material
+    }
+
     pub fn pad(self) -> Self {
         Self {
             min: self.min + PAD_THICKNESS,
@@ -105,15 +138,26 @@ impl AsBoundingVolume for Aabb {
     }
 }
 
+#[derive(AsStd140, AsStd430, Default, Debug, Clone, Copy, PartialEq)]
+pub struct Vertex {
+    pub position: Vec3,
+    pub uv: Vec2,
+}
+
+impl Vertex {
+    pub fn new(position: Vec3, uv: Vec2) -> Self {
+        Self { position, uv }
+    }
+}
+
+/// A triangle referencing three vertices in the object list's vertex buffer, and its material. Only
+/// the indices are uploaded to the gpu; the bounds are kept around for building the BVH.
 #[derive(AsStd140, Default, Debug, Clone, Copy)]
 pub struct Triangle {
-    pub a: Vec3,
-    pub b: Vec3,
-    pub c: Vec3,
-    pub uv_a: Vec2,
-    pub uv_b: Vec2,
-    pub uv_c: Vec2,
-    pub material: Material,
+    pub a: u32,
+    pub b: u32,
+    pub c: u32,
+    pub material: u32,
     pub bounds: BoundingVolume,
     pub center: Vec3,
 }
@@ -125,9 +169,6 @@ impl AsStd430 for Triangle {
         buf.write(&self.a);
         buf.write(&self.b);
         buf.write(&self.c);
-        buf.write(&self.uv_a);
-        buf.write(&self.uv_b);
-        buf.write(&self.uv_c);
         buf.write(&self.material);
 
         buf.align();
@@ -137,29 +178,30 @@ impl AsStd430 for Triangle {
 }
 
 impl Triangle {
-    pub fn new(
-        a: Vec3,
-        b: Vec3,
-        c: Vec3,
-        uv_a: Vec2,
-        uv_b: Vec2,
-        uv_c: Vec2,
-        material: Material,
-    ) -> Self {
-        Self {
-            a,
-            b,
-            c,
-            uv_a,
-            uv_b,
-            uv_c,
+    pub fn new(indices: [u32; 3], material: u32, vertices: &[Vertex]) -> Self {
+        let mut triangle = Self {
+            a: indices[0],
+            b: indices[1],
+            c: indices[2],
             material,
-            bounds: BoundingVolume::new(a.min(b.min(c)), a.max(b.max(c))),
-            center: (a + b + c) / 3.0,
-        }
+            ..Default::default()
+        };
+
+        triangle.update_bounds(vertices);
+        triangle
     }
 
-    pub fn vertices(&self) -> [Vec3; 3] {
+    /// Recomputes the bounds and center from the vertices' current positions.
+    pub fn update_bounds(&mut self, vertices: &[Vertex]) {
+        let [a, b, c] = self
+            .indices()
+            .map(|index| vertices[index as usize].position);
+
+        self.bounds = BoundingVolume::new(a.min(b.min(c)), a.max(b.max(c)));
+        self.center = (a + b + c) / 3.0;
+    }
+
+    pub fn indices(&self) -> [u32; 3] {
         [self.a, self.b, self.c]
     }
 }
@@ -176,13 +218,107 @@ impl AsBoundingVolume for Triangle {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: f32,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Transform {
+    pub fn new(translation: Vec3, rotation: Quat, scale: f32) -> Self {
+        Self {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(
+            Vec3::splat(self.scale),
+            self.rotation,
+            self.translation,
+        )
+    }
+}
+
+/// The triangles of a mesh file, in the file's own space. They're stored as a contiguous range of the
+/// object list's triangles, and placed in the scene by mesh instances.
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    pub path: PathBuf,
+    pub triangle_offset: u32,
+    pub triangle_count: u32,
+    /// The mesh's vertices are a contiguous range of the object list's vertices too.
+    pub vertex_offset: u32,
+    pub vertex_count: u32,
+    /// The object list's vertex version when the mesh's vertices last moved, so that only the BVHs of
+    /// meshes that moved are refitted.
+    pub vertex_version: u32,
+    pub bounds: BoundingVolume,
+    /// Cameras defined in the mesh file, in the file's space.
+    pub cameras: Vec<GltfCamera>,
+}
+
+impl Mesh {
+    pub fn triangle_range(&self) -> Range<usize> {
+        let start = self.triangle_offset as usize;
+        start..start + self.triangle_count as usize
+    }
+
+    pub fn vertex_range(&self) -> Range<usize> {
+        let start = self.vertex_offset as usize;
+        start..start + self.vertex_count as usize
+    }
+
+    /// Whether the mesh's vertices moved after the object list's vertex version was `vertex_version`.
+    pub fn moved_since(&self, vertex_version: u32) -> bool {
+        self.vertex_version > vertex_version
+    }
+}
+
+/// A placement of a mesh in the scene. Instances of the same mesh share its triangles.
+#[derive(Debug, Clone, Copy)]
+pub struct MeshInstance {
+    /// Index of the mesh in the object list.
+    pub mesh: u32,
+    pub transform: Transform,
+    /// Overrides the materials defined in the mesh file.
+    pub material: Option<u32>,
+}
+
 pub struct ObjectList {
     spheres: Vec<Sphere>,
     planes: Vec<Plane>,
     aabbs: Vec<Aabb>,
+    vertices: Vec<Vertex>,
     triangles: Vec<Triangle>,
+    meshes: Vec<Mesh>,
+    mesh_instances: Vec<MeshInstance>,
+    lights: Vec<Light>,
+    grid_volumes: Vec<GridVolume>,
+    materials: MaterialList,
+    textures: TextureList,
+    grids: GridList,
 
     version: u32,
+    /// Only changes when meshes or their triangles change, and not when they're instanced or moved.
+    mesh_version: u32,
+    /// Changes when vertices move without triangles being added or removed, which only needs the
+    /// meshes' BVHs to be refitted.
+    vertex_version: u32,
 }
 
 impl ObjectList {
@@ -191,22 +327,43 @@ impl ObjectList {
             spheres: Vec::new(),
             planes: Vec::new(),
             aabbs: Vec::new(),
+            vertices: Vec::new(),
             triangles: Vec::new(),
+            meshes: Vec::new(),
+            mesh_instances: Vec::new(),
+            lights: Vec::new(),
+            grid_volumes: Vec::new(),
+            materials: MaterialList::new(),
+            textures: TextureList::new(),
+            grids: GridList::new(),
             version: 0,
+            mesh_version: 0,
+            vertex_version: 0,
         }
     }
 
+    pub fn clear(&mut self) {
+        self.version += 1;
+        self.mesh_version += 1;
+
+        self.spheres.clear();
+        self.planes.clear();
+        self.aabbs.clear();
+        self.vertices.clear();
+        self.triangles.clear();
+        self.meshes.clear();
+        self.mesh_instances.clear();
+        self.lights.clear();
+        self.grid_volumes.clear();
+        self.materials.clear();
+        self.textures.clear();
+        self.grids.clear();
+    }
+
     pub fn cubeception(&mut self, albedo: Vec3, position: Vec3, radius: f32, ior: f32, depth: u32) {
         self.version += 1;
 
-        let material = Material {
-            albedo,
-            ty: MaterialType::Dielectric,
-            emission: Vec3::ZERO,
-            roughness: 0.0,
-            ior,
-            g: 0.0,
-        };
+        let material = self.materials.push(Material::dielectric(albedo, 0.0, ior));
 
         let mut radius = radius;
 
@@ -241,22 +398,16 @@ impl ObjectList {
 
         let center = Vec3::new(0.0, 30.0, 0.0);
 
-        // self.push_sphere(Sphere::new(
-        //     center,
-        //     7.5,
-        //     Material {
-        //         albedo: Vec3::ONE,
-        //         ty: MaterialType::Volume,
-        //         g: 0.75,
-        //         ..Default::default()
-        //     },
-        // ));
+        // a haze around the center sphere
+        let haze = self
+            .materials
+            .push(Material::volume(Vec3::ZERO, Vec3::splat(0.05), 0.75));
+        self.push_sphere(Sphere::new(center, 7.5, haze));
 
-        self.push_sphere(Sphere::new(
-            center,
-            5.0,
-            Material::lambertian(Vec3::new(0.5, 1.0, 0.2)),
-        ));
+        let material = self
+            .materials
+            .push(Material::lambertian(Vec3::new(0.5, 1.0, 0.2)));
+        self.push_sphere(Sphere::new(center, 5.0, material));
 
         let mut rng = rand::thread_rng();
 
@@ -271,29 +422,25 @@ impl ObjectList {
 
             let radius = 1.0;
 
-            self.push_sphere(Sphere::new(center, radius, Material::random()));
+            let material = self.materials.push(Material::random());
+            self.push_sphere(Sphere::new(center, radius, material));
         }
     }
 
     pub fn bvh_test_scene(&mut self) {
-        self.version += 1;
+        self.clear();
 
-        self.spheres.clear();
-        self.planes.clear();
-        self.aabbs.clear();
-        self.triangles.clear();
+        let material = self
+            .materials
+            .push(Material::metal(Vec3::new(1.0, 0.5, 0.25), 0.05));
 
-        let triangles = util::gltf::load_triangles_from_gltf(
+        self.push_mesh(
             "assets/meshes/suzanne",
-            Vec3::new(0.0, -1.5, -0.25),
-            Quat::IDENTITY,
-            1.0,
-            Material::metal(Vec3::new(1.0, 0.5, 0.25), 0.05),
+            Transform::new(Vec3::new(0.0, -1.5, -0.25), Quat::IDENTITY, 1.0),
+            Some(material),
         )
         .unwrap();
 
-        self.triangles.extend_from_slice(&triangles);
-
         // self.planes.push(Plane::new(
         //     Vec3::Y,
         //     Vec3::new(0.0, -3.0, 0.0),
@@ -302,38 +449,29 @@ impl ObjectList {
     }
 
     pub fn random_scene(&mut self) {
-        self.version += 1;
+        self.clear();
 
-        self.spheres.clear();
-        self.planes.clear();
-        self.aabbs.clear();
-        self.triangles.clear();
+        let ground = self.materials.push(Material {
+            ty: MaterialType::Lambertian,
+            albedo: Vec3::ONE,
+            emission: Vec3::ZERO,
+            roughness: 0.0,
+            ior: 0.0,
+            g: 0.0,
+            ..Default::default()
+        });
+
+        let water = self
+            .materials
+            .push(Material::dielectric(Vec3::ONE, 0.1, 1.05));
 
         self.push_plane(Plane::new(
             Vec3::Y,
             Vec3::ZERO - PAD_THICKNESS * 2.5,
-            Material {
-                ty: MaterialType::Lambertian,
-                albedo: Vec3::ONE,
-                emission: Vec3::ZERO,
-                roughness: 0.0,
-                ior: 0.0,
-                g: 0.0,
-            },
+            ground,
         ));
 
-        self.push_plane(Plane::new(
-            Vec3::Y,
-            Vec3::ZERO,
-            Material {
-                ty: MaterialType::Dielectric,
-                albedo: Vec3::ONE,
-                emission: Vec3::ZERO,
-                roughness: 0.1,
-                ior: 1.05,
-                g: 0.0,
-            },
-        ));
+        self.push_plane(Plane::new(Vec3::Y, Vec3::ZERO, water));
 
         let region_size = 7;
         let regions_radius = 3;
@@ -361,12 +499,13 @@ impl ObjectList {
                 match rand::thread_rng().gen_range(0..3) {
                     0 => {
                         let radius = rand_radius();
+                        let material = self.materials.push(Material::random());
 
                         self.push_sphere(
                             Sphere::new(
                                 Vec3::new(x + offset_x, radius, z + offset_z),
                                 radius,
-                                Material::random(),
+                                material,
                             )
                             .pad(),
                         )
@@ -375,6 +514,7 @@ impl ObjectList {
                         let radius_x = rand_radius();
                         let radius_y = rand_radius();
                         let radius_z = rand_radius();
+                        let material = self.materials.push(Material::random());
 
                         self.push_aabb(
                             Aabb::new(
@@ -384,7 +524,7 @@ impl ObjectList {
                                     2.0 * radius_y,
                                     z + offset_z + radius_z,
                                 ),
-                                Material::random(),
+                                material,
                             )
                             .pad(),
                         )
@@ -394,18 +534,15 @@ impl ObjectList {
                         // let angle = rand::thread_rng().gen_range(0.0..f32::consts::TAU);
                         // let rotation = Quat::from_rotation_y(angle);
 
-                        // let triangles = util::gltf::load_triangles_from_gltf(
+                        // // every instance shares the triangles and BVH of the first one
+                        // let material = self.materials.push(Material::random());
+
+                        // self.push_mesh(
                         //     "assets/meshes/suzanne",
-                        //     Vec3::new(x + offset_x, scale, z + offset_z),
-                        //     rotation,
-                        //     scale,
-                        //     Material::random(),
+                        //     Transform::new(Vec3::new(x + offset_x, scale, z + offset_z), rotation, scale),
+                        //     Some(material),
                         // )
                         // .unwrap();
-
-                        // for triangle in triangles {
-                        //     self.push_triangle(triangle);
-                        // }
                     }
                     _ => unreachable!(),
                 }
@@ -428,25 +565,122 @@ impl ObjectList {
         self.aabbs.push(aabb);
     }
 
-    pub fn push_triangle(&mut self, triangle: Triangle) {
+    pub fn push_light(&mut self, light: Light) {
         self.version += 1;
-        self.triangles.push(triangle);
+        self.lights.push(light);
     }
 
-    pub fn push_mesh<P: AsRef<Path>>(&mut self, path: P) -> Result<(), GltfLoadError> {
-        self.version += 1;
+    /// Places a volume filled by a voxel grid file in the scene. The file is only loaded the first time
+    /// it's used, later volumes share its grid.
+    pub fn push_grid_volume<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        transform: Transform,
+        material: u32,
+    ) -> Result<(), GridLoadError> {
+        let grid = self.grids.push_file(path)?;
 
-        let triangles = util::gltf::load_triangles_from_glb(
-            "assets/meshes/dragon.glb",
-            Vec3::new(0.0, -1.5, -0.25),
-            Quat::from_rotation_y(f32::consts::PI) * Quat::from_rotation_x(f32::consts::PI / 2.0),
-            0.5,
-            Material::dielectric(Vec3::new(1.0, 0.75, 0.2), 0.05, 1.5),
-        )?;
+        self.version += 1;
+        self.grid_volumes.push(GridVolume {
+            grid,
+            transform,
+            material,
+        });
 
         Ok(())
     }
 
+    /// Adds indexed triangles with a single material. Every three indices form a triangle, and are
+    /// relative to the given vertices.
+    fn push_triangles(&mut self, vertices: &[Vertex], indices: &[u32], material: u32) {
+        self.version += 1;
+        self.mesh_version += 1;
+
+        let vertex_offset = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(vertices);
+
+        self.triangles.extend(indices.chunks_exact(3).map(|chunk| {
+            Triangle::new(
+                [chunk[0], chunk[1], chunk[2]].map(|index| index + vertex_offset),
+                material,
+                &self.vertices,
+            )
+        }));
+    }
+
+    /// Places an instance of a `.glb` or `.gltf` file, or of a directory containing a `.gltf` file,
+    /// in the scene. The file is only loaded the first time it's used, later instances share its
+    /// triangles. Without a `material`, the materials defined in the file are used.
+    ///
+    /// Returns the cameras defined in the file, placed with the instance.
+    pub fn push_mesh<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        transform: Transform,
+        material: Option<u32>,
+    ) -> Result<Vec<GltfCamera>, GltfLoadError> {
+        let path = path.as_ref();
+
+        let mesh = match self.meshes.iter().position(|mesh| mesh.path == path) {
+            Some(index) => index as u32,
+            None => self.load_mesh(path)?,
+        };
+
+        self.version += 1;
+        self.mesh_instances.push(MeshInstance {
+            mesh,
+            transform,
+            material,
+        });
+
+        let matrix = transform.matrix();
+
+        Ok(self.meshes[mesh as usize]
+            .cameras
+            .iter()
+            .map(|camera| camera.transform(matrix))
+            .collect())
+    }
+
+    fn load_mesh(&mut self, path: &Path) -> Result<u32, GltfLoadError> {
+        let scene = util::gltf::load_gltf_scene(path, &mut self.materials, &mut self.textures)?;
+
+        Ok(self.add_mesh(path, scene))
+    }
+
+    /// Adds the meshes of a loaded file as a single mesh, which instances of `path` then place
+    /// without loading the file again. Returns the index of the mesh.
+    pub(in crate::state) fn add_mesh(&mut self, path: &Path, scene: GltfScene) -> u32 {
+        let GltfScene { meshes, cameras } = scene;
+
+        let triangle_offset = self.triangles.len();
+        let vertex_offset = self.vertices.len();
+
+        for mesh in meshes {
+            self.push_triangles(&mesh.vertices, &mesh.indices, mesh.material);
+        }
+
+        let mut bounds = BoundingVolume::EMPTY;
+
+        for triangle in &self.triangles[triangle_offset..] {
+            bounds.grow(triangle);
+        }
+
+        self.mesh_version += 1;
+        self.meshes.push(Mesh {
+            path: path.to_owned(),
+            triangle_offset: triangle_offset as u32,
+            triangle_count: (self.triangles.len() - triangle_offset) as u32,
+            vertex_offset: vertex_offset as u32,
+            vertex_count: (self.vertices.len() - vertex_offset) as u32,
+            vertex_version: self.vertex_version,
+            bounds,
+            cameras,
+        });
+
+        self.meshes.len() as u32 - 1
+    }
+
     pub fn spheres(&self) -> &[Sphere] {
         &self.spheres
     }
@@ -470,10 +704,134 @@ impl ObjectList {
 
     pub fn triangles_mut(&mut self) -> &mut [Triangle] {
         self.version += 1;
+        self.mesh_version += 1;
         &mut self.triangles
     }
 
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    /// Moving vertices doesn't change the mesh version, so the meshes' BVHs are refitted instead of
+    /// rebuilt. Vertices are in the meshes' own space.
+    pub fn vertices_mut(&mut self) -> &mut [Vertex] {
+        self.version += 1;
+        self.vertex_version += 1;
+
+        for mesh in &mut self.meshes {
+            mesh.vertex_version = self.vertex_version;
+        }
+
+        &mut self.vertices
+    }
+
+    /// The vertices of a single mesh, see `vertices_mut`. Only the BVH of this mesh is refitted.
+    pub fn mesh_vertices_mut(&mut self, mesh: u32) -> &mut [Vertex] {
+        self.version += 1;
+        self.vertex_version += 1;
+
+        let mesh = &mut self.meshes[mesh as usize];
+        mesh.vertex_version = self.vertex_version;
+
+        &mut self.vertices[mesh.vertex_range()]
+    }
+
+    /// Recomputes the bounds of every triangle and mesh after vertices were moved. Only the BVHs use
+    /// the bounds, so this doesn't change any version.
+    pub fn update_triangle_bounds(&mut self) {
+        for triangle in &mut self.triangles {
+            triangle.update_bounds(&self.vertices);
+        }
+
+        for mesh in &mut self.meshes {
+            mesh.bounds = BoundingVolume::EMPTY;
+
+            for triangle in &self.triangles[mesh.triangle_range()] {
+                mesh.bounds.grow(triangle);
+            }
+        }
+    }
+
+    pub fn meshes(&self) -> &[Mesh] {
+        &self.meshes
+    }
+
+    pub fn mesh_instances(&self) -> &[MeshInstance] {
+        &self.mesh_instances
+    }
+
+    /// Whether any instance of the mesh emits light, through the mesh's own materials or the material
+    /// overriding them.
+    pub fn mesh_is_emissive(&self, mesh: u32) -> bool {
+        let emissive = |material: u32| {
+            self.materials
+                .get(material)
+                .is_some_and(|material| light::luminance(material.emission) > 0.0)
+        };
+
+        // the triangles are only checked once, for the first instance that doesn't override them
+        let mut triangles_emissive = None;
+
+        self.mesh_instances
+            .iter()
+            .filter(|instance| instance.mesh == mesh)
+            .any(|instance| match instance.material {
+                Some(material) => emissive(material),
+                None => *triangles_emissive.get_or_insert_with(|| {
+                    self.triangles[self.meshes[mesh as usize].triangle_range()]
+                        .iter()
+                        .any(|triangle| emissive(triangle.material))
+                }),
+            })
+    }
+
+    /// Moving instances or changing their materials doesn't change the mesh version, so only the
+    /// top-level BVH has to be refitted.
+    pub fn mesh_instances_mut(&mut self) -> &mut [MeshInstance] {
+        self.version += 1;
+        &mut self.mesh_instances
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    pub fn grid_volumes(&self) -> &[GridVolume] {
+        &self.grid_volumes
+    }
+
+    pub fn materials(&self) -> &MaterialList {
+        &self.materials
+    }
+
+    /// Materials can be edited without changing the object list's version, so that the geometry
+    /// doesn't have to be uploaded again or the BVH rebuilt.
+    pub fn materials_mut(&mut self) -> &mut MaterialList {
+        &mut self.materials
+    }
+
+    pub fn textures(&self) -> &TextureList {
+        &self.textures
+    }
+
+    pub fn textures_mut(&mut self) -> &mut TextureList {
+        self.version += 1;
+        &mut self.textures
+    }
+
+    pub fn grids(&self) -> &GridList {
+        &self.grids
+    }
+
     pub fn version(&self) -> u32 {
         self.version
     }
+
+    pub fn mesh_version(&self) -> u32 {
+        self.mesh_version
+    }
+
+    pub fn vertex_version(&self) -> u32 {
+        self.vertex_version
+    }
 }
@@ -0,0 +1,821 @@
+use glam::{Mat4, Vec2, Vec3};
+
+use super::{
+    bvh::{AccelerationStructure, BoundingVolume, BvhNode, BvhPrimitive, PrimitiveType},
+    material::NO_MATERIAL,
+    object::{Aabb, ObjectList, Plane, Sphere, Vertex},
+};
+
+/// A ray cast against the scene on the cpu. The direction doesn't have to be normalized; distances
+/// along the ray are in multiples of it.
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub pos: Vec3,
+    pub dir: Vec3,
+}
+
+impl Ray {
+    pub fn new(pos: Vec3, dir: Vec3) -> Self {
+        Self { pos, dir }
+    }
+
+    pub fn at(&self, distance: f32) -> Vec3 {
+        self.pos + self.dir * distance
+    }
+
+    /// The direction isn't normalized after transforming, so that distances along the ray stay the
+    /// same in both spaces.
+    fn transform(&self, matrix: Mat4) -> Self {
+        Self {
+            pos: matrix.transform_point3(self.pos),
+            dir: matrix.transform_vector3(self.dir),
+        }
+    }
+}
+
+/// The object a ray hit, by its index in the object list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectId {
+    Sphere(u32),
+    Plane(u32),
+    Aabb(u32),
+    MeshInstance(u32),
+}
+
+#[derive(Debug, Clone, Copy)]
+#[allow(unused)]
+pub struct Hit {
+    pub object: ObjectId,
+    /// Index of the triangle in the object list's triangles, if the object is a mesh instance.
+    pub primitive: Option<u32>,
+    pub distance: f32,
+    pub position: Vec3,
+    /// Faces against the ray, like the normals the shaders trace with.
+    pub normal: Vec3,
+    /// Whether the ray hit the surface from the outside.
+    pub front_face: bool,
+    /// Only triangles have texture coordinates.
+    pub uv: Option<Vec2>,
+    /// Id of the material in the object list's materials, after mesh instances' overrides.
+    pub material: u32,
+}
+
+/// Where a ray hit a surface, before it's known which object the surface belongs to. These mirror
+/// the intersection functions in `intersect.wgsl`.
+struct Intersection {
+    distance: f32,
+    normal: Vec3,
+    front_face: bool,
+    uv: Option<Vec2>,
+}
+
+impl Intersection {
+    /// Flips the outward normal to face against the ray.
+    fn facing(ray: Ray, distance: f32, outward_normal: Vec3, uv: Option<Vec2>) -> Self {
+        let front_face = ray.dir.dot(outward_normal) < 0.0;
+
+        Self {
+            distance,
+            normal: match front_face {
+                true => outward_normal,
+                false => -outward_normal,
+            },
+            front_face,
+            uv,
+        }
+    }
+
+    fn into_hit(self, ray: Ray, object: ObjectId, primitive: Option<u32>, material: u32) -> Hit {
+        Hit {
+            object,
+            primitive,
+            distance: self.distance,
+            position: ray.at(self.distance),
+            normal: self.normal,
+            front_face: self.front_face,
+            uv: self.uv,
+            material,
+        }
+    }
+}
+
+impl Sphere {
+    fn intersect(&self, ray: Ray) -> Option<Intersection> {
+        let origin_to_center = ray.pos - self.center();
+
+        let a = ray.dir.dot(ray.dir);
+        let b = origin_to_center.dot(ray.dir);
+        let c = origin_to_center.dot(origin_to_center) - self.radius() * self.radius();
+
+        let determinant = b * b - a * c;
+
+        if determinant < 0.0 {
+            return None;
+        }
+
+        let determinant_sqrt = determinant.sqrt();
+        let near = (-b - determinant_sqrt) / a;
+        let far = (-b + determinant_sqrt) / a;
+
+        // rays starting inside the sphere hit its far side
+        let distance = if near >= 0.0 { near } else { far };
+
+        if distance < 0.0 {
+            return None;
+        }
+
+        let outward_normal = (ray.at(distance) - self.center()).normalize();
+
+        Some(Intersection::facing(ray, distance, outward_normal, None))
+    }
+}
+
+impl Plane {
+    fn intersect(&self, ray: Ray) -> Option<Intersection> {
+        let denom = self.normal().dot(ray.dir);
+
+        if denom.abs() < 1e-6 {
+            return None;
+        }
+
+        let distance = self.normal().dot(self.point() - ray.pos) / denom;
+
+        if distance < 0.0 {
+            return None;
+        }
+
+        // planes have no inside
+        Some(Intersection {
+            distance,
+            normal: self.normal() * -denom.signum(),
+            front_face: true,
+            uv: None,
+        })
+    }
+}
+
+impl Aabb {
+    fn intersect(&self, ray: Ray) -> Option<Intersection> {
+        let inside = ray.pos.cmpge(self.min()).all() && ray.pos.cmple(self.max()).all();
+
+        let t_min = (self.min() - ray.pos) / ray.dir;
+        let t_max = (self.max() - ray.pos) / ray.dir;
+
+        let t1 = t_min.min(t_max);
+        let t2 = t_min.max(t_max);
+
+        let t_near = t1.max_element();
+        let t_far = t2.min_element();
+
+        // the normal is along the axis whose slab the ray crosses last, or first when leaving
+        let axis_normal = |t: Vec3, distance: f32| {
+            Vec3::select(t.cmpeq(Vec3::splat(distance)), Vec3::ONE, Vec3::ZERO) * -ray.dir.signum()
+        };
+
+        if inside {
+            return Some(Intersection {
+                distance: t_far,
+                normal: axis_normal(t2, t_far),
+                front_face: false,
+                uv: None,
+            });
+        }
+
+        if t_near > t_far || t_far < 0.0 {
+            return None;
+        }
+
+        Some(Intersection {
+            distance: t_near,
+            normal: axis_normal(t1, t_near),
+            front_face: true,
+            uv: None,
+        })
+    }
+}
+
+fn intersect_triangle(ray: Ray, [a, b, c]: [Vertex; 3]) -> Option<Intersection> {
+    let edge1 = b.position - a.position;
+    let edge2 = c.position - a.position;
+
+    let h = ray.dir.cross(edge2);
+    let determinant = h.dot(edge1);
+
+    if determinant.abs() < 1e-6 {
+        return None;
+    }
+
+    let f = 1.0 / determinant;
+    let s = ray.pos - a.position;
+    let u = f * s.dot(h);
+
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(edge1);
+    let v = f * ray.dir.dot(q);
+
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let distance = f * edge2.dot(q);
+
+    if distance < 1e-6 {
+        return None;
+    }
+
+    let uv = a.uv * (1.0 - u - v) + b.uv * u + c.uv * v;
+
+    Some(Intersection::facing(
+        ray,
+        distance,
+        edge1.cross(edge2).normalize(),
+        Some(uv),
+    ))
+}
+
+impl BoundingVolume {
+    /// The distance along the ray to where it enters the bounds, or zero if it starts inside them.
+    fn intersect(self, ray: Ray) -> Option<f32> {
+        if self.empty {
+            return None;
+        }
+
+        let inverse_dir = ray.dir.recip();
+
+        let t_min = (self.min - ray.pos) * inverse_dir;
+        let t_max = (self.max - ray.pos) * inverse_dir;
+
+        let t_near = t_min.min(t_max).max_element().max(0.0);
+        let t_far = t_min.max(t_max).min_element();
+
+        (t_near <= t_far).then_some(t_near)
+    }
+}
+
+/// Traverses the BVH whose root is at `root` in `nodes`, nearest child first, and returns the
+/// closest hit nearer than `max_distance`. `intersect` is called with the index of every object in
+/// the leaves the ray reaches, and the distance of the closest hit so far.
+fn traverse(
+    nodes: &[BvhNode],
+    root: u32,
+    ray: Ray,
+    max_distance: f32,
+    mut intersect: impl FnMut(u32, f32) -> Option<Hit>,
+) -> Option<Hit> {
+    let mut closest_hit = None;
+    let mut closest_distance = max_distance;
+
+    let mut stack = Vec::new();
+
+    if let Some(distance) = nodes[root as usize].bounds.intersect(ray) {
+        stack.push((root, distance));
+    }
+
+    while let Some((index, distance)) = stack.pop() {
+        // a closer hit may have been found since the node was pushed
+        if distance >= closest_distance {
+            continue;
+        }
+
+        let node = nodes[index as usize];
+
+        if node.child_node == 0 {
+            for object in node.start_index..node.start_index + node.len {
+                if let Some(hit) = intersect(object, closest_distance) {
+                    if hit.distance < closest_distance {
+                        closest_distance = hit.distance;
+                        closest_hit = Some(hit);
+                    }
+                }
+            }
+
+            continue;
+        }
+
+        let mut children: Vec<_> = [node.child_node, node.child_node + 1]
+            .into_iter()
+            .filter_map(|child| {
+                let distance = nodes[child as usize].bounds.intersect(ray)?;
+                (distance < closest_distance).then_some((child, distance))
+            })
+            .collect();
+
+        // push the nearest child last, so it's visited first
+        children.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        stack.extend(children);
+    }
+
+    closest_hit
+}
+
+impl ObjectList {
+    /// Casts a ray against every object and returns the closest hit, traversing the acceleration
+    /// structure like the raytracer does. The acceleration structure has to be up to date with the
+    /// object list, see `AccelerationStructure::update`.
+    pub fn raycast(&self, acceleration_structure: &AccelerationStructure, ray: Ray) -> Option<Hit> {
+        let mut closest_hit = None;
+        let mut closest_distance = f32::INFINITY;
+
+        // planes are unbounded, so they aren't in the BVH
+        for (index, plane) in self.planes().iter().enumerate() {
+            if let Some(intersection) = plane.intersect(ray) {
+                if intersection.distance < closest_distance {
+                    closest_distance = intersection.distance;
+                    closest_hit = Some(intersection.into_hit(
+                        ray,
+                        ObjectId::Plane(index as u32),
+                        None,
+                        plane.material(),
+                    ));
+                }
+            }
+        }
+
+        let primitives = acceleration_structure.primitives();
+
+        traverse(
+            acceleration_structure.nodes(),
+            acceleration_structure.tlas_root(),
+            ray,
+            closest_distance,
+            |index, max_distance| {
+                self.raycast_primitive(
+                    acceleration_structure,
+                    primitives[index as usize],
+                    ray,
+                    max_distance,
+                )
+            },
+        )
+        .or(closest_hit)
+    }
+
+    fn raycast_primitive(
+        &self,
+        acceleration_structure: &AccelerationStructure,
+        primitive: BvhPrimitive,
+        ray: Ray,
+        max_distance: f32,
+    ) -> Option<Hit> {
+        let index = primitive.index;
+
+        match primitive.ty {
+            PrimitiveType::Sphere => {
+                let sphere = self.spheres()[index as usize];
+
+                sphere.intersect(ray).map(|intersection| {
+                    intersection.into_hit(ray, ObjectId::Sphere(index), None, sphere.material())
+                })
+            }
+            PrimitiveType::Aabb => {
+                let aabb = self.aabbs()[index as usize];
+
+                aabb.intersect(ray).map(|intersection| {
+                    intersection.into_hit(ray, ObjectId::Aabb(index), None, aabb.material())
+                })
+            }
+            PrimitiveType::MeshInstance => {
+                let instance = acceleration_structure.instances()[index as usize];
+                let object_ray = ray.transform(instance.world_to_object);
+
+                let mut hit = traverse(
+                    acceleration_structure.nodes(),
+                    instance.blas_root,
+                    object_ray,
+                    max_distance,
+                    |triangle_index, _| {
+                        let triangle = self.triangles()[triangle_index as usize];
+                        let vertices = triangle
+                            .indices()
+                            .map(|vertex| self.vertices()[vertex as usize]);
+
+                        intersect_triangle(object_ray, vertices).map(|intersection| {
+                            intersection.into_hit(
+                                ray,
+                                ObjectId::MeshInstance(index),
+                                Some(triangle_index),
+                                triangle.material,
+                            )
+                        })
+                    },
+                )?;
+
+                // instances are only rotated and uniformly scaled, so normals can be transformed
+                // like directions
+                hit.normal = instance
+                    .object_to_world
+                    .transform_vector3(hit.normal)
+                    .normalize();
+
+                if instance.material != NO_MATERIAL {
+                    hit.material = instance.material;
+                }
+
+                Some(hit)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use glam::{EulerRot, Quat};
+
+    use crate::{
+        state::{bvh::BvhBuildSettings, object::Transform},
+        util::gltf::{GltfMesh, GltfScene},
+    };
+
+    use super::*;
+
+    const QUAD_PATH: &str = "quad.glb";
+    const OTHER_QUAD_PATH: &str = "other_quad.glb";
+    const QUAD_MATERIAL: u32 = 5;
+
+    /// Adds a square of two triangles spanning -1 to 1 on x and y, facing +z, as the mesh loaded
+    /// from `QUAD_PATH`.
+    fn add_quad(object_list: &mut ObjectList) {
+        add_quad_mesh(object_list, QUAD_PATH);
+    }
+
+    /// Adds the square of `add_quad` as the mesh loaded from `path`.
+    fn add_quad_mesh(object_list: &mut ObjectList, path: &str) {
+        let vertices = vec![
+            Vertex::new(Vec3::new(-1.0, -1.0, 0.0), Vec2::new(0.0, 0.0)),
+            Vertex::new(Vec3::new(1.0, -1.0, 0.0), Vec2::new(1.0, 0.0)),
+            Vertex::new(Vec3::new(1.0, 1.0, 0.0), Vec2::new(1.0, 1.0)),
+            Vertex::new(Vec3::new(-1.0, 1.0, 0.0), Vec2::new(0.0, 1.0)),
+        ];
+
+        object_list.add_mesh(
+            Path::new(path),
+            GltfScene {
+                meshes: vec![GltfMesh {
+                    vertices,
+                    indices: vec![0, 1, 2, 0, 2, 3],
+                    material: QUAD_MATERIAL,
+                }],
+                cameras: Vec::new(),
+            },
+        );
+    }
+
+    fn push_quad_instance(
+        object_list: &mut ObjectList,
+        transform: Transform,
+        material: Option<u32>,
+    ) {
+        object_list
+            .push_mesh(QUAD_PATH, transform, material)
+            .unwrap();
+    }
+
+    fn raycast(object_list: &mut ObjectList, ray: Ray) -> Option<Hit> {
+        let acceleration_structure =
+            AccelerationStructure::from_objects(object_list, BvhBuildSettings::default(), false);
+
+        object_list.raycast(&acceleration_structure, ray)
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    fn assert_vec_close(a: Vec3, b: Vec3) {
+        assert!(a.abs_diff_eq(b, 1e-4), "{} != {}", a, b);
+    }
+
+    /// The closest hit of every object, intersecting each one without the acceleration structure.
+    fn brute_force(object_list: &ObjectList, ray: Ray) -> Option<(ObjectId, Option<u32>, f32)> {
+        let mut hits = Vec::new();
+
+        for (index, sphere) in object_list.spheres().iter().enumerate() {
+            hits.extend(
+                sphere
+                    .intersect(ray)
+                    .map(|hit| (ObjectId::Sphere(index as u32), None, hit.distance)),
+            );
+        }
+
+        for (index, plane) in object_list.planes().iter().enumerate() {
+            hits.extend(
+                plane
+                    .intersect(ray)
+                    .map(|hit| (ObjectId::Plane(index as u32), None, hit.distance)),
+            );
+        }
+
+        for (index, aabb) in object_list.aabbs().iter().enumerate() {
+            hits.extend(
+                aabb.intersect(ray)
+                    .map(|hit| (ObjectId::Aabb(index as u32), None, hit.distance)),
+            );
+        }
+
+        for (index, instance) in object_list.mesh_instances().iter().enumerate() {
+            let object_ray = ray.transform(instance.transform.matrix().inverse());
+            let mesh = &object_list.meshes()[instance.mesh as usize];
+
+            for triangle_index in mesh.triangle_range() {
+                let vertices = object_list.triangles()[triangle_index]
+                    .indices()
+                    .map(|vertex| object_list.vertices()[vertex as usize]);
+
+                hits.extend(intersect_triangle(object_ray, vertices).map(|hit| {
+                    (
+                        ObjectId::MeshInstance(index as u32),
+                        Some(triangle_index as u32),
+                        hit.distance,
+                    )
+                }));
+            }
+        }
+
+        hits.into_iter()
+            .min_by(|(_, _, a), (_, _, b)| a.total_cmp(b))
+    }
+
+    #[test]
+    fn sphere_hit_and_miss() {
+        let mut object_list = ObjectList::new();
+        object_list.push_sphere(Sphere::new(Vec3::new(0.0, 0.0, -5.0), 1.0, 3));
+
+        let hit = raycast(&mut object_list, Ray::new(Vec3::ZERO, Vec3::NEG_Z)).unwrap();
+
+        assert_eq!(hit.object, ObjectId::Sphere(0));
+        assert_eq!(hit.primitive, None);
+        assert_eq!(hit.material, 3);
+        assert!(hit.front_face);
+        assert_close(hit.distance, 4.0);
+        assert_vec_close(hit.position, Vec3::new(0.0, 0.0, -4.0));
+        assert_vec_close(hit.normal, Vec3::Z);
+
+        // from inside, the far side is hit from behind
+        let hit = raycast(
+            &mut object_list,
+            Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::NEG_Z),
+        )
+        .unwrap();
+
+        assert!(!hit.front_face);
+        assert_close(hit.distance, 1.0);
+        assert_vec_close(hit.normal, Vec3::Z);
+
+        assert!(raycast(&mut object_list, Ray::new(Vec3::ZERO, Vec3::X)).is_none());
+        assert!(raycast(&mut object_list, Ray::new(Vec3::ZERO, Vec3::Z)).is_none());
+    }
+
+    #[test]
+    fn plane_hit_and_miss() {
+        let mut object_list = ObjectList::new();
+        object_list.push_plane(Plane::new(Vec3::Y, Vec3::new(0.0, -2.0, 0.0), 4));
+
+        let hit = raycast(&mut object_list, Ray::new(Vec3::ZERO, Vec3::NEG_Y)).unwrap();
+
+        assert_eq!(hit.object, ObjectId::Plane(0));
+        assert_eq!(hit.primitive, None);
+        assert_eq!(hit.material, 4);
+        assert_close(hit.distance, 2.0);
+        assert_vec_close(hit.normal, Vec3::Y);
+
+        // parallel to the plane, and away from it
+        assert!(raycast(&mut object_list, Ray::new(Vec3::ZERO, Vec3::X)).is_none());
+        assert!(raycast(&mut object_list, Ray::new(Vec3::ZERO, Vec3::Y)).is_none());
+    }
+
+    #[test]
+    fn aabb_hit_and_miss() {
+        let mut object_list = ObjectList::new();
+        object_list.push_aabb(Aabb::new(
+            Vec3::new(-1.0, -1.0, -6.0),
+            Vec3::new(1.0, 1.0, -4.0),
+            2,
+        ));
+
+        let hit = raycast(&mut object_list, Ray::new(Vec3::ZERO, Vec3::NEG_Z)).unwrap();
+
+        assert_eq!(hit.object, ObjectId::Aabb(0));
+        assert_eq!(hit.primitive, None);
+        assert_eq!(hit.material, 2);
+        assert!(hit.front_face);
+        assert_close(hit.distance, 4.0);
+        assert_vec_close(hit.normal, Vec3::Z);
+
+        assert!(raycast(
+            &mut object_list,
+            Ray::new(Vec3::new(3.0, 0.0, 0.0), Vec3::NEG_Z)
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn triangle_hit_and_miss() {
+        let mut object_list = ObjectList::new();
+        add_quad(&mut object_list);
+        push_quad_instance(
+            &mut object_list,
+            Transform::new(Vec3::new(0.0, 0.0, -3.0), Quat::IDENTITY, 1.0),
+            None,
+        );
+
+        let ray = Ray::new(Vec3::new(0.5, -0.5, 0.0), Vec3::NEG_Z);
+        let hit = raycast(&mut object_list, ray).unwrap();
+
+        assert_eq!(hit.object, ObjectId::MeshInstance(0));
+        assert_eq!(hit.material, QUAD_MATERIAL);
+        assert!(hit.front_face);
+        assert_close(hit.distance, 3.0);
+        assert_vec_close(hit.normal, Vec3::Z);
+
+        let uv = hit.uv.unwrap();
+        assert_close(uv.x, 0.75);
+        assert_close(uv.y, 0.25);
+
+        // the hit triangle is the one below the quad's diagonal
+        let triangle = object_list.triangles()[hit.primitive.unwrap() as usize];
+        let positions = triangle
+            .indices()
+            .map(|vertex| object_list.vertices()[vertex as usize].position);
+
+        assert!(positions.contains(&Vec3::new(1.0, -1.0, 0.0)));
+
+        assert!(raycast(
+            &mut object_list,
+            Ray::new(Vec3::new(3.0, 0.0, 0.0), Vec3::NEG_Z)
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn instance_material_overrides_triangles() {
+        let mut object_list = ObjectList::new();
+        add_quad(&mut object_list);
+        push_quad_instance(
+            &mut object_list,
+            Transform::new(Vec3::new(0.0, 0.0, -3.0), Quat::IDENTITY, 1.0),
+            Some(7),
+        );
+
+        let hit = raycast(&mut object_list, Ray::new(Vec3::ZERO, Vec3::NEG_Z)).unwrap();
+
+        assert_eq!(hit.material, 7);
+    }
+
+    #[test]
+    fn closest_hit_wins() {
+        let mut object_list = ObjectList::new();
+
+        // pushed farthest first, so the closest object isn't the first one in the list
+        for z in [-9.0, -6.0, -3.0] {
+            object_list.push_sphere(Sphere::new(Vec3::new(0.0, 0.0, z), 1.0, 0));
+        }
+
+        object_list.push_aabb(Aabb::new(
+            Vec3::new(-1.0, -1.0, -12.0),
+            Vec3::new(1.0, 1.0, -11.0),
+            0,
+        ));
+        object_list.push_plane(Plane::new(Vec3::Z, Vec3::new(0.0, 0.0, -20.0), 0));
+
+        let ray = Ray::new(Vec3::ZERO, Vec3::NEG_Z);
+        let hit = raycast(&mut object_list, ray).unwrap();
+
+        assert_eq!(hit.object, ObjectId::Sphere(2));
+        assert_close(hit.distance, 2.0);
+
+        // a plane in front of everything in the BVH
+        object_list.push_plane(Plane::new(Vec3::Z, Vec3::new(0.0, 0.0, -1.0), 0));
+
+        let hit = raycast(&mut object_list, ray).unwrap();
+
+        assert_eq!(hit.object, ObjectId::Plane(1));
+        assert_close(hit.distance, 1.0);
+    }
+
+    #[test]
+    fn transformed_instances_match_brute_force() {
+        let mut object_list = ObjectList::new();
+        add_quad(&mut object_list);
+
+        push_quad_instance(
+            &mut object_list,
+            Transform::new(Vec3::new(-1.5, 0.5, 0.0), Quat::from_rotation_y(0.4), 1.0),
+            None,
+        );
+        push_quad_instance(
+            &mut object_list,
+            Transform::new(
+                Vec3::new(1.5, -0.5, -1.0),
+                Quat::from_euler(EulerRot::XYZ, 0.3, -0.5, 0.8),
+                0.75,
+            ),
+            None,
+        );
+        push_quad_instance(
+            &mut object_list,
+            Transform::new(Vec3::new(0.0, 1.0, -3.0), Quat::from_rotation_x(-0.6), 2.0),
+            None,
+        );
+
+        object_list.push_sphere(Sphere::new(Vec3::new(0.3, -1.2, -2.0), 0.5, 0));
+        object_list.push_aabb(Aabb::new(
+            Vec3::new(-2.5, -2.0, -4.0),
+            Vec3::new(-1.5, -1.0, -3.5),
+            0,
+        ));
+
+        let acceleration_structure = AccelerationStructure::from_objects(
+            &mut object_list,
+            BvhBuildSettings::default(),
+            false,
+        );
+
+        let mut hit_count = 0;
+
+        for i in 0..16 {
+            for j in 0..16 {
+                // no direction is along an axis
+                let dir = Vec3::new((i as f32 - 7.5) * 0.06, (j as f32 - 7.5) * 0.06, -1.0);
+                let ray = Ray::new(Vec3::new(0.0, 0.0, 6.0), dir);
+
+                let hit = object_list.raycast(&acceleration_structure, ray);
+
+                match (hit, brute_force(&object_list, ray)) {
+                    (Some(hit), Some((object, primitive, distance))) => {
+                        assert_eq!(hit.object, object);
+                        assert_eq!(hit.primitive, primitive);
+                        assert_close(hit.distance, distance);
+                        hit_count += 1;
+                    }
+                    (None, None) => {}
+                    (hit, expected) => panic!("{:?} != {:?}", hit, expected),
+                }
+            }
+        }
+
+        // enough rays should hit something for the comparison to mean anything
+        assert!(hit_count >= 32, "only {} rays hit", hit_count);
+    }
+
+    #[test]
+    fn moving_one_mesh_refits_its_bvh_alone() {
+        let mut object_list = ObjectList::new();
+        add_quad(&mut object_list);
+        add_quad_mesh(&mut object_list, OTHER_QUAD_PATH);
+
+        push_quad_instance(
+            &mut object_list,
+            Transform::new(Vec3::new(0.0, 0.0, -3.0), Quat::IDENTITY, 1.0),
+            None,
+        );
+        object_list
+            .push_mesh(
+                OTHER_QUAD_PATH,
+                Transform::new(Vec3::new(4.0, 0.0, -3.0), Quat::IDENTITY, 1.0),
+                None,
+            )
+            .unwrap();
+
+        let mut acceleration_structure = AccelerationStructure::from_objects(
+            &mut object_list,
+            BvhBuildSettings::default(),
+            false,
+        );
+
+        let mesh_version = object_list.mesh_version();
+        let vertex_version = object_list.vertex_version();
+
+        // push the first quad two units further away
+        for vertex in object_list.mesh_vertices_mut(0) {
+            vertex.position.z -= 2.0;
+        }
+
+        assert_eq!(object_list.mesh_version(), mesh_version);
+        assert!(object_list.meshes()[0].moved_since(vertex_version));
+        assert!(!object_list.meshes()[1].moved_since(vertex_version));
+
+        acceleration_structure.update(&mut object_list);
+
+        let hit = object_list
+            .raycast(&acceleration_structure, Ray::new(Vec3::ZERO, Vec3::NEG_Z))
+            .unwrap();
+
+        assert_eq!(hit.object, ObjectId::MeshInstance(0));
+        assert_close(hit.distance, 5.0);
+
+        let hit = object_list
+            .raycast(
+                &acceleration_structure,
+                Ray::new(Vec3::new(4.0, 0.0, 0.0), Vec3::NEG_Z),
+            )
+            .unwrap();
+
+        assert_eq!(hit.object, ObjectId::MeshInstance(1));
+        assert_close(hit.distance, 3.0);
+    }
+}
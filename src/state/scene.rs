@@ -0,0 +1,740 @@
+use std::{collections::BTreeMap, error::Error, fmt::Display, path::Path, path::PathBuf};
+
+use glam::{EulerRot, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+use winit::dpi::PhysicalSize;
+
+use crate::util::gltf::GltfLoadError;
+
+use super::{
+    atmosphere::PhysicalSky,
+    bvh::BoundingVolume,
+    camera::Camera,
+    grid::GridLoadError,
+    light::Light,
+    material::{Material, NO_TEXTURE},
+    object::{Aabb, ObjectList, Plane, Sphere, Transform},
+    texture::{TextureList, TextureLoadError, TextureSource},
+    wide_bvh::BvhLayout,
+};
+
+#[derive(Debug)]
+pub enum SceneError {
+    IoError(std::io::Error),
+    ParseError(ron::error::SpannedError),
+    SerializeError(ron::Error),
+    MeshLoadError {
+        path: PathBuf,
+        error: GltfLoadError,
+    },
+    TextureLoadError {
+        path: PathBuf,
+        error: TextureLoadError,
+    },
+    GridLoadError {
+        path: PathBuf,
+        error: GridLoadError,
+    },
+    UnknownMaterial {
+        name: String,
+        available: Vec<String>,
+    },
+    InvalidObject {
+        index: usize,
+        reason: String,
+    },
+    InvalidLight {
+        index: usize,
+        reason: String,
+    },
+    InvalidCamera(String),
+    InvalidSky(String),
+}
+
+impl From<std::io::Error> for SceneError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IoError(value)
+    }
+}
+
+impl From<ron::error::SpannedError> for SceneError {
+    fn from(value: ron::error::SpannedError) -> Self {
+        Self::ParseError(value)
+    }
+}
+
+impl From<ron::Error> for SceneError {
+    fn from(value: ron::Error) -> Self {
+        Self::SerializeError(value)
+    }
+}
+
+impl Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneError::IoError(error) => write!(f, "couldn't read scene file: {}", error),
+            SceneError::ParseError(error) => write!(f, "invalid scene file: {}", error),
+            SceneError::SerializeError(error) => write!(f, "couldn't serialize scene: {}", error),
+            SceneError::MeshLoadError { path, error } => {
+                write!(f, "couldn't load mesh {:?}: {}", path, error)
+            }
+            SceneError::TextureLoadError { path, error } => {
+                write!(f, "couldn't load texture {:?}: {}", path, error)
+            }
+            SceneError::GridLoadError { path, error } => {
+                write!(f, "couldn't load voxel grid {:?}: {}", path, error)
+            }
+            SceneError::UnknownMaterial { name, available } => write!(
+                f,
+                "unknown material \"{}\", expected one of {:?}",
+                name, available
+            ),
+            SceneError::InvalidObject { index, reason } => {
+                write!(f, "invalid object at index {}: {}", index, reason)
+            }
+            SceneError::InvalidLight { index, reason } => {
+                write!(f, "invalid light at index {}: {}", index, reason)
+            }
+            SceneError::InvalidCamera(reason) => write!(f, "invalid camera: {}", reason),
+            SceneError::InvalidSky(reason) => write!(f, "invalid sky: {}", reason),
+        }
+    }
+}
+
+impl Error for SceneError {}
+
+/// Where the light arriving from the sky comes from. Written as just the path of an image, or as the
+/// settings of a physical sky, e.g. `Physical(sun_elevation: 10.0)`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SkyEnvironment {
+    /// An equirectangular `.hdr` or `.exr` panorama, or a directory containing the six raw faces of a
+    /// cubemap, relative to the working directory.
+    Map(PathBuf),
+    Physical(PhysicalSky),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SkySettings {
+    #[serde(alias = "cubemap")]
+    pub environment: SkyEnvironment,
+    /// Rotation of the sky about the y axis, in degrees.
+    pub rotation: f32,
+    /// Multiplies the radiance of the sky.
+    pub intensity: f32,
+    /// Whether camera rays see the sky. If not, the sky still lights the scene and shows up in
+    /// reflections, but the background is black.
+    pub visible: bool,
+}
+
+impl Default for SkySettings {
+    fn default() -> Self {
+        Self {
+            environment: SkyEnvironment::Map(PathBuf::from("assets/textures/cubemap/meadow")),
+            rotation: 0.0,
+            intensity: 1.0,
+            visible: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    /// Number of accumulated samples per pixel for offline renders.
+    pub samples: u32,
+    /// Meshes with at least this many triangles get their BVH built on the gpu, see
+    /// `BvhBuildSettings::gpu_build_threshold`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpu_bvh_threshold: Option<u32>,
+    /// The layout BVHs are traversed in, which can be toggled in the viewer.
+    pub bvh_layout: BvhLayout,
+    /// Sample emissive objects and the sky directly at every diffuse bounce, combined with hitting them
+    /// by chance through multiple importance sampling. Can be toggled in the viewer to compare.
+    pub light_sampling: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+            samples: 1024,
+            gpu_bvh_threshold: None,
+            bvh_layout: BvhLayout::default(),
+            light_sampling: true,
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(self.width, self.height)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+struct CameraDescriptor {
+    position: Vec3,
+    look_at: Vec3,
+    fov: f32,
+    near: f32,
+    far: f32,
+    movement_speed: f32,
+}
+
+impl Default for CameraDescriptor {
+    fn default() -> Self {
+        Self {
+            position: Vec3::new(0.0, 0.0, 10.0),
+            look_at: Vec3::ZERO,
+            fov: 45.0,
+            near: 1.0,
+            far: 100.0,
+            movement_speed: 10.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+struct TransformDescriptor {
+    translation: Vec3,
+    /// Euler angles in degrees, applied in XYZ order.
+    rotation: Vec3,
+    scale: f32,
+}
+
+impl Default for TransformDescriptor {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::ZERO,
+            rotation: Vec3::ZERO,
+            scale: 1.0,
+        }
+    }
+}
+
+impl From<TransformDescriptor> for Transform {
+    fn from(value: TransformDescriptor) -> Self {
+        let rotation = value.rotation * (std::f32::consts::PI / 180.0);
+
+        Transform::new(
+            value.translation,
+            Quat::from_euler(EulerRot::XYZ, rotation.x, rotation.y, rotation.z),
+            value.scale,
+        )
+    }
+}
+
+impl From<Transform> for TransformDescriptor {
+    fn from(value: Transform) -> Self {
+        let (x, y, z) = value.rotation.to_euler(EulerRot::XYZ);
+
+        Self {
+            translation: value.translation,
+            rotation: Vec3::new(x, y, z) * (180.0 / std::f32::consts::PI),
+            scale: value.scale,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum ObjectDescriptor {
+    Sphere {
+        center: Vec3,
+        radius: f32,
+        material: String,
+    },
+    Plane {
+        normal: Vec3,
+        point: Vec3,
+        material: String,
+    },
+    Aabb {
+        min: Vec3,
+        max: Vec3,
+        material: String,
+    },
+    Mesh {
+        path: PathBuf,
+        #[serde(default)]
+        transform: TransformDescriptor,
+        /// Overrides the materials defined in the mesh file.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        material: Option<String>,
+    },
+    /// A voxel grid filled with the material's medium, see `GridVolume`.
+    Volume {
+        path: PathBuf,
+        #[serde(default)]
+        transform: TransformDescriptor,
+        material: String,
+    },
+}
+
+/// Image files for the texture slots of a material, relative to the working directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct MaterialTexturesDescriptor {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    albedo: Option<PathBuf>,
+    /// Roughness in the green channel and metalness in the blue channel.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    roughness_metalness: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    normal: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    emission: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SceneDescriptor {
+    #[serde(default)]
+    materials: BTreeMap<String, Material>,
+    /// Textures of the materials with the same name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    textures: BTreeMap<String, MaterialTexturesDescriptor>,
+    #[serde(default)]
+    camera: CameraDescriptor,
+    #[serde(default)]
+    sky: SkySettings,
+    #[serde(default)]
+    render: RenderSettings,
+    #[serde(default)]
+    objects: Vec<ObjectDescriptor>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    lights: Vec<Light>,
+}
+
+// optional fields can be written without wrapping them in `Some(...)`
+fn ron_options() -> ron::Options {
+    ron::Options::default().with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+}
+
+/// Everything needed to render a scene, loaded from a scene description file.
+///
+/// Scene files are written in RON. Objects refer to materials by name, and mesh paths are relative to
+/// the working directory, like every other asset path.
+pub struct Scene {
+    pub object_list: ObjectList,
+    pub camera: Camera,
+    pub sky: SkySettings,
+    pub render_settings: RenderSettings,
+}
+
+impl Scene {
+    /// Loads a scene description file, or imports a `.glb` or `.gltf` file as a scene.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SceneError> {
+        let path = path.as_ref();
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("glb" | "gltf") => Self::from_gltf(path),
+            _ => Self::from_ron(&std::fs::read_to_string(path)?),
+        }
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self, SceneError> {
+        let descriptor: SceneDescriptor = ron_options().from_str(source)?;
+
+        let render_settings = descriptor.render;
+        let camera = Self::load_camera(&descriptor.camera, render_settings.size())?;
+
+        let mut object_list = ObjectList::new();
+        let materials = Self::load_materials(&descriptor, object_list.textures_mut())?;
+
+        let material_ids: BTreeMap<String, u32> = materials
+            .into_iter()
+            .map(|(name, material)| {
+                let id = object_list.materials_mut().push_named(&name, material);
+                (name, id)
+            })
+            .collect();
+
+        let material = |name: &str| {
+            material_ids
+                .get(name)
+                .copied()
+                .ok_or_else(|| SceneError::UnknownMaterial {
+                    name: name.to_owned(),
+                    available: material_ids.keys().cloned().collect(),
+                })
+        };
+
+        for (index, object) in descriptor.objects.iter().enumerate() {
+            let invalid = |reason: &str| SceneError::InvalidObject {
+                index,
+                reason: reason.to_owned(),
+            };
+
+            match object {
+                ObjectDescriptor::Sphere {
+                    center,
+                    radius,
+                    material: name,
+                } => {
+                    if *radius <= 0.0 {
+                        return Err(invalid("sphere radius must be positive"));
+                    }
+
+                    object_list.push_sphere(Sphere::new(*center, *radius, material(name)?));
+                }
+                ObjectDescriptor::Plane {
+                    normal,
+                    point,
+                    material: name,
+                } => {
+                    let normal = normal
+                        .try_normalize()
+                        .ok_or_else(|| invalid("plane normal must be non-zero"))?;
+
+                    object_list.push_plane(Plane::new(normal, *point, material(name)?));
+                }
+                ObjectDescriptor::Aabb {
+                    min,
+                    max,
+                    material: name,
+                } => {
+                    if min.cmpgt(*max).any() {
+                        return Err(invalid("aabb min must not be greater than max"));
+                    }
+
+                    object_list.push_aabb(Aabb::new(*min, *max, material(name)?));
+                }
+                ObjectDescriptor::Mesh {
+                    path,
+                    transform,
+                    material: name,
+                } => {
+                    if transform.scale <= 0.0 {
+                        return Err(invalid("mesh scale must be positive"));
+                    }
+
+                    let material = name.as_deref().map(material).transpose()?;
+
+                    object_list
+                        .push_mesh(path, (*transform).into(), material)
+                        .map_err(|error| SceneError::MeshLoadError {
+                            path: path.clone(),
+                            error,
+                        })?;
+                }
+                ObjectDescriptor::Volume {
+                    path,
+                    transform,
+                    material: name,
+                } => {
+                    if transform.scale <= 0.0 {
+                        return Err(invalid("volume scale must be positive"));
+                    }
+
+                    object_list
+                        .push_grid_volume(path, (*transform).into(), material(name)?)
+                        .map_err(|error| SceneError::GridLoadError {
+                            path: path.clone(),
+                            error,
+                        })?;
+                }
+            }
+        }
+
+        for (index, light) in descriptor.lights.iter().enumerate() {
+            light
+                .validate()
+                .map_err(|reason| SceneError::InvalidLight {
+                    index,
+                    reason: reason.to_owned(),
+                })?;
+
+            object_list.push_light(*light);
+        }
+
+        if let SkyEnvironment::Physical(physical_sky) = &descriptor.sky.environment {
+            physical_sky
+                .validate()
+                .map_err(|reason| SceneError::InvalidSky(reason.to_owned()))?;
+        }
+
+        Ok(Self {
+            object_list,
+            camera,
+            sky: descriptor.sky,
+            render_settings,
+        })
+    }
+
+    fn load_materials(
+        descriptor: &SceneDescriptor,
+        textures: &mut TextureList,
+    ) -> Result<BTreeMap<String, Material>, SceneError> {
+        let mut materials = descriptor.materials.clone();
+
+        for (name, texture_paths) in &descriptor.textures {
+            let material = materials
+                .get_mut(name)
+                .ok_or_else(|| SceneError::UnknownMaterial {
+                    name: name.to_owned(),
+                    available: descriptor.materials.keys().cloned().collect(),
+                })?;
+
+            let mut load = |path: &Option<PathBuf>| match path {
+                Some(path) => {
+                    textures
+                        .push_file(path)
+                        .map_err(|error| SceneError::TextureLoadError {
+                            path: path.clone(),
+                            error,
+                        })
+                }
+                None => Ok(NO_TEXTURE),
+            };
+
+            material.albedo_texture = load(&texture_paths.albedo)?;
+            material.roughness_metalness_texture = load(&texture_paths.roughness_metalness)?;
+            material.normal_texture = load(&texture_paths.normal)?;
+            material.emission_texture = load(&texture_paths.emission)?;
+        }
+
+        Ok(materials)
+    }
+
+    /// Imports every mesh and camera of a glTF file, with its own materials. The first camera in the
+    /// file is used, otherwise the camera is placed to frame the whole scene.
+    pub fn from_gltf<P: AsRef<Path>>(path: P) -> Result<Self, SceneError> {
+        let path = path.as_ref();
+
+        let mut object_list = ObjectList::new();
+        let cameras = object_list
+            .push_mesh(path, Transform::default(), None)
+            .map_err(|error| SceneError::MeshLoadError {
+                path: path.to_owned(),
+                error,
+            })?;
+
+        let render_settings = RenderSettings::default();
+
+        let descriptor = match cameras.first() {
+            Some(camera) => CameraDescriptor {
+                position: camera.position,
+                look_at: camera.position + camera.forward(),
+                fov: camera.fov,
+                near: camera.near,
+                far: camera.far.unwrap_or(CameraDescriptor::default().far),
+                ..Default::default()
+            },
+            None => Self::framing_camera(&object_list),
+        };
+
+        let camera = Self::load_camera(&descriptor, render_settings.size())?;
+
+        Ok(Self {
+            object_list,
+            camera,
+            sky: SkySettings::default(),
+            render_settings,
+        })
+    }
+
+    fn framing_camera(object_list: &ObjectList) -> CameraDescriptor {
+        let default = CameraDescriptor::default();
+
+        let mut bounds = BoundingVolume::EMPTY;
+
+        for instance in object_list.mesh_instances() {
+            let mesh = &object_list.meshes()[instance.mesh as usize];
+            bounds.grow(&mesh.bounds.transform(instance.transform.matrix()));
+        }
+
+        if bounds.empty {
+            return default;
+        }
+
+        let center = bounds.center();
+        let radius = ((bounds.max - bounds.min).length() / 2.0).max(f32::EPSILON);
+
+        // move back far enough for the bounding sphere to fit into the vertical field of view
+        let distance = radius / (default.fov.to_radians() / 2.0).sin();
+
+        CameraDescriptor {
+            position: center + Vec3::Z * distance,
+            look_at: center,
+            near: distance * 0.01,
+            far: (distance + radius) * 2.0,
+            movement_speed: radius,
+            ..default
+        }
+    }
+
+    fn load_camera(
+        descriptor: &CameraDescriptor,
+        size: PhysicalSize<u32>,
+    ) -> Result<Camera, SceneError> {
+        if descriptor.position.distance_squared(descriptor.look_at) < f32::EPSILON {
+            return Err(SceneError::InvalidCamera(
+                "position and look_at must be different points".to_owned(),
+            ));
+        }
+
+        if descriptor.fov <= 0.0 || descriptor.fov >= 180.0 {
+            return Err(SceneError::InvalidCamera(
+                "fov must be between 0 and 180 degrees".to_owned(),
+            ));
+        }
+
+        if descriptor.near <= 0.0 || descriptor.far <= descriptor.near {
+            return Err(SceneError::InvalidCamera(
+                "near must be positive and less than far".to_owned(),
+            ));
+        }
+
+        Ok(Camera::new(
+            descriptor.position,
+            descriptor.look_at,
+            descriptor.fov,
+            size,
+            descriptor.near,
+            descriptor.far,
+            descriptor.movement_speed,
+        ))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneError> {
+        std::fs::write(path, self.to_ron()?)?;
+        Ok(())
+    }
+
+    pub fn to_ron(&self) -> Result<String, SceneError> {
+        let descriptor = Self::describe(
+            &self.object_list,
+            &self.camera,
+            &self.sky,
+            self.render_settings,
+        );
+
+        let config = ron::ser::PrettyConfig::new().struct_names(false);
+        Ok(ron_options().to_string_pretty(&descriptor, config)?)
+    }
+
+    fn describe(
+        object_list: &ObjectList,
+        camera: &Camera,
+        sky: &SkySettings,
+        render_settings: RenderSettings,
+    ) -> SceneDescriptor {
+        let material_list = object_list.materials();
+
+        // only materials used by objects are written, named materials keep their name
+        let mut used_materials: BTreeMap<u32, String> = BTreeMap::new();
+
+        let mut material_name = |id: u32| {
+            used_materials
+                .entry(id)
+                .or_insert_with(|| match material_list.name(id) {
+                    Some(name) => name.to_owned(),
+                    None => {
+                        let mut name = format!("material_{}", id);
+
+                        while material_list.find(&name).is_some() {
+                            name.push('_');
+                        }
+
+                        name
+                    }
+                })
+                .clone()
+        };
+
+        let mut objects = Vec::new();
+
+        for sphere in object_list.spheres() {
+            objects.push(ObjectDescriptor::Sphere {
+                center: sphere.center(),
+                radius: sphere.radius(),
+                material: material_name(sphere.material()),
+            });
+        }
+
+        for plane in object_list.planes() {
+            objects.push(ObjectDescriptor::Plane {
+                normal: plane.normal(),
+                point: plane.point(),
+                material: material_name(plane.material()),
+            });
+        }
+
+        for aabb in object_list.aabbs() {
+            objects.push(ObjectDescriptor::Aabb {
+                min: aabb.min(),
+                max: aabb.max(),
+                material: material_name(aabb.material()),
+            });
+        }
+
+        for instance in object_list.mesh_instances() {
+            objects.push(ObjectDescriptor::Mesh {
+                path: object_list.meshes()[instance.mesh as usize].path.clone(),
+                transform: instance.transform.into(),
+                material: instance.material.map(&mut material_name),
+            });
+        }
+
+        for volume in object_list.grid_volumes() {
+            objects.push(ObjectDescriptor::Volume {
+                path: object_list.grids().grids()[volume.grid as usize]
+                    .path
+                    .clone(),
+                transform: volume.transform.into(),
+                material: material_name(volume.material),
+            });
+        }
+
+        let used_materials: Vec<(String, Material)> = used_materials
+            .into_iter()
+            .filter_map(|(id, name)| material_list.get(id).map(|material| (name, *material)))
+            .collect();
+
+        // only textures loaded from image files can be referred to by scene files
+        let texture_path = |index: u32| match object_list.textures().source(index) {
+            Some(TextureSource::File(path)) => Some(path.clone()),
+            _ => None,
+        };
+
+        let textures = used_materials
+            .iter()
+            .filter(|(_, material)| material.has_textures())
+            .map(|(name, material)| {
+                let descriptor = MaterialTexturesDescriptor {
+                    albedo: texture_path(material.albedo_texture),
+                    roughness_metalness: texture_path(material.roughness_metalness_texture),
+                    normal: texture_path(material.normal_texture),
+                    emission: texture_path(material.emission_texture),
+                };
+
+                (name.clone(), descriptor)
+            })
+            .collect();
+
+        let materials = used_materials.into_iter().collect();
+
+        SceneDescriptor {
+            materials,
+            textures,
+            camera: CameraDescriptor {
+                position: camera.position,
+                look_at: camera.position + camera.forward(),
+                fov: camera.fov,
+                near: camera.near(),
+                far: camera.far(),
+                movement_speed: camera.movement_speed,
+            },
+            sky: sky.clone(),
+            render: render_settings,
+            objects,
+            lights: object_list.lights().to_vec(),
+        }
+    }
+}
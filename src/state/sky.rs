@@ -0,0 +1,340 @@
+use std::{
+    error::Error,
+    f32::consts::{PI, TAU},
+    fmt::Display,
+    path::Path,
+};
+
+use glam::{Vec2, Vec3};
+use image::Rgba32FImage;
+use rayon::{
+    iter::{IntoParallelIterator, ParallelIterator},
+    slice::ParallelSliceMut,
+};
+
+use super::{atmosphere::PhysicalSky, light::luminance, scene::SkyEnvironment};
+
+/// Texels per side of every face in the table the sky is importance sampled with. Faces are averaged
+/// down to this size, which is still fine enough to find a small sun.
+pub const SKY_DISTRIBUTION_SIZE: usize = 64;
+
+/// Directions sampled per side of every texel of the table, when the sky is a panorama.
+const PANORAMA_SAMPLES: usize = 8;
+
+/// Wavelengths a physical sky is weighed by in the table, spread over the visible spectrum.
+const PHYSICAL_SKY_WAVELENGTHS: [f32; 3] = [450.0, 550.0, 650.0];
+
+/// Names of the raw cubemap face files, in the order of the cubemap's layers.
+const CUBEMAP_FACES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+/// Bytes per texel of raw cubemap faces, which are rgba f32.
+const CUBEMAP_TEXEL_SIZE: usize = 4 * std::mem::size_of::<f32>();
+
+#[derive(Debug)]
+#[allow(unused)]
+pub enum SkyLoadError {
+    IoError(std::io::Error),
+    ImageError(image::ImageError),
+    InvalidCubemap(String),
+}
+
+impl From<std::io::Error> for SkyLoadError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IoError(value)
+    }
+}
+
+impl From<image::ImageError> for SkyLoadError {
+    fn from(value: image::ImageError) -> Self {
+        Self::ImageError(value)
+    }
+}
+
+impl Display for SkyLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for SkyLoadError {}
+
+/// The image data of a sky, in linear radiance.
+pub enum SkySource {
+    /// The six faces of a cubemap as rgba f32 texels, in the order px, nx, py, ny, pz, nz.
+    Cubemap { faces: Vec<Vec<u8>>, size: u32 },
+    /// An equirectangular panorama, which is converted to a cubemap on the gpu.
+    Panorama(Rgba32FImage),
+    /// Computed by the raytracer for every wavelength, so there's no image.
+    Physical(PhysicalSky),
+}
+
+impl SkySource {
+    pub fn new(environment: &SkyEnvironment) -> Result<Self, SkyLoadError> {
+        match environment {
+            SkyEnvironment::Map(path) => Self::load(path),
+            SkyEnvironment::Physical(physical_sky) => Ok(Self::Physical(*physical_sky)),
+        }
+    }
+
+    /// Loads a `.hdr` or `.exr` panorama, or a directory containing the raw faces of a cubemap, see
+    /// `SkySettings::environment`. The path is relative to the working directory.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SkyLoadError> {
+        let path = std::env::current_dir()?.join(path);
+
+        if !path.is_dir() {
+            return Ok(Self::Panorama(image::open(&path)?.into_rgba32f()));
+        }
+
+        let mut faces = CUBEMAP_FACES
+            .map(|face| std::fs::read(path.join(face)))
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let texels = faces[0].len() / CUBEMAP_TEXEL_SIZE;
+        let size = (texels as f64).sqrt() as usize;
+        let face_len = size * size * CUBEMAP_TEXEL_SIZE;
+
+        if size == 0 || faces.iter().any(|face| face.len() != face_len) {
+            return Err(SkyLoadError::InvalidCubemap(format!(
+                "faces in {:?} must be square and the same size",
+                path
+            )));
+        }
+
+        // raw faces are stored gamma encoded
+        for face in &mut faces {
+            face.par_chunks_exact_mut(CUBEMAP_TEXEL_SIZE)
+                .for_each(|texel| {
+                    for channel in texel.chunks_exact_mut(4).take(3) {
+                        let value = f32::from_le_bytes((&*channel).try_into().unwrap());
+                        channel.copy_from_slice(&value.powf(2.2).to_le_bytes());
+                    }
+                });
+        }
+
+        Ok(Self::Cubemap {
+            faces,
+            size: size as u32,
+        })
+    }
+
+    /// Texels per side of every face of the cubemap the sky is sampled from. Panoramas get about as
+    /// many texels around the horizon as they have, and physical skies aren't read from the cubemap.
+    pub fn cubemap_size(&self) -> u32 {
+        match self {
+            SkySource::Cubemap { size, .. } => *size,
+            SkySource::Panorama(panorama) => (panorama.width() / 4).max(1),
+            SkySource::Physical(_) => 1,
+        }
+    }
+}
+
+/// The unnormalized direction a cubemap is sampled in to read the point on the face, with both
+/// coordinates from -1 to 1 starting at the top left corner of the face. Must match
+/// `cube_face_direction` in `space.wgsl`.
+pub fn cube_face_direction(face: usize, coords: Vec2) -> Vec3 {
+    let Vec2 { x: s, y: t } = coords;
+
+    match face {
+        0 => Vec3::new(1.0, -t, -s),
+        1 => Vec3::new(-1.0, -t, s),
+        2 => Vec3::new(s, 1.0, t),
+        3 => Vec3::new(s, -1.0, -t),
+        4 => Vec3::new(s, -t, 1.0),
+        _ => Vec3::new(-s, -t, -1.0),
+    }
+}
+
+/// Where a normalized direction points to on an equirectangular panorama, with the center of the
+/// panorama towards -z and its top towards +y. Must match `equirect_uv` in `equirect_to_cubemap.wgsl`.
+pub fn equirect_uv(dir: Vec3) -> Vec2 {
+    Vec2::new(
+        0.5 + dir.x.atan2(-dir.z) / TAU,
+        dir.y.clamp(-1.0, 1.0).acos() / PI,
+    )
+}
+
+/// How much light arrives from every part of the sky cubemap, so that the raytracer can pick directions
+/// towards the sky in proportion to their brightness.
+///
+/// The rows of all six faces are stacked on top of each other in the cubemap's face order. The
+/// marginal cdf picks a row in proportion to the light arriving from it, and the row's conditional cdf
+/// picks a texel within it. Must be sampled like `sample_sky_direction` in `raytrace.wgsl` does.
+#[derive(Debug, Clone)]
+pub struct SkyDistribution {
+    size: usize,
+    marginal_cdf: Vec<f32>,
+    conditional_cdf: Vec<f32>,
+}
+
+impl SkyDistribution {
+    pub fn new(source: &SkySource) -> Self {
+        match source {
+            SkySource::Cubemap { faces, size } => Self::from_cubemap(faces, *size as usize),
+            SkySource::Panorama(panorama) => Self::from_panorama(panorama),
+            SkySource::Physical(physical_sky) => Self::from_physical(physical_sky),
+        }
+    }
+
+    /// Averages blocks of texels of the faces, given as rgba f32 texels with `face_size` texels per
+    /// side.
+    fn from_cubemap(faces: &[Vec<u8>], face_size: usize) -> Self {
+        let size = SKY_DISTRIBUTION_SIZE.min(face_size);
+        let block = face_size / size;
+
+        let texel = |face: usize, x: usize, y: usize| {
+            let offset = (y * face_size + x) * CUBEMAP_TEXEL_SIZE;
+            let channel = |index: usize| {
+                let start = offset + index * std::mem::size_of::<f32>();
+                f32::from_le_bytes(faces[face][start..start + 4].try_into().unwrap())
+            };
+
+            Vec3::new(channel(0), channel(1), channel(2))
+        };
+
+        Self::from_texels(size, |face, x, y| {
+            let mut sum = 0.0;
+
+            for texel_y in y * block..(y + 1) * block {
+                for texel_x in x * block..(x + 1) * block {
+                    sum += luminance(texel(face, texel_x, texel_y).max(Vec3::ZERO));
+                }
+            }
+
+            sum / (block * block) as f32
+        })
+    }
+
+    /// Averages the panorama over a grid of directions within every texel.
+    fn from_panorama(panorama: &Rgba32FImage) -> Self {
+        let size = SKY_DISTRIBUTION_SIZE;
+        let (width, height) = panorama.dimensions();
+
+        Self::from_texels(size, |face, x, y| {
+            let mut sum = 0.0;
+
+            for sample_y in 0..PANORAMA_SAMPLES {
+                for sample_x in 0..PANORAMA_SAMPLES {
+                    let offset = |texel: usize, sample: usize| {
+                        texel as f32 + (sample as f32 + 0.5) / PANORAMA_SAMPLES as f32
+                    };
+
+                    let coords = Vec2::new(offset(x, sample_x), offset(y, sample_y)) / size as f32;
+                    let dir = cube_face_direction(face, coords * 2.0 - 1.0).normalize();
+                    let uv = equirect_uv(dir);
+
+                    let pixel = panorama.get_pixel(
+                        ((uv.x * width as f32) as u32).min(width - 1),
+                        ((uv.y * height as f32) as u32).min(height - 1),
+                    );
+
+                    sum += luminance(Vec3::from_slice(&pixel.0[..3]).max(Vec3::ZERO));
+                }
+            }
+
+            sum / (PANORAMA_SAMPLES * PANORAMA_SAMPLES) as f32
+        })
+    }
+
+    /// Evaluates the sky in the center of every texel. The sun's disc is sampled on its own, since it's
+    /// much smaller than a texel.
+    fn from_physical(physical_sky: &PhysicalSky) -> Self {
+        let size = SKY_DISTRIBUTION_SIZE;
+
+        Self::from_texels(size, |face, x, y| {
+            let coords = (Vec2::new(x as f32, y as f32) + 0.5) / size as f32;
+            let dir = cube_face_direction(face, coords * 2.0 - 1.0).normalize();
+
+            PHYSICAL_SKY_WAVELENGTHS
+                .iter()
+                .map(|&wavelength| physical_sky.radiance(dir, wavelength))
+                .sum::<f32>()
+                / PHYSICAL_SKY_WAVELENGTHS.len() as f32
+        })
+    }
+
+    /// Builds the cdfs from the luminance of every texel of a `size` by `size` table per face.
+    fn from_texels(size: usize, luminance: impl Fn(usize, usize, usize) -> f32 + Sync) -> Self {
+        let mut weights: Vec<f32> = (0..6 * size)
+            .into_par_iter()
+            .flat_map_iter(|row| {
+                let face = row / size;
+                let y = row % size;
+                let luminance = &luminance;
+
+                (0..size).map(move |x| luminance(face, x, y) * Self::solid_angle(size, x, y))
+            })
+            .collect();
+
+        // a black sky is sampled uniformly instead, so that the cdfs stay valid
+        if weights.iter().sum::<f32>() <= 0.0 {
+            for (index, weight) in weights.iter_mut().enumerate() {
+                *weight = Self::solid_angle(size, index % size, index / size % size);
+            }
+        }
+
+        let mut marginal_cdf = Vec::with_capacity(6 * size);
+        let mut conditional_cdf = Vec::with_capacity(6 * size * size);
+
+        for row in weights.chunks_exact(size) {
+            marginal_cdf.push(row.iter().sum());
+            conditional_cdf.extend(Self::cdf(row));
+        }
+
+        Self {
+            size,
+            marginal_cdf: Self::cdf(&marginal_cdf),
+            conditional_cdf,
+        }
+    }
+
+    /// The solid angle of a texel, as seen from the center of the cube. Only an approximation for
+    /// weighing texels, the raytracer computes the exact density of every direction it samples.
+    fn solid_angle(size: usize, x: usize, y: usize) -> f32 {
+        let texel_size = 2.0 / size as f32;
+        let center = |i: usize| (i as f32 + 0.5) * texel_size - 1.0;
+
+        let distance_squared = 1.0 + center(x) * center(x) + center(y) * center(y);
+
+        texel_size * texel_size / (distance_squared * distance_squared.sqrt())
+    }
+
+    /// Rows without any weight are picked from uniformly.
+    fn cdf(weights: &[f32]) -> Vec<f32> {
+        let total: f32 = weights.iter().sum();
+        let mut cumulative = 0.0;
+
+        let mut cdf: Vec<f32> = weights
+            .iter()
+            .enumerate()
+            .map(|(index, weight)| match total > 0.0 {
+                true => {
+                    cumulative += weight;
+                    cumulative / total
+                }
+                false => (index + 1) as f32 / weights.len() as f32,
+            })
+            .collect();
+
+        // make sure the last entry is always picked for the largest random numbers
+        if let Some(last) = cdf.last_mut() {
+            *last = 1.0;
+        }
+
+        cdf
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// One entry per row, of every face.
+    pub fn marginal_cdf(&self) -> &[f32] {
+        &self.marginal_cdf
+    }
+
+    /// `size` entries per row.
+    pub fn conditional_cdf(&self) -> &[f32] {
+        &self.conditional_cdf
+    }
+}
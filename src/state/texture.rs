@@ -0,0 +1,152 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use image::{imageops::FilterType, RgbaImage};
+
+#[derive(Debug)]
+#[allow(unused)]
+pub enum TextureLoadError {
+    ImageError(image::ImageError),
+    TooManyTextures,
+}
+
+impl From<image::ImageError> for TextureLoadError {
+    fn from(value: image::ImageError) -> Self {
+        Self::ImageError(value)
+    }
+}
+
+impl From<std::io::Error> for TextureLoadError {
+    fn from(value: std::io::Error) -> Self {
+        Self::ImageError(value.into())
+    }
+}
+
+impl Display for TextureLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureLoadError::ImageError(error) => write!(f, "{}", error),
+            TextureLoadError::TooManyTextures => write!(
+                f,
+                "a scene can't have more than {} textures",
+                TextureList::MAX_LAYERS
+            ),
+        }
+    }
+}
+
+impl Error for TextureLoadError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextureSource {
+    File(PathBuf),
+    /// An image embedded in or referenced by a glTF file.
+    Gltf {
+        path: PathBuf,
+        image_index: usize,
+    },
+}
+
+/// Material textures, stored as the layers of one texture array on the gpu. Every image is resized to
+/// the same square layer size when it's added, and there can't be more layers than every device
+/// supports in a texture array.
+pub struct TextureList {
+    layers: Vec<RgbaImage>,
+    sources: Vec<TextureSource>,
+
+    version: u32,
+}
+
+impl TextureList {
+    pub const LAYER_SIZE: u32 = 1024;
+    /// The most layers of a texture array that every device supports.
+    pub const MAX_LAYERS: usize = 256;
+
+    pub fn new() -> Self {
+        Self {
+            layers: Vec::new(),
+            sources: Vec::new(),
+            version: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.version += 1;
+
+        self.layers.clear();
+        self.sources.clear();
+    }
+
+    /// Loads an image file, relative to the working directory, and returns its texture index. Files
+    /// that were already loaded aren't loaded again.
+    pub fn push_file<P: AsRef<Path>>(&mut self, path: P) -> Result<u32, TextureLoadError> {
+        let source = TextureSource::File(path.as_ref().to_owned());
+
+        if let Some(index) = self.find(&source) {
+            return Ok(index);
+        }
+
+        let image = image::open(std::env::current_dir()?.join(path))?.to_rgba8();
+
+        self.push_image(image, source)
+    }
+
+    /// Adds an image and returns its texture index.
+    pub fn push_image(
+        &mut self,
+        image: RgbaImage,
+        source: TextureSource,
+    ) -> Result<u32, TextureLoadError> {
+        if self.layers.len() >= Self::MAX_LAYERS {
+            return Err(TextureLoadError::TooManyTextures);
+        }
+
+        self.version += 1;
+
+        let image = if image.dimensions() == (Self::LAYER_SIZE, Self::LAYER_SIZE) {
+            image
+        } else {
+            image::imageops::resize(
+                &image,
+                Self::LAYER_SIZE,
+                Self::LAYER_SIZE,
+                FilterType::Triangle,
+            )
+        };
+
+        self.layers.push(image);
+        self.sources.push(source);
+
+        Ok(self.layers.len() as u32 - 1)
+    }
+
+    pub fn find(&self, source: &TextureSource) -> Option<u32> {
+        self.sources
+            .iter()
+            .position(|s| s == source)
+            .map(|index| index as u32)
+    }
+
+    pub fn layers(&self) -> &[RgbaImage] {
+        &self.layers
+    }
+
+    pub fn source(&self, index: u32) -> Option<&TextureSource> {
+        self.sources.get(index as usize)
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+}
@@ -0,0 +1,271 @@
+use glam::{UVec3, UVec4, Vec3};
+use gpu_bytes::{AsStd140, AsStd430, Std140Bytes, Std430Bytes};
+use serde::{Deserialize, Serialize};
+
+use super::bvh::{BoundingVolume, BvhNode, MAX_TRAVERSAL_DEPTH};
+
+/// Number of children of every wide BVH node.
+pub const WIDE_BVH_WIDTH: usize = 4;
+
+/// Stands in for the wide root of a BVH that has no wide layout, e.g. because it's built on the gpu.
+/// Such BVHs are always traversed in the binary layout.
+pub const NO_WIDE_ROOT: u32 = u32::MAX;
+
+/// Marks a child slot as an internal node, in place of the object count of a leaf.
+const INTERNAL_CHILD: u32 = 0xffff;
+
+/// Leaves with more objects than this don't fit in a child slot.
+const MAX_LEAF_LEN: u32 = INTERNAL_CHILD - 1;
+
+/// Which layout the raytracer traverses BVHs in. Both are always built, so the layout can be switched
+/// at any time. Must match the `BVH_LAYOUT_*` constants in `bvh.wgsl`.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BvhLayout {
+    /// The binary BVH the builders produce, testing two children per node.
+    #[default]
+    Binary,
+    /// The binary BVH collapsed into a 4-ary BVH with quantized child bounds, testing four children
+    /// per node with fewer nodes to visit and less memory to read.
+    Wide,
+}
+
+impl BvhLayout {
+    pub fn toggled(self) -> Self {
+        match self {
+            BvhLayout::Binary => BvhLayout::Wide,
+            BvhLayout::Wide => BvhLayout::Binary,
+        }
+    }
+}
+
+/// A node of a wide BVH. The bounds of its children are quantized to a byte per axis, as steps of a
+/// power of two from the node's lower corner.
+///
+/// Every child slot is either an internal node, a leaf holding a range of objects directly, or empty.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct WideBvhNode {
+    /// Lower corner of the node's bounds.
+    pub origin: Vec3,
+    /// The biased exponent of the quantization step on every axis, a byte per axis.
+    pub exponents: u32,
+    /// The quantized lower corner of every child on every axis, a byte per child.
+    pub lower: UVec3,
+    /// The object count of the first two children, 16 bits per child. Internal nodes are marked with
+    /// `INTERNAL_CHILD`, and empty slots have no objects.
+    pub counts_low: u32,
+    /// The quantized upper corner of every child on every axis, a byte per child.
+    pub upper: UVec3,
+    /// The object count of the last two children, see `counts_low`.
+    pub counts_high: u32,
+    /// The node index of every internal child, and the index of the first object of every leaf.
+    pub children: UVec4,
+}
+
+impl AsStd140 for WideBvhNode {
+    fn as_std140(&self) -> Std140Bytes {
+        let mut buf = Std140Bytes::new();
+
+        buf.write(&self.origin);
+        buf.write(&self.exponents);
+        buf.write(&self.lower);
+        buf.write(&self.counts_low);
+        buf.write(&self.upper);
+        buf.write(&self.counts_high);
+        buf.write(&self.children);
+        buf.align();
+
+        buf
+    }
+}
+
+impl AsStd430 for WideBvhNode {
+    fn as_std430(&self) -> Std430Bytes {
+        let mut buf = Std430Bytes::new();
+
+        buf.write(&self.origin);
+        buf.write(&self.exponents);
+        buf.write(&self.lower);
+        buf.write(&self.counts_low);
+        buf.write(&self.upper);
+        buf.write(&self.counts_high);
+        buf.write(&self.children);
+        buf.align();
+
+        buf
+    }
+}
+
+impl WideBvhNode {
+    /// A node without children, whose quantization grid covers the bounds.
+    fn new(bounds: BoundingVolume) -> Self {
+        let mut exponents = 0;
+
+        for axis in 0..3 {
+            exponents |= Self::exponent(bounds.min[axis], bounds.max[axis]) << (8 * axis);
+        }
+
+        Self {
+            origin: bounds.min,
+            exponents,
+            ..Default::default()
+        }
+    }
+
+    /// The smallest biased exponent whose power of two covers the range in 255 steps.
+    fn exponent(min: f32, max: f32) -> u32 {
+        let step = (max - min) / 255.0;
+
+        let mut exponent = ((step.to_bits() >> 23) & 0xff).max(1);
+
+        // round up to the next power of two
+        if step.to_bits() & 0x7fffff != 0 {
+            exponent += 1;
+        }
+
+        // make sure the grid covers the range after rounding, too
+        while exponent < 254 && min + 255.0 * Self::step(exponent) < max {
+            exponent += 1;
+        }
+
+        exponent.min(254)
+    }
+
+    fn step(exponent: u32) -> f32 {
+        f32::from_bits(exponent << 23)
+    }
+
+    /// Quantizes a coordinate on one axis, rounding down for lower corners and up for upper corners,
+    /// so that the decoded bounds always contain the original bounds.
+    fn quantize(&self, axis: usize, value: f32, round_up: bool) -> u32 {
+        let origin = self.origin[axis];
+        let step = Self::step((self.exponents >> (8 * axis)) & 0xff);
+        let decode = |quantized: u32| origin + quantized as f32 * step;
+
+        let scaled = (value - origin) / step;
+        let mut quantized = match round_up {
+            true => scaled.ceil(),
+            false => scaled.floor(),
+        }
+        .clamp(0.0, 255.0) as u32;
+
+        // the division can round either way, so check the decoded value like the shader computes it
+        if round_up {
+            while quantized < 255 && decode(quantized) < value {
+                quantized += 1;
+            }
+        } else {
+            while quantized > 0 && decode(quantized) > value {
+                quantized -= 1;
+            }
+        }
+
+        quantized
+    }
+
+    fn set_child(&mut self, slot: usize, bounds: BoundingVolume, child: u32, count: u32) {
+        for axis in 0..3 {
+            let shift = 8 * slot;
+
+            self.lower[axis] |= self.quantize(axis, bounds.min[axis], false) << shift;
+            self.upper[axis] |= self.quantize(axis, bounds.max[axis], true) << shift;
+        }
+
+        let shift = 16 * (slot % 2);
+
+        match slot < 2 {
+            true => self.counts_low |= count << shift,
+            false => self.counts_high |= count << shift,
+        }
+
+        self.children[slot] = child;
+    }
+}
+
+/// Collapses the binary BVH whose root is at `root` in `nodes` into a wide BVH appended to
+/// `wide_nodes`, and returns the index of the wide root. Leaves keep their object ranges, so the wide
+/// BVH refers to the same objects.
+///
+/// Every wide node takes the children of its binary node, and then keeps replacing the internal
+/// child with the largest surface area by its own children until the node is full. Returns None if a
+/// leaf has too many objects to fit in a child slot, or if the wide BVH is too deep for traversal's
+/// node stack, in which case nothing is appended.
+pub fn collapse(nodes: &[BvhNode], root: u32, wide_nodes: &mut Vec<WideBvhNode>) -> Option<u32> {
+    let wide_root = wide_nodes.len();
+    wide_nodes.push(WideBvhNode::default());
+
+    let mut stack = vec![(root as usize, wide_root, 0)];
+
+    while let Some((index, wide_index, depth)) = stack.pop() {
+        let node = nodes[index];
+
+        let mut children = Vec::with_capacity(WIDE_BVH_WIDTH);
+
+        if node.child_node != 0 {
+            children.push(node.child_node as usize);
+            children.push(node.child_node as usize + 1);
+
+            while children.len() < WIDE_BVH_WIDTH {
+                let widest = children
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &child)| nodes[child].child_node != 0)
+                    .max_by(|(_, &a), (_, &b)| {
+                        let area = |child: usize| nodes[child].bounds.surface_area();
+                        area(a).total_cmp(&area(b))
+                    })
+                    .map(|(slot, _)| slot);
+
+                let Some(slot) = widest else {
+                    break;
+                };
+
+                let child_node = nodes[children[slot]].child_node as usize;
+                children[slot] = child_node;
+                children.push(child_node + 1);
+            }
+        } else {
+            // a root that's a leaf becomes a node with a single leaf
+            children.push(index);
+        }
+
+        let mut wide_node = WideBvhNode::new(node.bounds);
+
+        for (slot, &child) in children.iter().enumerate() {
+            let child_node = nodes[child];
+
+            if child_node.child_node != 0 {
+                // traversal keeps the untested children of every node along the way on its stack
+                if (depth + 1) * (WIDE_BVH_WIDTH - 1) + 1 > MAX_TRAVERSAL_DEPTH as usize {
+                    wide_nodes.truncate(wide_root);
+                    return None;
+                }
+
+                let child_wide_index = wide_nodes.len();
+                wide_nodes.push(WideBvhNode::default());
+                stack.push((child, child_wide_index, depth + 1));
+
+                wide_node.set_child(
+                    slot,
+                    child_node.bounds,
+                    child_wide_index as u32,
+                    INTERNAL_CHILD,
+                );
+            } else if child_node.len > MAX_LEAF_LEN {
+                wide_nodes.truncate(wide_root);
+                return None;
+            } else if child_node.len > 0 {
+                wide_node.set_child(
+                    slot,
+                    child_node.bounds,
+                    child_node.start_index,
+                    child_node.len,
+                );
+            }
+        }
+
+        wide_nodes[wide_index] = wide_node;
+    }
+
+    Some(wide_root as u32)
+}
@@ -5,12 +5,15 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use glam::{Quat, Vec2, Vec3};
-use gltf::{mesh::Mode, Gltf};
-
-use crate::{
-    engine::{render_state::GpuState, render_state_ext::texture::Texture},
-    state::{material::Material, object::Triangle},
+use glam::{Mat4, Quat, Vec2, Vec3};
+use gltf::{camera::Projection, image::Format, json::Value, mesh::Mode, Gltf};
+use image::RgbaImage;
+
+use crate::state::{
+    dispersion::Dispersion,
+    material::{Material, MaterialList, MaterialType, NO_TEXTURE},
+    object::Vertex,
+    texture::{TextureList, TextureLoadError, TextureSource},
 };
 
 #[derive(Debug)]
@@ -19,6 +22,8 @@ pub enum GltfLoadError {
     InvalidFileStructure,
     IoError(std::io::Error),
     GltfError(gltf::Error),
+    IndexOutOfBounds { index: u32, vertex_count: usize },
+    TextureLoadError(TextureLoadError),
 }
 
 impl From<std::io::Error> for GltfLoadError {
@@ -33,6 +38,12 @@ impl From<gltf::Error> for GltfLoadError {
     }
 }
 
+impl From<TextureLoadError> for GltfLoadError {
+    fn from(value: TextureLoadError) -> Self {
+        Self::TextureLoadError(value)
+    }
+}
+
 impl Display for GltfLoadError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
@@ -41,153 +52,414 @@ impl Display for GltfLoadError {
 
 impl Error for GltfLoadError {}
 
-pub fn load_triangles_from_gltf<P: AsRef<Path>>(
+/// A perspective camera defined in a glTF file, in the file's space.
+#[derive(Debug, Clone)]
+pub struct GltfCamera {
+    pub position: Vec3,
+    pub rotation: Quat,
+    /// Vertical field of view in degrees.
+    pub fov: f32,
+    pub near: f32,
+    /// `None` for cameras with an infinite projection.
+    pub far: Option<f32>,
+}
+
+impl GltfCamera {
+    pub fn forward(&self) -> Vec3 {
+        // gltf cameras look down their local -z axis
+        self.rotation * Vec3::NEG_Z
+    }
+
+    /// The camera placed by `matrix`, which must not be mirrored or scaled non-uniformly.
+    pub fn transform(&self, matrix: Mat4) -> Self {
+        let (_, rotation, position) = (matrix
+            * Mat4::from_rotation_translation(self.rotation, self.position))
+        .to_scale_rotation_translation();
+
+        Self {
+            position,
+            rotation: rotation.normalize(),
+            ..self.clone()
+        }
+    }
+}
+
+/// The triangles of one glTF primitive, in the file's space.
+pub struct GltfMesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    /// Id of the material in the material list passed to the loader.
+    pub material: u32,
+}
+
+pub struct GltfScene {
+    pub meshes: Vec<GltfMesh>,
+    pub cameras: Vec<GltfCamera>,
+}
+
+/// Loads the default scene of a `.glb` or `.gltf` file, or of a directory containing a `.gltf`
+/// file. Every node is placed with its world matrix in the file's space.
+///
+/// The file's materials are added to `materials`, and the images they use to `textures`.
+pub fn load_gltf_scene<P: AsRef<Path>>(
     relative_path: P,
-    offset: Vec3,
-    rotation: Quat,
-    scale: f32,
-    material: Material,
-) -> Result<Vec<Triangle>, GltfLoadError> {
+    materials: &mut MaterialList,
+    textures: &mut TextureList,
+) -> Result<GltfScene, GltfLoadError> {
+    let path = find_gltf_file(relative_path)?;
+    let base_path = path.parent().ok_or(GltfLoadError::InvalidFileStructure)?;
+
+    let Gltf { document, blob } = Gltf::open(&path)?;
+    let buffers = gltf::import_buffers(&document, Some(base_path), blob)?;
+
+    let mut materials = MaterialLoader {
+        gltf_path: &path,
+        base_path,
+        buffers: &buffers,
+        materials,
+        textures,
+        loaded: HashMap::new(),
+    };
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or(GltfLoadError::InvalidFileStructure)?;
+
+    let mut gltf_scene = GltfScene {
+        meshes: Vec::new(),
+        cameras: Vec::new(),
+    };
+
+    let mut stack: Vec<(gltf::Node, Mat4)> =
+        scene.nodes().map(|node| (node, Mat4::IDENTITY)).collect();
+
+    while let Some((node, parent_matrix)) = stack.pop() {
+        let matrix = parent_matrix * Mat4::from_cols_array_2d(&node.transform().matrix());
+
+        if let Some(mesh) = node.mesh() {
+            load_mesh(
+                &mut gltf_scene.meshes,
+                &mesh,
+                &buffers,
+                matrix,
+                &mut materials,
+            )?;
+        }
+
+        if let Some(camera) = node.camera() {
+            match camera.projection() {
+                Projection::Perspective(perspective) => {
+                    let (_, rotation, position) = matrix.to_scale_rotation_translation();
+
+                    gltf_scene.cameras.push(GltfCamera {
+                        position,
+                        rotation: rotation.normalize(),
+                        fov: perspective.yfov().to_degrees(),
+                        near: perspective.znear(),
+                        far: perspective.zfar(),
+                    });
+                }
+                Projection::Orthographic(_) => {
+                    log::warn!("Skipping orthographic camera {:?}", camera.name());
+                }
+            }
+        }
+
+        stack.extend(node.children().map(|child| (child, matrix)));
+    }
+
+    Ok(gltf_scene)
+}
+
+fn find_gltf_file<P: AsRef<Path>>(relative_path: P) -> Result<PathBuf, GltfLoadError> {
     let parent_path = std::env::current_dir()?;
     let path = parent_path.join(relative_path);
 
+    if !path.is_dir() {
+        return match path.extension().and_then(|e| e.to_str()) {
+            Some("glb" | "gltf") => Ok(path),
+            _ => Err(GltfLoadError::InvalidFileStructure),
+        };
+    }
+
     let paths = std::fs::read_dir(&path)?;
     let paths: Vec<PathBuf> = paths
         .into_iter()
         .map(|r| r.map(|p| p.path()))
         .collect::<Result<Vec<_>, _>>()?;
 
-    let gltf_path = paths
+    paths
         .into_iter()
-        .find(|p| p.extension().unwrap().to_str().unwrap() == "gltf")
-        .ok_or(GltfLoadError::InvalidFileStructure)?;
+        .find(|p| p.extension().is_some_and(|e| e == "gltf"))
+        .ok_or(GltfLoadError::InvalidFileStructure)
+}
+
+fn load_mesh(
+    meshes: &mut Vec<GltfMesh>,
+    mesh: &gltf::Mesh,
+    buffers: &[gltf::buffer::Data],
+    matrix: Mat4,
+    materials: &mut MaterialLoader,
+) -> Result<(), GltfLoadError> {
+    // mirroring transforms flip the winding order, which would turn the triangles inside out
+    let flip_winding = matrix.determinant() < 0.0;
+
+    for primitive in mesh.primitives() {
+        if primitive.mode() != Mode::Triangles {
+            continue;
+        }
 
-    let gltf = Gltf::open(gltf_path)?;
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
 
-    let bin_data = gltf.blob.as_deref();
+        let Some(positions) = reader.read_positions() else {
+            continue;
+        };
 
-    let mut uri_data: HashMap<&str, Vec<u8>> = HashMap::new();
+        let mut vertices: Vec<Vertex> = positions
+            .map(|position| Vertex::new(matrix.transform_point3(Vec3::from(position)), Vec2::ZERO))
+            .collect();
 
-    for buffer in gltf.buffers() {
-        match buffer.source() {
-            gltf::buffer::Source::Bin => {}
-            gltf::buffer::Source::Uri(uri) => {
-                let data = std::fs::read(path.join(uri))?;
-                uri_data.insert(uri, data);
+        if let Some(uv) = reader.read_tex_coords(0) {
+            for (vertex, uv) in vertices.iter_mut().zip(uv.into_f32()) {
+                vertex.uv = Vec2::from(uv);
             }
         }
-    }
 
-    let mut triangles = Vec::new();
+        // non-indexed primitives use every three consecutive vertices as a triangle
+        let mut indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..vertices.len() as u32).collect(),
+        };
 
-    for mesh in gltf.meshes() {
-        for primitive in mesh.primitives() {
-            if primitive.mode() != Mode::Triangles {
-                continue;
-            }
+        indices.truncate(indices.len() - indices.len() % 3);
 
-            let reader = primitive.reader(|buf| match buf.source() {
-                gltf::buffer::Source::Bin => bin_data,
-                gltf::buffer::Source::Uri(uri) => Some(uri_data.get(&uri).unwrap()),
+        if let Some(&index) = indices.iter().find(|&&i| i as usize >= vertices.len()) {
+            return Err(GltfLoadError::IndexOutOfBounds {
+                index,
+                vertex_count: vertices.len(),
             });
+        }
 
-            if let (Some(positions), Some(indices)) =
-                (reader.read_positions(), reader.read_indices())
-            {
-                let positions: Vec<[f32; 3]> = positions.collect();
-
-                for chunk in indices.into_u32().collect::<Vec<_>>().chunks(3) {
-                    triangles.push(Triangle::new(
-                        (rotation * (Vec3::from(positions[chunk[0] as usize]) * scale)) + offset,
-                        (rotation * (Vec3::from(positions[chunk[1] as usize]) * scale)) + offset,
-                        (rotation * (Vec3::from(positions[chunk[2] as usize]) * scale)) + offset,
-                        Vec2::ZERO,
-                        Vec2::ZERO,
-                        Vec2::ZERO,
-                        material,
-                    ));
-                }
+        if flip_winding {
+            for triangle in indices.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
             }
         }
+
+        meshes.push(GltfMesh {
+            vertices,
+            indices,
+            material: materials.load(&primitive.material())?,
+        });
     }
 
-    Ok(triangles)
+    Ok(())
 }
 
-pub fn load_triangles_from_glb<'a, P: AsRef<Path>>(
-    relative_path: P,
-    offset: Vec3,
-    rotation: Quat,
-    scale: f32,
-    material: Material,
-) -> Result<(Vec<Triangle>), GltfLoadError> {
-    let parent_path = std::env::current_dir()?;
-    let path = parent_path.join(relative_path);
+struct MaterialLoader<'a> {
+    gltf_path: &'a Path,
+    base_path: &'a Path,
+    buffers: &'a [gltf::buffer::Data],
+    materials: &'a mut MaterialList,
+    textures: &'a mut TextureList,
+    /// Material ids by glTF material index, `None` being the default material.
+    loaded: HashMap<Option<usize>, u32>,
+}
 
-    if path
-        .extension()
-        .ok_or(GltfLoadError::InvalidFileStructure)?
-        .to_string_lossy()
-        != "glb"
-    {
-        return Err(GltfLoadError::InvalidFileStructure);
-    }
+impl<'a> MaterialLoader<'a> {
+    /// Returns the id of a glTF material, adding it to the material list the first time it's used.
+    fn load(&mut self, material: &gltf::Material) -> Result<u32, GltfLoadError> {
+        if let Some(&id) = self.loaded.get(&material.index()) {
+            return Ok(id);
+        }
 
-    let gltf = Gltf::open(path)?;
-    let buffers: Vec<_> = gltf.buffers().collect();
+        let loaded = self.load_material(material)?;
+        let id = self.materials.push(loaded);
+        self.loaded.insert(material.index(), id);
 
-    let bin_data = gltf.blob.as_deref();
+        Ok(id)
+    }
 
-    let mut triangles = Vec::new();
+    fn load_material(&mut self, material: &gltf::Material) -> Result<Material, GltfLoadError> {
+        let pbr = material.pbr_metallic_roughness();
+
+        let texture_index = |loader: &mut Self, texture: Option<gltf::Texture>| match texture {
+            Some(texture) => loader.load_texture(texture),
+            None => Ok(NO_TEXTURE),
+        };
+
+        let albedo_texture =
+            texture_index(self, pbr.base_color_texture().map(|info| info.texture()))?;
+        let roughness_metalness_texture = texture_index(
+            self,
+            pbr.metallic_roughness_texture().map(|info| info.texture()),
+        )?;
+        let normal_texture =
+            texture_index(self, material.normal_texture().map(|info| info.texture()))?;
+        let emission_texture =
+            texture_index(self, material.emissive_texture().map(|info| info.texture()))?;
+
+        Ok(Material {
+            albedo_texture,
+            roughness_metalness_texture,
+            normal_texture,
+            emission_texture,
+            ..material_from_gltf(material)
+        })
+    }
 
-    // for texture in gltf.textures() {
-    //     let texture_data = match texture.source().source() {
-    //         gltf::image::Source::View { view, mime_type } => match view.buffer().source() {
-    //             gltf::buffer::Source::Bin => {
-    //                 let start = view.offset();
-    //                 let length = view.length();
+    fn load_texture(&mut self, texture: gltf::Texture) -> Result<u32, GltfLoadError> {
+        let image = texture.source();
 
-    //                 &bin_data.unwrap()[start..(start + length)]
-    //             }
-    //             gltf::buffer::Source::Uri(_) => todo!(),
-    //         },
-    //         gltf::image::Source::Uri { uri, mime_type } => todo!(),
-    //     };
-    // }
+        let source = TextureSource::Gltf {
+            path: self.gltf_path.to_owned(),
+            image_index: image.index(),
+        };
 
-    for mesh in gltf.meshes() {
-        for primitive in mesh.primitives() {
-            if primitive.mode() != Mode::Triangles {
-                continue;
-            }
+        if let Some(index) = self.textures.find(&source) {
+            return Ok(index);
+        }
 
-            let reader = primitive.reader(|buf| match buf.source() {
-                gltf::buffer::Source::Bin => bin_data,
-                gltf::buffer::Source::Uri(_) => None,
-            });
+        let data =
+            gltf::image::Data::from_source(image.source(), Some(self.base_path), self.buffers)?;
+
+        Ok(self.textures.push_image(rgba_from_gltf(&data), source)?)
+    }
+}
 
-            if let (Some(positions), Some(indices), Some(uv)) = (
-                reader.read_positions(),
-                reader.read_indices(),
-                reader.read_tex_coords(0),
-            ) {
-                let positions: Vec<[f32; 3]> = positions.collect();
-                let uv: Vec<[f32; 2]> = uv.into_f32().collect();
-
-                for chunk in indices.into_u32().collect::<Vec<_>>().chunks(3) {
-                    triangles.push(Triangle::new(
-                        (rotation * (Vec3::from(positions[chunk[0] as usize]) * scale)) + offset,
-                        (rotation * (Vec3::from(positions[chunk[1] as usize]) * scale)) + offset,
-                        (rotation * (Vec3::from(positions[chunk[2] as usize]) * scale)) + offset,
-                        Vec2::from(uv[chunk[0] as usize]),
-                        Vec2::from(uv[chunk[1] as usize]),
-                        Vec2::from(uv[chunk[2] as usize]),
-                        material,
-                    ));
+/// Converts a decoded glTF image to 8-bit rgba. One and two channel images come from grayscale
+/// files, so their first channel is used as the color.
+fn rgba_from_gltf(data: &gltf::image::Data) -> RgbaImage {
+    let (channels, sample_size) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+
+    let sample = |bytes: &[u8]| match sample_size {
+        1 => bytes[0],
+        2 => (u16::from_ne_bytes([bytes[0], bytes[1]]) >> 8) as u8,
+        _ => {
+            let value = f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            (value.clamp(0.0, 1.0) * 255.0).round() as u8
+        }
+    };
+
+    let pixels = data
+        .pixels
+        .chunks_exact(channels * sample_size)
+        .flat_map(|pixel| {
+            let mut samples = pixel.chunks_exact(sample_size).map(sample);
+
+            match channels {
+                1 | 2 => {
+                    let luma = samples.next().unwrap();
+                    [luma, luma, luma, samples.next().unwrap_or(u8::MAX)]
                 }
+                _ => [
+                    samples.next().unwrap(),
+                    samples.next().unwrap(),
+                    samples.next().unwrap(),
+                    samples.next().unwrap_or(u8::MAX),
+                ],
             }
-        }
+        })
+        .collect();
+
+    RgbaImage::from_raw(data.width, data.height, pixels).unwrap()
+}
+
+/// Maps a glTF metallic-roughness material onto a principled material, without its textures. Clearcoat,
+/// sheen, anisotropy and dispersion are read from the raw KHR_materials_clearcoat, KHR_materials_sheen,
+/// KHR_materials_anisotropy and KHR_materials_dispersion extensions, which the gltf crate doesn't
+/// parse.
+pub fn material_from_gltf(material: &gltf::Material) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+
+    let albedo = Vec3::from_slice(&pbr.base_color_factor()[..3]);
+    let emission =
+        Vec3::from(material.emissive_factor()) * material.emissive_strength().unwrap_or(1.0);
+
+    let transmission = material
+        .transmission()
+        .map_or(0.0, |transmission| transmission.transmission_factor());
+
+    // the attenuation color is what white light turns into after travelling the attenuation distance,
+    // which is infinite for clear materials
+    let absorption = material
+        .volume()
+        .filter(|volume| transmission > 0.0 && volume.attenuation_distance().is_finite())
+        .map_or(Vec3::ZERO, |volume| {
+            let color = Vec3::from(volume.attenuation_color()).max(Vec3::splat(1e-6));
+            -Vec3::new(color.x.ln(), color.y.ln(), color.z.ln()) / volume.attenuation_distance()
+        });
+
+    let specular = material.specular();
+    let clearcoat = material.extension_value("KHR_materials_clearcoat");
+    let sheen = material.extension_value("KHR_materials_sheen");
+    let anisotropy = material.extension_value("KHR_materials_anisotropy");
+
+    // KHR_materials_dispersion describes dispersion as 20 over the Abbe number
+    let dispersion = match extension_f32(
+        material.extension_value("KHR_materials_dispersion"),
+        "dispersion",
+        0.0,
+    ) {
+        dispersion if dispersion > 0.0 => Dispersion::Abbe(20.0 / dispersion),
+        _ => Dispersion::None,
+    };
+
+    Material {
+        albedo,
+        ty: MaterialType::Principled,
+        emission,
+        roughness: pbr.roughness_factor(),
+        absorption,
+        // 1.5 is the default of KHR_materials_ior
+        ior: material.ior().unwrap_or(1.5),
+        g: 0.0,
+        dispersion,
+        metallic: pbr.metallic_factor(),
+        specular_tint: specular.as_ref().map_or(Vec3::ONE, |specular| {
+            specular.specular_color_factor().into()
+        }),
+        specular: specular.map_or(1.0, |specular| specular.specular_factor()),
+        sheen: extension_vec3(sheen, "sheenColorFactor", Vec3::ZERO),
+        sheen_roughness: extension_f32(sheen, "sheenRoughnessFactor", 0.0),
+        clearcoat: extension_f32(clearcoat, "clearcoatFactor", 0.0),
+        clearcoat_roughness: extension_f32(clearcoat, "clearcoatRoughnessFactor", 0.0),
+        anisotropy: extension_f32(anisotropy, "anisotropyStrength", 0.0),
+        anisotropy_rotation: extension_f32(anisotropy, "anisotropyRotation", 0.0),
+        transmission,
+        ..Default::default()
     }
+}
 
-    Ok(triangles)
+fn extension_f32(extension: Option<&Value>, key: &str, default: f32) -> f32 {
+    extension
+        .and_then(|extension| extension.get(key))
+        .and_then(Value::as_f64)
+        .map_or(default, |value| value as f32)
+}
+
+fn extension_vec3(extension: Option<&Value>, key: &str, default: Vec3) -> Vec3 {
+    match extension
+        .and_then(|extension| extension.get(key))
+        .and_then(Value::as_array)
+        .map(|values| values.as_slice())
+    {
+        Some([x, y, z]) => match (x.as_f64(), y.as_f64(), z.as_f64()) {
+            (Some(x), Some(y), Some(z)) => Vec3::new(x as f32, y as f32, z as f32),
+            _ => default,
+        },
+        _ => default,
+    }
 }
@@ -0,0 +1,139 @@
+use std::{error::Error, fmt::Display, io::Write, path::Path};
+
+use glam::{Mat3, Vec3};
+
+#[derive(Debug)]
+#[allow(unused)]
+pub enum ImageWriteError {
+    UnsupportedFormat(String),
+    IoError(std::io::Error),
+    ImageError(image::ImageError),
+}
+
+impl From<std::io::Error> for ImageWriteError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IoError(value)
+    }
+}
+
+impl From<image::ImageError> for ImageWriteError {
+    fn from(value: image::ImageError) -> Self {
+        Self::ImageError(value)
+    }
+}
+
+impl Display for ImageWriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for ImageWriteError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// 8-bit sRGB, tone mapped the same way as the final pass.
+    Png,
+    /// 32-bit float linear radiance.
+    Exr,
+    /// 32-bit float linear radiance, in the portable float map format.
+    Pfm,
+}
+
+impl OutputFormat {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, ImageWriteError> {
+        let extension = path
+            .as_ref()
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+
+        match extension.as_str() {
+            "png" => Ok(Self::Png),
+            "exr" => Ok(Self::Exr),
+            "pfm" => Ok(Self::Pfm),
+            _ => Err(ImageWriteError::UnsupportedFormat(extension)),
+        }
+    }
+}
+
+/// Writes a linear rgba float image to the given path, choosing the format from the file extension.
+/// The alpha channel is discarded.
+pub fn write_image<P: AsRef<Path>>(
+    path: P,
+    width: u32,
+    height: u32,
+    pixels: &[f32],
+) -> Result<(), ImageWriteError> {
+    let path = path.as_ref();
+
+    let rgb: Vec<f32> = pixels
+        .chunks_exact(4)
+        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect();
+
+    match OutputFormat::from_path(path)? {
+        OutputFormat::Png => {
+            let bytes: Vec<u8> = rgb
+                .chunks_exact(3)
+                .flat_map(|pixel| {
+                    let color = tone_map(Vec3::from_slice(pixel));
+                    color
+                        .to_array()
+                        .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+                })
+                .collect();
+
+            image::RgbImage::from_raw(width, height, bytes)
+                .unwrap()
+                .save_with_format(path, image::ImageFormat::Png)?;
+        }
+        OutputFormat::Exr => {
+            image::Rgb32FImage::from_raw(width, height, rgb)
+                .unwrap()
+                .save_with_format(path, image::ImageFormat::OpenExr)?;
+        }
+        OutputFormat::Pfm => write_pfm(path, width, height, &rgb)?,
+    }
+
+    Ok(())
+}
+
+fn write_pfm(path: &Path, width: u32, height: u32, rgb: &[f32]) -> Result<(), std::io::Error> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+    // a negative scale marks the data as little endian
+    write!(file, "PF\n{} {}\n-1.0\n", width, height)?;
+
+    // pfm scanlines are stored bottom to top
+    for row in rgb.chunks_exact(width as usize * 3).rev() {
+        for value in row {
+            file.write_all(&value.to_le_bytes())?;
+        }
+    }
+
+    file.flush()
+}
+
+// Matches the tone mapping in final.wgsl, so that png output looks the same as the window
+fn tone_map(color: Vec3) -> Vec3 {
+    const ACES_INPUT_MATRIX: Mat3 = Mat3::from_cols(
+        Vec3::new(0.59719, 0.07600, 0.02840),
+        Vec3::new(0.35458, 0.90834, 0.13383),
+        Vec3::new(0.04823, 0.01566, 0.83777),
+    );
+
+    const ACES_OUTPUT_MATRIX: Mat3 = Mat3::from_cols(
+        Vec3::new(1.60475, -0.10208, -0.00327),
+        Vec3::new(-0.53108, 1.10813, -0.07276),
+        Vec3::new(-0.07367, -0.00605, 1.07602),
+    );
+
+    let color = color.max(Vec3::ZERO).powf(1.0 / 2.2);
+    let color = ACES_INPUT_MATRIX * color;
+
+    let a = color * (color + 0.0245786) - 0.000090537;
+    let b = color * (0.983729 * color + 0.432951) + 0.238081;
+
+    ACES_OUTPUT_MATRIX * (a / b)
+}
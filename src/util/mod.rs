@@ -1,6 +1,7 @@
 use std::path::Path;
 
 pub mod gltf;
+pub mod image;
 pub mod preprocess;
 
 pub fn path_name_to_string<P: AsRef<Path>>(path: P) -> String {